@@ -0,0 +1,147 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Load test of concurrent reads: Merkle proofs of one tree are generated by 1, 2, 4, ... threads,
+//! up to the number of cores, and the throughput is compared to a single thread.  
+//! `cargo bench --bench concurrent_reads` runs it on a tree of 100 000 synthetic keys with each backend,
+//! set `READ_BENCH_LEAVES` for another size and `READ_BENCH_SECS` for the time of each run (2 s by default).
+//! The last run repeats the largest one while another tree is updated, which must not slow the reads down.
+
+use smt_backend_lib::{apis::MultiSMTStore, parity_apis::MultiSMTParityStore, traits::MSS};
+use smt_primitives::{
+    keccak_hasher::Keccak256Hasher,
+    kv::{SMTKey, SMTValue},
+};
+use std::{
+    env,
+    path::Path,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn main() {
+    let leaves = env_or("READ_BENCH_LEAVES", 100_000);
+    let secs = env_or("READ_BENCH_SECS", 2);
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    println!("{} leaves, {} cores, {} s per run", leaves, cores, secs);
+    bench("rocksdb", leaves, secs, cores, |path| {
+        MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(path).unwrap()
+    });
+    bench("paritydb", leaves, secs, cores, |path| {
+        MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(path).unwrap()
+    });
+}
+
+fn bench<S>(name: &str, leaves: u64, secs: u64, cores: usize, open: impl Fn(&Path) -> S)
+where
+    S: MSS<String, SMTKey, SMTValue, Keccak256Hasher> + Sync,
+{
+    let dir = tempfile::tempdir().unwrap();
+    let store = open(dir.path());
+    store
+        .ingest(
+            "bench".to_string(),
+            &mut (0..leaves).map(|i| Ok(entry(i))),
+            &mut |_| {},
+        )
+        .unwrap();
+
+    println!("{}:", name);
+    let duration = Duration::from_secs(secs);
+    let mut threads = 1;
+    let mut single = 0.0;
+    while threads <= cores {
+        let throughput = proofs_per_sec(&store, leaves, threads, duration, false);
+        if threads == 1 {
+            single = throughput;
+        }
+        println!(
+            "  {:>3} threads  {:>10.0} proofs/s ({:.1}x)",
+            threads,
+            throughput,
+            throughput / single
+        );
+        if threads == cores {
+            break;
+        }
+        threads = (threads * 2).min(cores);
+    }
+    let throughput = proofs_per_sec(&store, leaves, cores, duration, true);
+    println!(
+        "  {:>3} threads  {:>10.0} proofs/s ({:.1}x, another tree updated meanwhile)",
+        cores,
+        throughput,
+        throughput / single
+    );
+}
+
+/// The number of proofs `threads` threads generate per second, a writer updates another tree if `write` is set.
+fn proofs_per_sec<S>(store: &S, leaves: u64, threads: usize, duration: Duration, write: bool) -> f64
+where
+    S: MSS<String, SMTKey, SMTValue, Keccak256Hasher> + Sync,
+{
+    let proofs = AtomicU64::new(0);
+    let done = AtomicBool::new(false);
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for t in 0..threads as u64 {
+            let (proofs, done) = (&proofs, &done);
+            scope.spawn(move || {
+                let mut i = t;
+                while !done.load(Ordering::Relaxed) {
+                    let (key, _) = entry(i.wrapping_mul(7919) % leaves.max(1));
+                    store.get_merkle_proof("bench".to_string(), key).unwrap();
+                    proofs.fetch_add(1, Ordering::Relaxed);
+                    i += threads as u64;
+                }
+            });
+        }
+        if write {
+            let done = &done;
+            scope.spawn(move || {
+                let mut i = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let (key, value) = entry(i);
+                    store.update("other".to_string(), key, value).unwrap();
+                    i += 1;
+                }
+            });
+        }
+        thread::sleep(duration);
+        done.store(true, Ordering::Relaxed);
+    });
+    proofs.into_inner() as f64 / start.elapsed().as_secs_f64()
+}
+
+fn entry(i: u64) -> (SMTKey, SMTValue) {
+    (
+        SMTKey {
+            address: format!("address-{}", i),
+        },
+        SMTValue {
+            nonce: 1,
+            balance: i as u128 + 1,
+        },
+    )
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
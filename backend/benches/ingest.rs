@@ -0,0 +1,117 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compare building a tree with [`MSS::ingest`] to `update_all` and to repeated `update`.  
+//! `cargo bench --bench ingest` builds a tree of a million synthetic keys with each backend,
+//! set `INGEST_BENCH_LEAVES` for another size. Repeated `update` is timed on the first
+//! `INGEST_BENCH_UPDATES` keys (10 000 by default) and extrapolated, a million single updates take hours.
+
+use smt_backend_lib::{apis::MultiSMTStore, parity_apis::MultiSMTParityStore, traits::MSS};
+use smt_primitives::{
+    keccak_hasher::Keccak256Hasher,
+    kv::{SMTKey, SMTValue},
+};
+use std::{
+    env,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+fn main() {
+    let leaves = env_or("INGEST_BENCH_LEAVES", 1_000_000);
+    let updates = env_or("INGEST_BENCH_UPDATES", 10_000).min(leaves);
+    println!("{} leaves, update timed on {} leaves", leaves, updates);
+    bench("rocksdb", leaves, updates, |path| {
+        MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(path).unwrap()
+    });
+    bench("paritydb", leaves, updates, |path| {
+        MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(path).unwrap()
+    });
+}
+
+fn bench<S>(name: &str, leaves: u64, updates: u64, open: impl Fn(&Path) -> S)
+where
+    S: MSS<String, SMTKey, SMTValue, Keccak256Hasher>,
+{
+    let dir = tempfile::tempdir().unwrap();
+
+    let store = open(&dir.path().join("ingest"));
+    let start = Instant::now();
+    let info = store
+        .ingest(
+            "bench".to_string(),
+            &mut (0..leaves).map(|i| Ok(entry(i))),
+            &mut |_| {},
+        )
+        .unwrap();
+    let ingest = start.elapsed();
+    drop(store);
+
+    let store = open(&dir.path().join("update_all"));
+    let start = Instant::now();
+    let root = store
+        .update_all("bench".to_string(), (0..leaves).map(entry).collect())
+        .unwrap();
+    let update_all = start.elapsed();
+    assert_eq!(root, info.root);
+    drop(store);
+
+    let store = open(&dir.path().join("update"));
+    let start = Instant::now();
+    for (key, value) in (0..updates).map(entry) {
+        store.update("bench".to_string(), key, value).unwrap();
+    }
+    let update = start
+        .elapsed()
+        .mul_f64(leaves as f64 / updates.max(1) as f64);
+
+    println!("{}:", name);
+    println!("  ingest      {:>10.2?}", ingest);
+    println!(
+        "  update_all  {:>10.2?} ({:.1}x)",
+        update_all,
+        ratio(update_all, ingest)
+    );
+    println!(
+        "  update      {:>10.2?} ({:.1}x, extrapolated)",
+        update,
+        ratio(update, ingest)
+    );
+}
+
+fn entry(i: u64) -> (SMTKey, SMTValue) {
+    (
+        SMTKey {
+            address: format!("address-{}", i),
+        },
+        SMTValue {
+            nonce: 1,
+            balance: i as u128 + 1,
+        },
+    )
+}
+
+fn ratio(a: Duration, b: Duration) -> f64 {
+    a.as_secs_f64() / b.as_secs_f64()
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
@@ -31,6 +31,18 @@ def update_key2(path: str):
     print("user2 更新数据后root是:\n", post(path, j2))
     
 
+def update_batch(path: str):
+    j = {
+        "prefix": prefix,
+        "kvs": [
+            {"address": user1_address, "balance": balance, "nonce": 2},
+            {"address": user2_address, "balance": balance, "nonce": 2},
+        ]
+    }
+
+    print("批量更新数据后root是:\n", post(path, j))
+
+
 def get_merkel_proof(path: str):
     j = {
         "address": user1_address,
@@ -82,6 +94,8 @@ def main():
     print("--"*50)
     update_key2("update")
     print("--"*50)
+    update_batch("update_batch")
+    print("--"*50)
     p = get_merkel_proof("merkle_proof")
     print("--"*50)
     p = json.loads(p)
@@ -0,0 +1,112 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Buffer the node changes of a tree in memory, so that a whole update is written in one atomic commit.  
+//! Reads must go through the batch first, because the tree reads back the nodes it has just changed.
+
+use crate::{error::Error, registry::TreeMeta};
+use sparse_merkle_tree::{traits::StoreWriteOps, H256};
+use std::collections::BTreeMap;
+
+/// Pending changes of the keys in a database, `None` marks a deletion.
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    changes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl WriteBatch {
+    /// Insert or overwrite a value.
+    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.changes.insert(key, Some(value));
+    }
+
+    /// Delete a value.
+    pub fn delete(&mut self, key: Vec<u8>) {
+        self.changes.insert(key, None);
+    }
+
+    /// `None` if the key is not changed in this batch, `Some(None)` if it is deleted.
+    pub fn get(&self, key: &[u8]) -> Option<Option<&Vec<u8>>> {
+        self.changes.get(key).map(|v| v.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Take all changes out of the batch, ordered by key.
+    pub fn take(&mut self) -> BTreeMap<Vec<u8>, Option<Vec<u8>>> {
+        std::mem::take(&mut self.changes)
+    }
+}
+
+/// The store of one tree, which buffers its writes until they are committed.  
+/// Implemented by the stores of all backends, so that code writing a tree in several steps, like an
+/// ingest or a session, is shared by them.
+pub trait BufferedStore<V>: StoreWriteOps<V> {
+    /// Record the original key of the leaf `path`, or remove it if `None`.
+    fn index_key(&mut self, path: H256, key: Option<Vec<u8>>);
+
+    /// The number of buffered writes.
+    fn pending(&self) -> usize;
+
+    /// The number of leaves added (or removed if negative) by the buffered writes.
+    fn leaf_delta(&self) -> i64;
+
+    /// Write the buffered changes.
+    fn commit(&mut self) -> Result<(), Error>;
+
+    /// Write the buffered changes as a new version of the tree, see [`crate::history`].
+    fn commit_version(
+        &mut self,
+        meta: &TreeMeta,
+        root: H256,
+        history_depth: u64,
+    ) -> Result<(), Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_batch() {
+        let mut batch = WriteBatch::default();
+        assert!(batch.is_empty());
+        assert_eq!(batch.get(b"key1"), None);
+
+        batch.put(b"key1".to_vec(), b"value1".to_vec());
+        batch.put(b"key2".to_vec(), b"value2".to_vec());
+        batch.delete(b"key2".to_vec());
+        assert_eq!(batch.get(b"key1"), Some(Some(&b"value1".to_vec())));
+        assert_eq!(batch.get(b"key2"), Some(None));
+        assert_eq!(batch.len(), 2);
+
+        let changes = batch.take();
+        assert!(batch.is_empty());
+        assert_eq!(
+            changes.into_iter().collect::<Vec<_>>(),
+            vec![
+                (b"key1".to_vec(), Some(b"value1".to_vec())),
+                (b"key2".to_vec(), None)
+            ]
+        );
+    }
+}
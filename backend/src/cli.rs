@@ -1,8 +1,325 @@
-use clap::Parser;
+use crate::{
+    hashers::HasherKind,
+    history::DEFAULT_HISTORY_DEPTH,
+    pool::{default_threads, DEFAULT_QUEUE_SIZE},
+    server::{BatchLimits, DEFAULT_MAX_BATCH_SIZE, DEFAULT_MAX_BODY_SIZE, DEFAULT_MAX_INGEST_SIZE},
+};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use sparse_merkle_tree::H256;
+use std::path::PathBuf;
 
+/// The database the Merkle trees are stored in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Database {
+    Rocksdb,
+    Paritydb,
+}
+
+/// Command-line arguments of the SMT backend, each one falls back to an environment variable (or `.env`).
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
-    #[arg(short, long, help = "paritydb or rocksdb")]
-    pub database: String,
+    #[arg(
+        short,
+        long,
+        env = "DATABASE",
+        value_enum,
+        default_value_t = Database::Rocksdb,
+        help = "paritydb or rocksdb"
+    )]
+    pub database: Database,
+    #[arg(
+        long,
+        env = "HOST",
+        default_value = "0.0.0.0",
+        help = "Address to bind"
+    )]
+    pub host: String,
+    #[arg(
+        short,
+        long,
+        env = "PORT",
+        default_value_t = 8080,
+        help = "Port to bind"
+    )]
+    pub port: u16,
+    #[arg(
+        long,
+        env = "DB_PATH",
+        default_value = "./db",
+        help = "Directory of the database"
+    )]
+    pub db_path: PathBuf,
+    #[arg(
+        long,
+        env = "LOG_PATH",
+        default_value = "./logs",
+        help = "Directory of the log files"
+    )]
+    pub log_path: String,
+    #[arg(
+        long,
+        env = "IMPLICIT_CREATE",
+        default_value_t = true,
+        action = ArgAction::Set,
+        help = "Whether updating an unknown tree creates it, otherwise trees must be created with /trees/create first"
+    )]
+    pub implicit_create: bool,
+    #[arg(
+        long,
+        env = "HISTORY_DEPTH",
+        default_value_t = DEFAULT_HISTORY_DEPTH,
+        help = "Number of past roots of each tree that can still be proven against, 0 keeps none"
+    )]
+    pub history_depth: u64,
+    #[arg(
+        long,
+        env = "MAX_BATCH_SIZE",
+        default_value_t = DEFAULT_MAX_BATCH_SIZE,
+        help = "Number of entries one /update_batch request may carry"
+    )]
+    pub max_batch_size: usize,
+    #[arg(
+        long,
+        env = "MAX_BODY_SIZE",
+        default_value_t = DEFAULT_MAX_BODY_SIZE,
+        help = "Size in bytes of the JSON body of one /update_batch request"
+    )]
+    pub max_body_size: usize,
+    #[arg(
+        long,
+        env = "MAX_INGEST_SIZE",
+        default_value_t = DEFAULT_MAX_INGEST_SIZE,
+        help = "Size in bytes of one /ingest upload, larger uploads are rejected with 413"
+    )]
+    pub max_ingest_size: u64,
+    #[arg(
+        long,
+        env = "STORE_THREADS",
+        help = "Number of threads running store operations, one per core by default"
+    )]
+    pub store_threads: Option<usize>,
+    #[arg(
+        long,
+        env = "STORE_QUEUE",
+        default_value_t = DEFAULT_QUEUE_SIZE,
+        help = "Number of store operations that may wait for a thread, further requests are rejected with 503"
+    )]
+    pub store_queue: usize,
+    #[arg(
+        long,
+        env = "LOG_LEVEL",
+        default_value = "info",
+        help = "Log level, e.g. info or debug"
+    )]
+    pub log_level: String,
+}
+
+impl Args {
+    /// The limits of the batch APIs.
+    pub fn batch_limits(&self) -> BatchLimits {
+        BatchLimits {
+            max_entries: self.max_batch_size,
+            max_body_size: self.max_body_size,
+            max_ingest_size: self.max_ingest_size,
+        }
+    }
+
+    /// The number of threads of the store pool.
+    pub fn store_threads(&self) -> usize {
+        self.store_threads.unwrap_or_else(default_threads)
+    }
+}
+
+/// Command-line arguments of `smt-cli`, which works on the database directly, so the backend must be stopped.
+#[derive(Parser, Debug)]
+#[command(name = "smt-cli", version, about, long_about = None)]
+pub struct ToolArgs {
+    #[arg(
+        short,
+        long,
+        env = "DATABASE",
+        value_enum,
+        default_value_t = Database::Rocksdb,
+        help = "paritydb or rocksdb"
+    )]
+    pub database: Database,
+    #[arg(
+        long,
+        env = "DB_PATH",
+        default_value = "./db",
+        help = "Directory of the database"
+    )]
+    pub db_path: PathBuf,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// The operations of `smt-cli`, JSON results are printed to stdout.
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// List the registered trees
+    Trees,
+    /// Print the root of a tree
+    Root {
+        #[arg(long)]
+        tree: String,
+    },
+    /// Print the value of a key
+    Value {
+        #[arg(long)]
+        tree: String,
+        #[arg(long)]
+        address: String,
+    },
+    /// Generate the Merkle proof of a key, against the current root unless `--root` is given
+    Proof {
+        #[arg(long)]
+        tree: String,
+        #[arg(long)]
+        address: String,
+        #[arg(long, value_parser = parse_h256, help = "A past root of the tree")]
+        root: Option<H256>,
+        #[arg(long, help = "Write the proof to this file instead of stdout")]
+        out: Option<PathBuf>,
+    },
+    /// Verify a proof file, a proof of an empty value is verified as a non-membership proof
+    Verify {
+        #[arg(long)]
+        file: PathBuf,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t,
+            help = "The hasher of the tree of the proof"
+        )]
+        hasher: HasherKind,
+    },
+    /// Insert the entries of a CSV (`address,nonce,balance`) or JSON file in one update
+    Load {
+        #[arg(long)]
+        tree: String,
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Build an empty tree from a large NDJSON (one JSON object per line) or CSV (`.csv`) file, streaming it into the database
+    Ingest {
+        #[arg(long)]
+        tree: String,
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Export a tree to a snapshot file
+    Export {
+        #[arg(long)]
+        tree: String,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Import a snapshot file as a new tree
+    Import {
+        #[arg(long)]
+        tree: String,
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Remove all leaves of a tree
+    Clear {
+        #[arg(long)]
+        tree: String,
+    },
+}
+
+/// Parse a hex root, with or without `0x`.
+pub fn parse_h256(s: &str) -> Result<H256, String> {
+    let bytes = hex::decode(s.trim_start_matches("0x")).map_err(|e| e.to_string())?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "a root is 32 bytes".to_string())?;
+    Ok(bytes.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        let args = Args::parse_from([
+            "smt-backend",
+            "--database",
+            "paritydb",
+            "--port",
+            "8081",
+            "--db-path",
+            "/data/db",
+            "--implicit-create",
+            "false",
+            "--history-depth",
+            "8",
+            "--max-batch-size",
+            "100",
+            "--max-ingest-size",
+            "2048",
+            "--store-threads",
+            "3",
+        ]);
+        assert_eq!(args.database, Database::Paritydb);
+        assert_eq!(args.port, 8081);
+        assert_eq!(args.db_path, PathBuf::from("/data/db"));
+        assert!(!args.implicit_create);
+        assert_eq!(args.history_depth, 8);
+        assert_eq!(
+            args.batch_limits(),
+            BatchLimits {
+                max_entries: 100,
+                max_body_size: DEFAULT_MAX_BODY_SIZE,
+                max_ingest_size: 2048,
+            }
+        );
+        assert_eq!(args.store_threads(), 3);
+        assert_eq!(args.store_queue, DEFAULT_QUEUE_SIZE);
+
+        assert!(Args::try_parse_from(["smt-backend", "--database", "sled"]).is_err());
+    }
+
+    #[test]
+    fn test_tool_args() {
+        let root = format!("0x{}", "11".repeat(32));
+        let args = ToolArgs::parse_from([
+            "smt-cli",
+            "--database",
+            "paritydb",
+            "proof",
+            "--tree",
+            "test",
+            "--address",
+            "1",
+            "--root",
+            &root,
+        ]);
+        assert_eq!(args.database, Database::Paritydb);
+        assert_eq!(
+            args.command,
+            Command::Proof {
+                tree: "test".to_string(),
+                address: "1".to_string(),
+                root: Some([0x11u8; 32].into()),
+                out: None,
+            }
+        );
+
+        assert!(ToolArgs::try_parse_from(["smt-cli", "root"]).is_err());
+        assert!(ToolArgs::try_parse_from([
+            "smt-cli",
+            "proof",
+            "--tree",
+            "t",
+            "--address",
+            "1",
+            "--root",
+            "0x11"
+        ])
+        .is_err());
+    }
 }
@@ -0,0 +1,67 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An SMT backend based on Actix and Swagger-UI, providing RPC for external service calls.
+//! A single database can create multiple Merkle trees.
+//! The database (RocksDB or ParityDb) is selected at runtime, see `smt-backend --help`.
+
+use clap::Parser;
+use dotenv::dotenv;
+use smt_backend_lib::{
+    apis::MultiSMTStore,
+    cli::{Args, Database},
+    parity_apis::MultiSMTParityStore,
+    pool::StorePool,
+    server::{init_logger, serve},
+};
+use smt_primitives::{
+    keccak_hasher::Keccak256Hasher,
+    kv::{SMTKey, SMTValue},
+};
+use std::io;
+
+#[actix_web::main]
+async fn main() -> io::Result<()> {
+    dotenv().ok();
+    let args = Args::parse();
+    let _logger = init_logger(&args.log_path, &args.log_level)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    log::info!("{:?}", args);
+
+    match args.database {
+        Database::Rocksdb => {
+            let multi_tree = MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(
+                args.db_path.join("rocksdb"),
+            )?
+            .with_implicit_create(args.implicit_create)
+            .with_history_depth(args.history_depth)
+            .with_all_hashers();
+            let pool = StorePool::new(multi_tree, args.store_threads(), args.store_queue)?;
+            serve::<_, String>(pool, &args.host, args.port, args.batch_limits()).await
+        }
+        Database::Paritydb => {
+            let multi_tree = MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(
+                args.db_path.join("paritydb"),
+            )?
+            .with_implicit_create(args.implicit_create)
+            .with_history_depth(args.history_depth)
+            .with_all_hashers();
+            let pool = StorePool::new(multi_tree, args.store_threads(), args.store_queue)?;
+            serve::<_, String>(pool, &args.host, args.port, args.batch_limits()).await
+        }
+    }
+}
@@ -0,0 +1,252 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `smt-cli`: operate on an SMT database without the HTTP server, e.g. for maintenance and migrations.
+//! It opens the database directly, so the backend using it must be stopped first.
+//! See `smt-cli --help`.
+
+use clap::Parser;
+use dotenv::dotenv;
+use serde::{de::DeserializeOwned, Serialize};
+use smt_backend_lib::{
+    apis::MultiSMTStore,
+    cli::{Command, Database, ToolArgs},
+    error::Error,
+    ingest::parse_line,
+    parity_apis::MultiSMTParityStore,
+    req::KVPair,
+    traits::MSS,
+};
+use smt_primitives::{
+    keccak_hasher::Keccak256Hasher,
+    kv::{SMTKey, SMTValue},
+    verify::Proof,
+};
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+fn main() -> io::Result<ExitCode> {
+    dotenv().ok();
+    let args = ToolArgs::parse();
+    match args.database {
+        Database::Rocksdb => run(
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(args.db_path.join("rocksdb"))?
+                .with_all_hashers(),
+            args.command,
+        ),
+        Database::Paritydb => run(
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(
+                args.db_path.join("paritydb"),
+            )?
+            .with_all_hashers(),
+            args.command,
+        ),
+    }
+}
+
+fn run<S>(store: S, command: Command) -> io::Result<ExitCode>
+where
+    S: MSS<String, SMTKey, SMTValue, Keccak256Hasher>,
+{
+    match command {
+        Command::Trees => print_json(&store.trees().map_err(other)?)?,
+        Command::Root { tree } => print_json(&store.get_root(tree).map_err(other)?)?,
+        Command::Value { tree, address } => {
+            print_json(&store.get_value(tree, SMTKey { address }).map_err(other)?)?
+        }
+        Command::Proof {
+            tree,
+            address,
+            root,
+            out,
+        } => {
+            let key = SMTKey { address };
+            let proof = match root {
+                Some(root) => store.get_merkle_proof_at(tree, root, key),
+                None => store.get_merkle_proof(tree, key),
+            }
+            .map_err(other)?;
+            match out {
+                Some(out) => fs::write(out, serde_json::to_vec_pretty(&proof)?)?,
+                None => print_json(&proof)?,
+            }
+        }
+        Command::Verify { file, hasher } => {
+            let mut proof: Proof<SMTKey, SMTValue> = read_json(&file)?;
+            // never trust the hashes in the file, they are derived from the key and the value
+            proof.path = hasher.leaf_hash(&proof.key);
+            proof.value_hash = hasher.leaf_hash(&proof.value);
+            let valid = if proof.value == SMTValue::default() {
+                hasher.verify_non_membership(proof)
+            } else {
+                hasher.verify(proof)
+            };
+            print_json(&valid)?;
+            if !valid {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Load { tree, file } => {
+            let kvs = parse_kvs(&fs::read_to_string(&file)?, is_json(&file))?;
+            print_json(&store.update_all(tree, kvs).map_err(other)?)?
+        }
+        Command::Ingest { tree, file } => {
+            let ndjson = !is_csv(&file);
+            let mut entries = BufReader::new(File::open(&file)?)
+                .lines()
+                .enumerate()
+                .filter_map(|(i, line)| {
+                    let entry = match line {
+                        Ok(line) if ndjson => parse_line(line.as_bytes())
+                            .map_err(|e| Error::bad_request(format!("line {}: {}", i + 1, e))),
+                        Ok(line) => parse_csv_line(i, &line).map_err(Error::bad_request),
+                        Err(e) => Err(Error::internal(e)),
+                    };
+                    entry.transpose()
+                });
+            let info = store
+                .ingest(tree, &mut entries, &mut |p| {
+                    eprintln!(
+                        "{:?}: {} entries read, {} branches written",
+                        p.stage, p.entries, p.branches
+                    )
+                })
+                .map_err(other)?;
+            print_json(&info)?
+        }
+        Command::Export { tree, out } => {
+            let mut writer = BufWriter::new(File::create(out)?);
+            let header = store.export_tree(tree, &mut writer).map_err(other)?;
+            writer.flush()?;
+            print_json(&header)?
+        }
+        Command::Import { tree, file } => {
+            let mut reader = BufReader::new(File::open(file)?);
+            print_json(&store.import_tree(tree, &mut reader).map_err(other)?)?
+        }
+        Command::Clear { tree } => {
+            store.clear(tree.clone()).map_err(other)?;
+            print_json(&store.get_root(tree).map_err(other)?)?
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn other(e: impl Display) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+fn invalid_data(e: impl Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn print_json<T: Serialize>(value: &T) -> io::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(invalid_data)
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "csv")
+}
+
+/// Parse the entries to load, either a JSON array of `{"address", "nonce", "balance"}` objects
+/// or CSV lines of `address,nonce,balance`, with an optional header line.
+fn parse_kvs(content: &str, json: bool) -> io::Result<Vec<(SMTKey, SMTValue)>> {
+    if json {
+        let kvs: Vec<KVPair<SMTKey, SMTValue>> =
+            serde_json::from_str(content).map_err(invalid_data)?;
+        return Ok(kvs.into_iter().map(|kv| (kv.key, kv.value)).collect());
+    }
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| parse_csv_line(i, line).transpose())
+        .collect()
+}
+
+/// Parse the `i`th (0-based) line of a CSV file of `address,nonce,balance` lines.
+/// Blank lines and a header line are `None`.
+fn parse_csv_line(i: usize, line: &str) -> io::Result<Option<(SMTKey, SMTValue)>> {
+    if line.trim().is_empty() || (i == 0 && line.starts_with("address")) {
+        return Ok(None);
+    }
+    let invalid = |e: &dyn Display| invalid_data(format!("line {}: {}", i + 1, e));
+    let fields = line.split(',').map(str::trim).collect::<Vec<&str>>();
+    let [address, nonce, balance] = fields[..] else {
+        return Err(invalid(&"expected address,nonce,balance"));
+    };
+    Ok(Some((
+        SMTKey {
+            address: address.to_string(),
+        },
+        SMTValue {
+            nonce: nonce.parse().map_err(|e| invalid(&e))?,
+            balance: balance.parse().map_err(|e| invalid(&e))?,
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kvs() {
+        let expected = vec![
+            (
+                SMTKey {
+                    address: "a".to_string(),
+                },
+                SMTValue {
+                    nonce: 1,
+                    balance: 100,
+                },
+            ),
+            (
+                SMTKey {
+                    address: "b".to_string(),
+                },
+                SMTValue {
+                    nonce: 2,
+                    balance: 200,
+                },
+            ),
+        ];
+        let csv = "address,nonce,balance\na,1,100\n\nb, 2, 200\n";
+        assert_eq!(parse_kvs(csv, false).unwrap(), expected);
+        assert_eq!(parse_kvs("a,1,100\nb,2,200", false).unwrap(), expected);
+        let json = r#"[{"address": "a", "nonce": 1, "balance": "100"}, {"address": "b", "nonce": 2, "balance": "200"}]"#;
+        assert_eq!(parse_kvs(json, true).unwrap(), expected);
+
+        let err = parse_kvs("a,1,100\nb,2", false).unwrap_err();
+        assert!(err.to_string().starts_with("line 2"));
+        assert!(parse_kvs("a,x,100", false).is_err());
+    }
+}
@@ -14,27 +14,233 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+//! The errors of the stores and the APIs. Every error has an [`ErrorCode`] and is answered with the
+//! status of its code and a JSON body `{"code", "message", "details"}`.  
+//! The stores return [`Error`], so the kind of an error is set where it is raised. Errors of the
+//! sparse-merkle-tree library only carry a message, they are classified by their variant by
+//! `From<SMTError> for Error`, its store errors are failures of the database.
 
-#![allow(unused_imports)]
 use actix_web::{
-    cookie::time::util::weeks_in_year, get, post, web, App, HttpResponse, HttpServer, Responder,
-    ResponseError,
+    error::{JsonPayloadError, QueryPayloadError},
+    http::StatusCode,
+    HttpRequest, HttpResponse, ResponseError,
 };
+use serde::{Deserialize, Serialize};
+use sparse_merkle_tree::error::Error as SMTError;
+use std::fmt::Display;
 use thiserror::Error as ThisError;
-#[derive(Debug, ThisError)]
-pub enum Error {
-    #[error("Internal server error: {0}")]
-    InternalError(String),
+use utoipa::ToSchema;
 
-    #[error("Unexpected error occurred")]
-    UnexpectedError,
+use crate::pool::PoolError;
+
+/// The kind of an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request is malformed or its content is invalid, 400.
+    BadRequest,
+    /// The request body is larger than the limit of the API, 413.
+    TooLarge,
+    /// The tree is not registered, 404.
+    UnknownTree,
+    /// A root, a session or another item the request refers to does not exist, 404.
+    NotFound,
+    /// The request conflicts with the state of a tree, e.g. the tree already exists, 409.
+    Conflict,
+    /// A proof or a rebuilt root does not verify, 422.
+    VerificationFailed,
+    /// Reading or writing the database failed, 500.
+    Store,
+    /// The database holds data that cannot be decoded or is inconsistent, 500.
+    Corruption,
+    /// The server is overloaded and the request can be retried later, 503.
+    Busy,
+    /// An unexpected failure of the server, 500.
+    Internal,
 }
 
-impl ResponseError for Error {
-    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+impl ErrorCode {
+    /// The code as it is sent in the error body.
+    pub fn as_str(self) -> &'static str {
         match self {
-            Error::InternalError(e) => HttpResponse::BadRequest().body(e.to_string()),
-            _ => HttpResponse::BadRequest().body("Unexpected error occurred"),
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::TooLarge => "too_large",
+            ErrorCode::UnknownTree => "unknown_tree",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::VerificationFailed => "verification_failed",
+            ErrorCode::Store => "store",
+            ErrorCode::Corruption => "corruption",
+            ErrorCode::Busy => "busy",
+            ErrorCode::Internal => "internal",
         }
     }
+
+    /// The HTTP status of errors of this kind.
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::UnknownTree | ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::VerificationFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Busy => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Store | ErrorCode::Corruption | ErrorCode::Internal => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// An error of this kind raised by a store.
+    pub fn store_error(self, message: impl Display) -> Error {
+        Error::new(self, message)
+    }
+}
+
+/// An error of the stores and the APIs, it is also the JSON body of the error response.
+#[derive(Debug, Clone, PartialEq, Eq, ThisError, Serialize, Deserialize, ToSchema)]
+#[error("{message}")]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    /// More about the cause, e.g. the error of the JSON parser.
+    pub details: Option<String>,
+}
+
+/// The result of the stores.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Display) -> Self {
+        Error {
+            code,
+            message: message.to_string(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: impl Display) -> Self {
+        self.details = Some(details.to_string());
+        self
+    }
+
+    pub fn bad_request(message: impl Display) -> Self {
+        Self::new(ErrorCode::BadRequest, message)
+    }
+
+    pub fn internal(message: impl Display) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    /// A failure of the database.
+    pub fn store(message: impl Display) -> Self {
+        Self::new(ErrorCode::Store, message)
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        self.code.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.code.status().is_server_error() {
+            log::error!("{:?}", self);
+        }
+        HttpResponse::build(self.status_code()).json(self)
+    }
+}
+
+impl From<SMTError> for Error {
+    fn from(e: SMTError) -> Self {
+        match e {
+            SMTError::Store(message) => Error::store(message),
+            e @ (SMTError::MissingBranch(..) | SMTError::MissingLeaf(..)) => {
+                Error::new(ErrorCode::Corruption, "A node of the tree is missing").with_details(e)
+            }
+            // the other errors are raised by proofs that do not fit the keys or the tree
+            e => Error::new(ErrorCode::VerificationFailed, "Invalid proof").with_details(e),
+        }
+    }
+}
+
+impl From<PoolError> for Error {
+    fn from(e: PoolError) -> Self {
+        match e {
+            PoolError::Busy => Error::new(ErrorCode::Busy, e),
+            PoolError::Panicked => Error::internal(e),
+        }
+    }
+}
+
+/// Answer a request body that is not valid JSON, or not the expected one, with a [`ErrorCode::BadRequest`],
+/// and a body over the limit with a [`ErrorCode::TooLarge`].
+pub fn json_error(e: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    match e {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            Error::new(ErrorCode::TooLarge, "Request body too large").with_details(e)
+        }
+        e => Error::bad_request("Invalid request body").with_details(e),
+    }
+    .into()
+}
+
+/// Answer an invalid query string with a [`ErrorCode::BadRequest`].
+pub fn query_error(e: QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    Error::bad_request("Invalid query string")
+        .with_details(e)
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use sparse_merkle_tree::H256;
+
+    #[test]
+    fn test_store_errors() {
+        let e = ErrorCode::UnknownTree.store_error("Unknown tree: a");
+        assert_eq!(e, Error::new(ErrorCode::UnknownTree, "Unknown tree: a"));
+        assert_eq!(e.status_code(), StatusCode::NOT_FOUND);
+
+        // database messages are never read as codes
+        for message in [
+            "IO error: disk full",
+            "corruption: bad block",
+            "not_found: a",
+        ] {
+            let e = Error::from(SMTError::Store(message.to_string()));
+            assert_eq!(e.code, ErrorCode::Store);
+            assert_eq!(e.message, message);
+        }
+
+        let e = Error::from(SMTError::MissingBranch(3, H256::zero()));
+        assert_eq!(e.code, ErrorCode::Corruption);
+        assert!(e.details.is_some());
+        assert_eq!(
+            Error::from(SMTError::CorruptedProof).status_code(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            Error::from(PoolError::Busy).status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_error_response() {
+        let e = Error::bad_request("Too many entries").with_details("at most 2");
+        let response = e.error_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({
+                "code": "bad_request",
+                "message": "Too many entries",
+                "details": "at most 2"
+            })
+        );
+    }
 }
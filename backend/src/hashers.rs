@@ -0,0 +1,578 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The hashers a tree can be built with.  
+//! The hasher of a tree is chosen when the tree is created and recorded in its metadata, see
+//! [`crate::registry::TreeMeta`]. The keys and values are hashed into the leaves with the hasher of the
+//! tree as well, see [`LeafHash`]. A backend builds the trees of one hasher, [`PerTreeHasher`] holds a
+//! backend for every hasher on the same database and routes each operation to the backend of the hasher
+//! of the tree. A tree that is created by its first update is built with [`HasherKind::default`].
+
+use crate::{
+    error::{Error, ErrorCode},
+    history::RootVersion,
+    ingest::Progress,
+    key_index::Page,
+    registry::{wrong_hasher, HasherName, TreeInfo, TreeMeta},
+    session::{unknown_session, SessionId, SESSION_TAG_BITS},
+    snapshot::{read_header, SNAPSHOT_MAGIC},
+    traits::{verify_batch_proof, verify_non_membership_proof, verify_proof, MSS},
+};
+use clap::ValueEnum;
+use codec::{Decode, Encode, IoReader};
+use scale_info::TypeInfo;
+use serde::{Deserialize, Serialize};
+use smt_primitives::{
+    blake2b_hasher::Blake2b256Hasher,
+    keccak_hasher::Keccak256Hasher,
+    kv::LeafHash,
+    poseidon_hasher::PoseidonHasher,
+    sha256_hasher::Sha256Hasher,
+    verify::{BatchProof, Proof},
+};
+use sparse_merkle_tree::{
+    traits::{Hasher, Value},
+    H256,
+};
+use std::{
+    fmt::{self, Debug},
+    io::Read,
+    marker::PhantomData,
+};
+use utoipa::{ToSchema, __dev::ComposeSchema};
+
+/// The hashers a tree can be built with, named like their [`HasherName`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema, ValueEnum,
+)]
+pub enum HasherKind {
+    /// Keccak256, for verifiers on Ethereum.
+    #[default]
+    #[serde(rename = "keccak256")]
+    Keccak256,
+    /// Blake2b with a 32-byte output, for verifiers on Substrate chains.
+    #[serde(rename = "blake2b256")]
+    Blake2b256,
+    /// SHA-256.
+    #[serde(rename = "sha256")]
+    Sha256,
+    /// Poseidon over BN254, for verifiers in circuits, see [`smt_primitives::poseidon_hasher`].
+    #[serde(rename = "poseidon-bn254")]
+    PoseidonBn254,
+}
+
+/// Evaluate `$body` with the type `$h` standing for the hasher of `$kind`.
+macro_rules! with_hasher {
+    ($kind:expr, $h:ident => $body:expr) => {
+        match $kind {
+            HasherKind::Keccak256 => {
+                type $h = Keccak256Hasher;
+                $body
+            }
+            HasherKind::Blake2b256 => {
+                type $h = Blake2b256Hasher;
+                $body
+            }
+            HasherKind::Sha256 => {
+                type $h = Sha256Hasher;
+                $body
+            }
+            HasherKind::PoseidonBn254 => {
+                type $h = PoseidonHasher;
+                $body
+            }
+        }
+    };
+}
+
+impl HasherKind {
+    /// All hashers.
+    pub const ALL: [HasherKind; 4] = [
+        HasherKind::Keccak256,
+        HasherKind::Blake2b256,
+        HasherKind::Sha256,
+        HasherKind::PoseidonBn254,
+    ];
+
+    /// The name recorded in the metadata of the trees of the hasher.
+    pub fn name(self) -> &'static str {
+        with_hasher!(self, H => H::NAME)
+    }
+
+    /// The hasher named `name`.
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| unknown_hasher(name))
+    }
+
+    /// The tag of the sessions of the backend of the hasher, see [`crate::session::Sessions::tagged`].
+    pub fn tag(self) -> u64 {
+        self as u64
+    }
+
+    /// The hash of a key or a value in the trees of the hasher, see [`LeafHash`].
+    pub fn leaf_hash<T: LeafHash>(self, item: &T) -> H256 {
+        with_hasher!(self, H => item.leaf_hash::<H>())
+    }
+
+    /// Verify a Merkle proof of a tree of the hasher, see [`MSS::verify`].
+    pub fn verify<K, V: Default + PartialEq>(self, proof: Proof<K, V>) -> bool {
+        with_hasher!(self, H => verify_proof::<H, K, V>(proof))
+    }
+
+    /// Verify a non-membership proof of a tree of the hasher, see [`MSS::verify_non_membership`].
+    pub fn verify_non_membership<K, V: Default + PartialEq>(self, proof: Proof<K, V>) -> bool {
+        with_hasher!(self, H => verify_non_membership_proof::<H, K, V>(proof))
+    }
+
+    /// Verify a batch Merkle proof of a tree of the hasher, see [`MSS::verify_batch`].
+    pub fn verify_batch<K, V>(self, proof: BatchProof<K, V>) -> bool {
+        with_hasher!(self, H => verify_batch_proof::<H, K, V>(proof))
+    }
+}
+
+/// The error of a hasher that is not one of [`HasherKind::ALL`].
+pub fn unknown_hasher(name: &str) -> Error {
+    ErrorCode::BadRequest.store_error(format!("Unknown hasher: {}", name))
+}
+
+/// A value in a tree built with the hasher `H`, its leaf is the hash of the value with `H` (see
+/// [`LeafHash`]) instead of [`Value::to_h256`]. It is stored as the bytes of the value.
+pub struct Leaf<V, H>(V, PhantomData<fn() -> H>);
+
+impl<V, H> Leaf<V, H> {
+    pub fn new(value: V) -> Self {
+        Leaf(value, PhantomData)
+    }
+
+    pub fn into_inner(self) -> V {
+        self.0
+    }
+}
+
+impl<V: Clone, H> Clone for Leaf<V, H> {
+    fn clone(&self) -> Self {
+        Leaf::new(self.0.clone())
+    }
+}
+
+impl<V: Debug, H> Debug for Leaf<V, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<V, H> Value for Leaf<V, H>
+where
+    V: LeafHash + Default,
+    H: Hasher + Default,
+{
+    fn to_h256(&self) -> H256 {
+        self.0.leaf_hash::<H>()
+    }
+
+    fn zero() -> Self {
+        Leaf::new(V::default())
+    }
+}
+
+impl<V: From<Vec<u8>>, H> From<Vec<u8>> for Leaf<V, H> {
+    fn from(bytes: Vec<u8>) -> Self {
+        Leaf::new(V::from(bytes))
+    }
+}
+
+impl<V: Into<Vec<u8>>, H> From<Leaf<V, H>> for Vec<u8> {
+    fn from(leaf: Leaf<V, H>) -> Self {
+        leaf.0.into()
+    }
+}
+
+/// The trees of all hashers in one store, with the backend of each hasher.  
+/// The backends must share the database and the locks of the trees, like the stores returned by
+/// `with_all_hashers` of the backends. The verify methods of [`MSS`] and [`MSS::get_next_root`] don't
+/// know the tree and use Keccak256, use [`HasherKind::verify`] for the trees of other hashers.
+pub struct PerTreeHasher<A, B, C, D> {
+    keccak256: A,
+    blake2b256: B,
+    sha256: C,
+    poseidon: D,
+}
+
+impl<A, B, C, D> PerTreeHasher<A, B, C, D> {
+    pub fn new(keccak256: A, blake2b256: B, sha256: C, poseidon: D) -> Self {
+        PerTreeHasher {
+            keccak256,
+            blake2b256,
+            sha256,
+            poseidon,
+        }
+    }
+}
+
+/// Evaluate `$body` with `$store` standing for the backend of the hasher `$kind`.
+macro_rules! route {
+    ($self:ident, $kind:expr, $store:ident => $body:expr) => {
+        match $kind {
+            HasherKind::Keccak256 => {
+                let $store = &$self.keccak256;
+                $body
+            }
+            HasherKind::Blake2b256 => {
+                let $store = &$self.blake2b256;
+                $body
+            }
+            HasherKind::Sha256 => {
+                let $store = &$self.sha256;
+                $body
+            }
+            HasherKind::PoseidonBn254 => {
+                let $store = &$self.poseidon;
+                $body
+            }
+        }
+    };
+}
+
+/// The hasher of a tree with the registry entry `meta`, a tree that is not registered will get the default one.
+fn tree_hasher(meta: Option<TreeMeta>) -> Result<HasherKind, Error> {
+    meta.map_or(Ok(HasherKind::default()), |meta| {
+        HasherKind::from_name(&meta.hasher)
+    })
+}
+
+/// The hasher of the backend a session was begun in.
+fn session_hasher(session: SessionId) -> Result<HasherKind, Error> {
+    let tag = session & ((1 << SESSION_TAG_BITS) - 1);
+    HasherKind::ALL
+        .into_iter()
+        .find(|kind| kind.tag() == tag)
+        .ok_or_else(|| unknown_session(session))
+}
+
+impl<T, K, V, A, B, C, D> MSS<T, K, V, Keccak256Hasher> for PerTreeHasher<A, B, C, D>
+where
+    T: Clone + Debug,
+    K: Value
+        + LeafHash
+        + Clone
+        + Serialize
+        + ToSchema
+        + Deserialize<'static>
+        + ComposeSchema
+        + Debug
+        + TypeInfo
+        + Encode
+        + Decode,
+    V: Default
+        + Value
+        + LeafHash
+        + Into<Vec<u8>>
+        + From<Vec<u8>>
+        + ToSchema
+        + Serialize
+        + Deserialize<'static>
+        + ComposeSchema
+        + PartialEq
+        + Clone
+        + Debug
+        + TypeInfo,
+    A: MSS<T, K, V, Keccak256Hasher>,
+    B: MSS<T, K, V, Blake2b256Hasher>,
+    C: MSS<T, K, V, Sha256Hasher>,
+    D: MSS<T, K, V, PoseidonHasher>,
+{
+    type Tree = A::Tree;
+
+    /// Create or open a tree, only the trees of Keccak256 can be opened.
+    fn new_tree_with_store(&self, tree_id: T) -> Result<A::Tree, Error> {
+        match tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)? {
+            HasherKind::Keccak256 => self.keccak256.new_tree_with_store(tree_id),
+            kind => Err(wrong_hasher(
+                &format!("{:?}", tree_id),
+                kind.name(),
+                Keccak256Hasher::NAME,
+            )),
+        }
+    }
+
+    fn update(&self, tree_id: T, key: K, value: V) -> Result<H256, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.update(tree_id, key, value))
+    }
+
+    fn update_all(&self, tree_id: T, kvs: Vec<(K, V)>) -> Result<H256, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.update_all(tree_id, kvs))
+    }
+
+    fn get_root(&self, tree_id: T) -> Result<H256, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.get_root(tree_id))
+    }
+
+    fn get_value(&self, tree_id: T, key: K) -> Result<V, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.get_value(tree_id, key))
+    }
+
+    fn get_merkle_proof(&self, tree_id: T, key: K) -> Result<Proof<K, V>, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.get_merkle_proof(tree_id, key))
+    }
+
+    fn get_batch_merkle_proof(&self, tree_id: T, keys: Vec<K>) -> Result<BatchProof<K, V>, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.get_batch_merkle_proof(tree_id, keys))
+    }
+
+    fn root_history(&self, tree_id: T) -> Result<Vec<RootVersion>, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.root_history(tree_id))
+    }
+
+    fn get_value_at(&self, tree_id: T, root: H256, key: K) -> Result<V, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.get_value_at(tree_id, root, key))
+    }
+
+    fn get_merkle_proof_at(&self, tree_id: T, root: H256, key: K) -> Result<Proof<K, V>, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.get_merkle_proof_at(tree_id, root, key))
+    }
+
+    fn keys(&self, tree_id: T, start_after: Option<H256>, limit: usize) -> Result<Page<K>, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.keys(tree_id, start_after, limit))
+    }
+
+    fn for_each_entry(
+        &self,
+        tree_id: T,
+        f: &mut dyn FnMut(K, Vec<u8>) -> Result<(), Error>,
+    ) -> Result<Option<TreeInfo>, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.for_each_entry(tree_id, f))
+    }
+
+    /// Import a snapshot into the backend of the hasher recorded in the snapshot.  
+    /// Only the header is read here, the backend reads it again followed by the rest of `reader`.
+    fn import_tree(&self, tree_id: T, reader: &mut dyn Read) -> Result<TreeMeta, Error> {
+        let header = read_header(&mut IoReader(&mut *reader))?;
+        let kind = HasherKind::from_name(&header.hasher)?;
+        let head = [&SNAPSHOT_MAGIC[..], &header.encode()].concat();
+        route!(self, kind, store => store.import_tree(tree_id, &mut head.as_slice().chain(&mut *reader)))
+    }
+
+    fn ingest(
+        &self,
+        tree_id: T,
+        entries: &mut dyn Iterator<Item = Result<(K, V), Error>>,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<TreeInfo, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.ingest(tree_id, entries, progress))
+    }
+
+    fn begin_session(&self, tree_id: T) -> Result<SessionId, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.begin_session(tree_id))
+    }
+
+    fn session_update(&self, session: SessionId, kvs: Vec<(K, V)>) -> Result<H256, Error> {
+        route!(self, session_hasher(session)?, store => store.session_update(session, kvs))
+    }
+
+    fn session_value(&self, session: SessionId, key: K) -> Result<V, Error> {
+        route!(self, session_hasher(session)?, store => store.session_value(session, key))
+    }
+
+    fn session_merkle_proof(&self, session: SessionId, key: K) -> Result<Proof<K, V>, Error> {
+        route!(self, session_hasher(session)?, store => store.session_merkle_proof(session, key))
+    }
+
+    fn commit_session(&self, session: SessionId) -> Result<H256, Error> {
+        route!(self, session_hasher(session)?, store => store.commit_session(session))
+    }
+
+    fn rollback_session(&self, session: SessionId) -> Result<(), Error> {
+        route!(self, session_hasher(session)?, store => store.rollback_session(session))
+    }
+
+    fn get_merkle_proof_old(&self, tree_id: T, keys: Vec<K>) -> Result<Vec<u8>, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.get_merkle_proof_old(tree_id, keys))
+    }
+
+    fn next_root(&self, tree_id: T, kvs: Vec<(K, V)>) -> Result<H256, Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.next_root(tree_id, kvs))
+    }
+
+    fn clear(&self, tree_id: T) -> Result<(), Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.clear(tree_id))
+    }
+
+    /// Register an empty tree in the backend of `hasher`.
+    fn create_tree(&self, tree_id: T, hasher: HasherKind) -> Result<TreeMeta, Error> {
+        route!(self, hasher, store => store.create_tree(tree_id, hasher))
+    }
+
+    /// The metadata of all registered trees, of all hashers.
+    fn trees(&self) -> Result<Vec<TreeMeta>, Error> {
+        self.keccak256.trees()
+    }
+
+    fn tree_meta(&self, tree_id: T) -> Result<Option<TreeMeta>, Error> {
+        self.keccak256.tree_meta(tree_id)
+    }
+
+    fn delete_tree(&self, tree_id: T) -> Result<(), Error> {
+        let kind = tree_hasher(self.keccak256.tree_meta(tree_id.clone())?)?;
+        route!(self, kind, store => store.delete_tree(tree_id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::apis::MultiSMTStore;
+    use smt_primitives::kv::{SMTKey, SMTValue};
+
+    fn key(i: u64) -> SMTKey {
+        SMTKey {
+            address: i.to_string(),
+        }
+    }
+
+    fn value(i: u64) -> SMTValue {
+        SMTValue {
+            nonce: i,
+            balance: i as u128 * 100,
+        }
+    }
+
+    #[test]
+    fn test_trees_of_all_hashers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+            .unwrap()
+            .with_all_hashers();
+        let kvs = (1..10).map(|i| (key(i), value(i))).collect::<Vec<_>>();
+
+        let mut roots = vec![];
+        for kind in HasherKind::ALL {
+            let name = kind.name().to_string();
+            assert_eq!(
+                store.create_tree(name.clone(), kind).unwrap().hasher,
+                kind.name()
+            );
+            let next_root = store.next_root(name.clone(), kvs.clone()).unwrap();
+            let root = store.update_all(name.clone(), kvs.clone()).unwrap();
+            assert_eq!(root, next_root);
+            assert_eq!(store.get_value(name.clone(), key(3)).unwrap(), value(3));
+
+            // only the hasher of the tree verifies its proofs
+            let proof = store.get_merkle_proof(name.clone(), key(3)).unwrap();
+            for other in HasherKind::ALL {
+                let mut proof = proof.clone();
+                proof.path = other.leaf_hash(&proof.key);
+                proof.value_hash = other.leaf_hash(&proof.value);
+                assert_eq!(other.verify(proof), other == kind);
+            }
+            let proof = store.get_merkle_proof(name.clone(), key(42)).unwrap();
+            assert!(kind.verify_non_membership(proof));
+            roots.push(root);
+        }
+        for (i, root) in roots.iter().enumerate() {
+            assert!(roots[i + 1..].iter().all(|other| other != root));
+        }
+
+        // the trees are listed with their hasher
+        let trees = store.trees().unwrap();
+        assert_eq!(trees.len(), 4);
+        assert!(trees.iter().all(|meta| meta.name == meta.hasher));
+    }
+
+    #[test]
+    fn test_sessions_and_snapshots() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+            .unwrap()
+            .with_implicit_create(true)
+            .with_all_hashers();
+        let tree = "blake".to_string();
+        store
+            .create_tree(tree.clone(), HasherKind::Blake2b256)
+            .unwrap();
+        store.update(tree.clone(), key(1), value(1)).unwrap();
+
+        let session = store.begin_session(tree.clone()).unwrap();
+        assert_eq!(session_hasher(session).unwrap(), HasherKind::Blake2b256);
+        let staged = store
+            .session_update(session, vec![(key(2), value(2))])
+            .unwrap();
+        assert_eq!(store.session_value(session, key(2)).unwrap(), value(2));
+        assert_eq!(store.commit_session(session).unwrap(), staged);
+        assert_eq!(store.get_root(tree.clone()).unwrap(), staged);
+
+        // the snapshot keeps the hasher of the tree
+        let mut snapshot = vec![];
+        let header = store.export_tree(tree.clone(), &mut snapshot).unwrap();
+        assert_eq!(header.hasher, Blake2b256Hasher::NAME);
+        let meta = store
+            .import_tree("copy".to_string(), &mut snapshot.as_slice())
+            .unwrap();
+        assert_eq!(meta.hasher, Blake2b256Hasher::NAME);
+        assert_eq!(store.get_root("copy".to_string()).unwrap(), staged);
+
+        // a tree created by its first update uses the default hasher
+        store
+            .update("implicit".to_string(), key(1), value(1))
+            .unwrap();
+        let meta = store.tree_meta("implicit".to_string()).unwrap().unwrap();
+        assert_eq!(meta.hasher, Keccak256Hasher::NAME);
+        assert_ne!(
+            store.get_root("implicit".to_string()).unwrap(),
+            store.get_root(tree).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_wrong_hasher() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let keccak =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        let sha256 = keccak.with_hasher::<Sha256Hasher>();
+        assert!(keccak
+            .create_tree("tree".to_string(), HasherKind::Sha256)
+            .is_err());
+        sha256
+            .create_tree("tree".to_string(), HasherKind::Sha256)
+            .unwrap();
+        // a backend never writes a tree of another hasher
+        assert!(keccak.update("tree".to_string(), key(1), value(1)).is_err());
+        sha256.update("tree".to_string(), key(1), value(1)).unwrap();
+
+        assert_eq!(HasherKind::from_name("sha256").unwrap(), HasherKind::Sha256);
+        assert!(HasherKind::from_name("md5").is_err());
+        assert_eq!(session_hasher(7).unwrap(), HasherKind::PoseidonBn254);
+    }
+}
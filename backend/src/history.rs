@@ -0,0 +1,229 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Past versions of the trees, so that proofs can be made against a root the tree has moved past.  
+//! Every commit of a tree is a new version, which records the previous values of the nodes it changes
+//! (an undo log) together with the root it produces. The state of a past version is the current state
+//! with the undo logs of all later versions applied, newest first.  
+//! The current version is stored under `prefix ++ HISTORY_TAG` and the undo logs under
+//! `prefix ++ HISTORY_TAG ++ version`. These keys never collide with node keys (32 or 33 bytes after
+//! the prefix), and they are deleted together with the nodes of the tree.
+
+use crate::{
+    batch::WriteBatch,
+    error::{Error, ErrorCode},
+};
+use codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use sparse_merkle_tree::H256;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// The tag of the undo log keys.
+pub const HISTORY_TAG: u8 = b'h';
+/// The number of past versions kept by default.
+pub const DEFAULT_HISTORY_DEPTH: u64 = 64;
+
+/// The undo log of one version.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct HistoryEntry {
+    /// The root after this version.
+    pub root: H256,
+    /// The values of the changed keys before this version, `None` if a key did not exist.
+    pub undo: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+/// A version of a tree and its root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct RootVersion {
+    pub version: u64,
+    pub root: H256,
+}
+
+/// The key of the current version.
+pub fn version_key(prefix: &[u8]) -> Vec<u8> {
+    [prefix, &[HISTORY_TAG]].concat()
+}
+
+/// The key of the undo log of `version`.
+pub fn history_key(prefix: &[u8], version: u64) -> Vec<u8> {
+    [prefix, &[HISTORY_TAG], &version.to_be_bytes()].concat()
+}
+
+/// The current version of a tree, 0 if it was never updated (or cleared since).
+pub fn current_version(
+    prefix: &[u8],
+    mut get: impl FnMut(&[u8]) -> Result<Option<Vec<u8>>, Error>,
+) -> Result<u64, Error> {
+    match get(&version_key(prefix))? {
+        Some(value) => {
+            u64::decode(&mut &value[..]).map_err(|e| ErrorCode::Corruption.store_error(e))
+        }
+        None => Ok(0),
+    }
+}
+
+fn decode_entry(value: &[u8]) -> Result<HistoryEntry, Error> {
+    HistoryEntry::decode(&mut &value[..]).map_err(|e| ErrorCode::Corruption.store_error(e))
+}
+
+/// The writes that record the `changes` as a new version with its `root`, and delete the versions
+/// that are more than `depth` versions old. `get` reads the values before the changes.
+pub fn record(
+    prefix: &[u8],
+    root: H256,
+    depth: u64,
+    changes: &BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    mut get: impl FnMut(&[u8]) -> Result<Option<Vec<u8>>, Error>,
+) -> Result<Vec<(Vec<u8>, Option<Vec<u8>>)>, Error> {
+    let version = current_version(prefix, &mut get)? + 1;
+    let mut writes = vec![(version_key(prefix), Some(version.encode()))];
+    if depth > 0 {
+        let undo = changes
+            .keys()
+            .map(|key| Ok((key.clone(), get(key)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        writes.push((
+            history_key(prefix, version),
+            Some(HistoryEntry { root, undo }.encode()),
+        ));
+    }
+    // usually only one version expires, but more do if the depth was lowered
+    let mut expired = version.saturating_sub(depth.max(1));
+    while expired > 0 {
+        let key = history_key(prefix, expired);
+        if get(&key)?.is_none() {
+            break;
+        }
+        writes.push((key, None));
+        expired -= 1;
+    }
+    Ok(writes)
+}
+
+/// The current root (`root`) and the roots of the past versions that are still kept, newest first.
+pub fn root_history(
+    prefix: &[u8],
+    root: H256,
+    mut get: impl FnMut(&[u8]) -> Result<Option<Vec<u8>>, Error>,
+) -> Result<Vec<RootVersion>, Error> {
+    let version = current_version(prefix, &mut get)?;
+    let mut roots = vec![RootVersion { version, root }];
+    for version in (1..version).rev() {
+        let Some(value) = get(&history_key(prefix, version))? else {
+            break;
+        };
+        roots.push(RootVersion {
+            version,
+            root: decode_entry(&value)?.root,
+        });
+    }
+    Ok(roots)
+}
+
+/// The changes that turn the current state back into the state whose root is `root`,
+/// `None` if no kept version has this root.
+pub fn rollback_to(
+    prefix: &[u8],
+    root: H256,
+    mut get: impl FnMut(&[u8]) -> Result<Option<Vec<u8>>, Error>,
+) -> Result<Option<WriteBatch>, Error> {
+    let version = current_version(prefix, &mut get)?;
+    let mut overlay = WriteBatch::default();
+    for version in (1..=version).rev() {
+        let Some(value) = get(&history_key(prefix, version))? else {
+            break;
+        };
+        let entry = decode_entry(&value)?;
+        if entry.root == root {
+            return Ok(Some(overlay));
+        }
+        for (key, value) in entry.undo {
+            match value {
+                Some(value) => overlay.put(key, value),
+                None => overlay.delete(key),
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The error of a root that is neither the current root nor a kept past root of a tree.
+pub fn unknown_root(name: &str, root: &H256) -> Error {
+    ErrorCode::NotFound.store_error(format!("Unknown root of tree {}: {:?}", name, root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let prefix = b"\x04a".to_vec();
+        let mut db: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+        let root = |i: u8| H256::from([i; 32]);
+        let key = |i: u8| [prefix.clone(), vec![i; 32]].concat();
+
+        // version i sets key 1 to i and adds key i
+        for i in 1..=5u8 {
+            let mut changes = BTreeMap::new();
+            changes.insert(key(1), Some(vec![i]));
+            changes.insert(key(i), Some(vec![i]));
+            let writes = record(&prefix, root(i), 3, &changes, |k| Ok(db.get(k).cloned())).unwrap();
+            for (k, v) in changes.into_iter().chain(writes) {
+                match v {
+                    Some(v) => db.insert(k, v),
+                    None => db.remove(&k),
+                };
+            }
+        }
+
+        // versions 3, 4 and 5 are kept
+        let get = |k: &[u8]| Ok(db.get(k).cloned());
+        assert_eq!(current_version(&prefix, get).unwrap(), 5);
+        assert!(db.contains_key(&history_key(&prefix, 3)));
+        assert!(!db.contains_key(&history_key(&prefix, 2)));
+        assert_eq!(
+            root_history(&prefix, root(5), get).unwrap(),
+            vec![
+                RootVersion {
+                    version: 5,
+                    root: root(5)
+                },
+                RootVersion {
+                    version: 4,
+                    root: root(4)
+                },
+                RootVersion {
+                    version: 3,
+                    root: root(3)
+                },
+            ]
+        );
+
+        assert_eq!(rollback_to(&prefix, root(2), get).unwrap(), None);
+        assert!(rollback_to(&prefix, root(5), get)
+            .unwrap()
+            .unwrap()
+            .is_empty());
+        let overlay = rollback_to(&prefix, root(3), get).unwrap().unwrap();
+        assert_eq!(overlay.get(&key(1)), Some(Some(&vec![3])));
+        assert_eq!(overlay.get(&key(3)), None);
+        assert_eq!(overlay.get(&key(4)), Some(None));
+        assert_eq!(overlay.get(&key(5)), Some(None));
+    }
+}
@@ -0,0 +1,290 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Build a tree of millions of leaves from a stream of entries.  
+//! [`MSS::update_all`](crate::traits::MSS::update_all) keeps all entries and every changed node in memory
+//! and reads the siblings of each node back from the store. An ingest only works on an empty tree, so no
+//! node has to be read: the leaves are written as they arrive, then sorted by their leaf keys and the
+//! branches are built bottom-up in one pass, keeping only the left siblings that wait for their right one.  
+//! Only the leaf keys and value hashes (64 bytes per entry) are kept in memory. The writes are committed in
+//! chunks, so an ingest is not atomic: the root of the tree stays empty until the last chunk is written,
+//! and an interrupted ingest is cleaned up by the next one.
+
+use crate::{
+    batch::BufferedStore,
+    error::{Error, ErrorCode},
+    hashers::Leaf,
+    registry::{TreeInfo, TreeMeta},
+    req::KVPair,
+    snapshot::root_mismatch,
+};
+use codec::Encode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use smt_primitives::kv::LeafHash;
+use sparse_merkle_tree::{
+    merge::{merge, MergeValue},
+    traits::Hasher,
+    BranchKey, BranchNode, H256,
+};
+use utoipa::ToSchema;
+
+/// The number of buffered writes that are committed at once.
+pub const DEFAULT_CHUNK_SIZE: usize = 100_000;
+
+/// The stage of an ingest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// The leaves are written as they are read.
+    Leaves,
+    /// The branches are built bottom-up.
+    Branches,
+}
+
+/// How far an ingest has come, reported after every committed chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Progress {
+    pub stage: Stage,
+    /// The number of entries read so far.
+    pub entries: u64,
+    /// The number of branches written so far.
+    pub branches: u64,
+}
+
+/// Write the entries to the empty tree of `store` and register it with `meta`.  
+/// The last entry of a key wins and entries with the default value are skipped. The tree has no past
+/// roots after an ingest, because undoing it would need an undo log as large as the tree.  
+/// If the built root is not `expected_root` the tree is not registered, the chunks written so far are
+/// left to the caller to remove.
+pub fn write_tree<K, V, H, S>(
+    store: &mut S,
+    mut meta: TreeMeta,
+    entries: &mut dyn Iterator<Item = Result<(K, V), Error>>,
+    chunk_size: usize,
+    expected_root: Option<H256>,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<TreeInfo, Error>
+where
+    K: LeafHash + Encode,
+    V: LeafHash,
+    H: Hasher + Default,
+    S: BufferedStore<Leaf<V, H>>,
+{
+    let chunk_size = chunk_size.max(1);
+    let mut report = Progress {
+        stage: Stage::Leaves,
+        entries: 0,
+        branches: 0,
+    };
+    let mut leaves = Vec::new();
+    for entry in entries {
+        let (key, value) = entry?;
+        let path = key.leaf_hash::<H>();
+        let hash = value.leaf_hash::<H>();
+        if hash.is_zero() {
+            // an earlier entry of the key may have been written
+            store.remove_leaf(&path)?;
+            store.index_key(path, None);
+        } else {
+            store.insert_leaf(path, Leaf::new(value))?;
+            store.index_key(path, Some(key.encode()));
+        }
+        leaves.push((path, hash));
+        report.entries += 1;
+        if store.pending() >= chunk_size {
+            store.commit()?;
+            progress(report);
+        }
+    }
+
+    // keep the last entry of each key, like `update_all`
+    leaves.reverse();
+    leaves.sort_by_key(|(path, _)| *path);
+    leaves.dedup_by_key(|(path, _)| *path);
+    leaves.retain(|(_, hash)| !hash.is_zero());
+
+    report.stage = Stage::Branches;
+    let root = build::<H>(&leaves, |key, branch| {
+        store.insert_branch(key, branch)?;
+        report.branches += 1;
+        if store.pending() >= chunk_size {
+            store.commit()?;
+            progress(report);
+        }
+        Ok(())
+    })?;
+
+    if let Some(expected_root) = expected_root.filter(|expected| *expected != root) {
+        return Err(root_mismatch(&expected_root, &root));
+    }
+    meta.leaf_count = leaves.len() as u64;
+    meta.touch(0);
+    store.commit_version(&meta, root, 0)?;
+    progress(report);
+    Ok(TreeInfo { meta, root })
+}
+
+/// Build the branches above `leaves`, which are the leaf keys and value hashes of all non-empty leaves
+/// of a tree, sorted by the leaf keys without duplicates. Returns the root.  
+/// `insert_branch` is called with the same branches [`SparseMerkleTree::update_all`] would insert into
+/// an empty store, each branch once and in no particular order.
+///
+/// [`SparseMerkleTree::update_all`]: sparse_merkle_tree::SparseMerkleTree::update_all
+pub fn build<H: Hasher + Default>(
+    leaves: &[(H256, H256)],
+    mut insert_branch: impl FnMut(BranchKey, BranchNode) -> Result<(), Error>,
+) -> Result<H256, Error> {
+    // left children waiting for their right sibling, the lowest height last
+    let mut waiting: Vec<(u8, MergeValue)> = Vec::new();
+    let mut root = H256::zero();
+    for (i, (path, value)) in leaves.iter().enumerate() {
+        // the height of the branch where this leaf and the next one split
+        let fork = match leaves.get(i + 1) {
+            Some((next, _)) if next > path => (0..=u8::MAX)
+                .rev()
+                .find(|height| path.get_bit(*height) != next.get_bit(*height)),
+            Some(_) => return Err(ErrorCode::Internal.store_error("Leaves are not sorted")),
+            None => None,
+        };
+        let mut node = MergeValue::from_h256(*value);
+        for height in 0..=u8::MAX {
+            if fork == Some(height) {
+                waiting.push((height, node));
+                break;
+            }
+            let parent = path.parent_path(height);
+            let (left, right) = if waiting.last().map(|(h, _)| *h) == Some(height) {
+                let (_, left) = waiting.pop().unwrap();
+                (left, node)
+            } else if path.is_right(height) {
+                (MergeValue::zero(), node)
+            } else {
+                (node, MergeValue::zero())
+            };
+            node = merge::<H>(height, &parent, &left, &right);
+            insert_branch(BranchKey::new(height, parent), BranchNode { left, right })?;
+        }
+        if fork.is_none() {
+            root = node.hash::<H>();
+        }
+    }
+    Ok(root)
+}
+
+/// Parse one line of an NDJSON upload, i.e. a [`KVPair`] object. Blank lines are `None`.
+pub fn parse_line<K, V>(line: &[u8]) -> Result<Option<(K, V)>, Error>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    let kv: KVPair<K, V> =
+        serde_json::from_slice(line).map_err(|e| ErrorCode::BadRequest.store_error(e))?;
+    Ok(Some((kv.key, kv.value)))
+}
+
+/// The error of an ingest into a tree that already has leaves.
+pub fn not_empty(name: &str) -> Error {
+    ErrorCode::Conflict.store_error(format!("Tree is not empty: {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smt_primitives::{
+        keccak_hasher::Keccak256Hasher,
+        kv::{SMTKey, SMTValue},
+    };
+    use sparse_merkle_tree::{default_store::DefaultStore, traits::Value, SparseMerkleTree};
+    use std::collections::HashMap;
+
+    type Tree = SparseMerkleTree<Keccak256Hasher, SMTValue, DefaultStore<SMTValue>>;
+
+    fn leaves(n: u64) -> Vec<(H256, SMTValue)> {
+        (0..n)
+            .map(|i| {
+                let key = SMTKey {
+                    address: i.to_string(),
+                };
+                let value = SMTValue {
+                    nonce: i,
+                    balance: i as u128 * 100,
+                };
+                (key.to_h256(), value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_build() {
+        for n in [0, 1, 2, 3, 100, 1000] {
+            let leaves = leaves(n);
+            let mut tree = Tree::default();
+            tree.update_all(leaves.clone()).unwrap();
+
+            let mut sorted = leaves
+                .iter()
+                .map(|(path, value)| (*path, value.to_h256()))
+                .collect::<Vec<_>>();
+            sorted.sort_by_key(|(path, _)| *path);
+            let mut branches = HashMap::new();
+            let root = build::<Keccak256Hasher>(&sorted, |key, branch| {
+                assert!(branches.insert(key, branch).is_none());
+                Ok(())
+            })
+            .unwrap();
+
+            assert_eq!(&root, tree.root());
+            let expected = tree.store().branches_map();
+            assert_eq!(branches.len(), expected.len());
+            for (key, branch) in &branches {
+                assert_eq!(expected.get(key), Some(branch));
+            }
+        }
+
+        // the leaves must be sorted and unique
+        let mut unsorted = leaves(2)
+            .into_iter()
+            .map(|(path, value)| (path, value.to_h256()))
+            .collect::<Vec<_>>();
+        unsorted.sort_by_key(|(path, _)| std::cmp::Reverse(*path));
+        assert!(build::<Keccak256Hasher>(&unsorted, |_, _| Ok(())).is_err());
+        unsorted[1] = unsorted[0];
+        assert!(build::<Keccak256Hasher>(&unsorted, |_, _| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let line = br#"{"address": "1", "nonce": 2, "balance": "300"}"#;
+        assert_eq!(
+            parse_line::<SMTKey, SMTValue>(line).unwrap(),
+            Some((
+                SMTKey {
+                    address: "1".to_string()
+                },
+                SMTValue {
+                    nonce: 2,
+                    balance: 300
+                }
+            ))
+        );
+        assert_eq!(parse_line::<SMTKey, SMTValue>(b"  \r").unwrap(), None);
+        assert!(parse_line::<SMTKey, SMTValue>(b"{\"address\": \"1\"}").is_err());
+    }
+}
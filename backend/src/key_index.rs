@@ -0,0 +1,134 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An index from the leaf keys of a tree (the hashes of the original keys) to the original keys,
+//! so that the keys and the entries of a tree can be listed.  
+//! The index is stored next to the nodes of the tree under `prefix ++ KEY_INDEX_TAG ++ path` and is
+//! written in the same commit as the leaves, so it is deleted together with the tree.  
+//! The keys of a tree are told apart by their length after the prefix: 1 or 9 bytes for the history,
+//! 32 for leaves, 33 for branches and 36 for the index. A leaf or branch key may start with
+//! `KEY_INDEX_TAG` as well, so a scan of [`index_prefix`] must only accept the keys of [`is_index_key`].  
+//! Listings are ordered by the leaf keys and paginated with the leaf key of the last item.
+
+use crate::error::{Error, ErrorCode};
+use codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use sparse_merkle_tree::H256;
+use utoipa::ToSchema;
+
+/// The tag of the index keys.
+pub const KEY_INDEX_TAG: &[u8] = b"keys";
+/// The number of items of a page if the request does not limit it.
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+/// The largest number of items of a page.
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// The common prefix of all index keys of a tree.
+pub fn index_prefix(prefix: &[u8]) -> Vec<u8> {
+    [prefix, KEY_INDEX_TAG].concat()
+}
+
+/// Whether `key`, found under the [`index_prefix`] of `prefix`, is an index key and not a node key.
+pub fn is_index_key(prefix: &[u8], key: &[u8]) -> bool {
+    key.len() == prefix.len() + KEY_INDEX_TAG.len() + 32
+}
+
+/// The index key of the leaf `path`.
+pub fn index_key(prefix: &[u8], path: &H256) -> Vec<u8> {
+    [prefix, KEY_INDEX_TAG, &path.encode()].concat()
+}
+
+/// The smallest index key of the page after `start_after`.
+pub fn page_start(prefix: &[u8], start_after: Option<H256>) -> Vec<u8> {
+    match start_after {
+        // the smallest key greater than the index key of `start_after`
+        Some(path) => [index_key(prefix, &path), vec![0]].concat(),
+        None => index_prefix(prefix),
+    }
+}
+
+/// Decode an original key as it is stored in the index.
+pub fn decode_key<K: Decode>(value: &[u8]) -> Result<K, Error> {
+    K::decode(&mut &value[..]).map_err(|e| ErrorCode::Corruption.store_error(e))
+}
+
+/// The error of an indexed key without a leaf.
+pub fn missing_leaf(name: &str) -> Error {
+    ErrorCode::Corruption.store_error(format!("Missing leaf of an indexed key in tree {}", name))
+}
+
+/// One page of a listing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The leaf key of the last item, pass it as `start_after` to get the next page.
+    /// `None` on the last page.
+    pub next: Option<H256>,
+}
+
+impl<T> Page<T> {
+    /// A page of at most `limit` items, out of `items` read with a limit of `limit + 1`,
+    /// so that whether there is a next page is known.
+    pub fn new(mut items: Vec<T>, limit: usize, path: impl Fn(&T) -> H256) -> Self {
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(path)
+        } else {
+            None
+        };
+        Page { items, next }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next: self.next,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page() {
+        let path = |i: &u8| H256::from([*i; 32]);
+        let page = Page::new(vec![1u8, 2, 3], 2, path);
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(page.next, Some(path(&2)));
+        let page = Page::new(vec![1u8, 2], 2, path);
+        assert_eq!(page.next, None);
+        assert_eq!(page.map(|i| i * 2).items, vec![2, 4]);
+
+        // the page after `start_after` starts after its index key, but before the next one
+        let prefix = b"\x04a";
+        let start = page_start(prefix, Some(path(&1)));
+        assert!(start.as_slice() > index_key(prefix, &path(&1)).as_slice());
+        let mut next = [1u8; 32];
+        next[31] = 2;
+        assert!(start.as_slice() < index_key(prefix, &next.into()).as_slice());
+        assert!(start.starts_with(&index_prefix(prefix)));
+        assert_eq!(page_start(prefix, None), index_prefix(prefix));
+
+        // leaves and branches may share the index prefix
+        assert!(is_index_key(prefix, &index_key(prefix, &path(&1))));
+        let node_key = [&index_prefix(prefix)[..], &[0u8; 28]].concat();
+        assert!(!is_index_key(prefix, &node_key));
+        assert!(!is_index_key(prefix, &[node_key, vec![0]].concat()));
+    }
+}
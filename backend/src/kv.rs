@@ -0,0 +1,80 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The key-value database below the stores.  
+//! The trees, the sessions, the history and the registry are written once over [`KvDb`], see
+//! [`crate::tree_store`] and [`crate::multi_store`]. A backend only opens its database, reads a key,
+//! iterates the keys under a prefix and commits a set of changes atomically.
+
+use codec::Encode;
+use std::{io, path::Path};
+
+/// A column of the database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    /// The tree nodes, the key index and the undo logs of all trees, keyed by the tree prefix.
+    Nodes,
+    /// The tree registry, keyed by the tree prefix.
+    Trees,
+}
+
+/// A change of the database, see [`KvDb::commit`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Write a value.
+    Put(Column, Vec<u8>, Vec<u8>),
+    /// Delete a key.
+    Delete(Column, Vec<u8>),
+    /// Delete all keys that start with a prefix.
+    DeletePrefix(Column, Vec<u8>),
+}
+
+/// An iterator over key-value pairs, ordered by key.
+pub type KvIter<'a> = Box<dyn Iterator<Item = io::Result<(Vec<u8>, Vec<u8>)>> + 'a>;
+
+/// The key prefix of all nodes of a tree.  
+/// The name is length-prefixed (SCALE-encoded), so the keys of two trees never share a prefix,
+/// even if one name is a prefix of the other.
+pub fn tree_prefix(name: &str) -> Vec<u8> {
+    name.encode()
+}
+
+/// A key-value database with ordered keys and atomic commits, shared by all trees of a store.
+pub trait KvDb: Send + Sync + Sized + 'static {
+    /// Open the database, create it if it does not exist.
+    fn open(path: &Path) -> io::Result<Self>;
+
+    /// The value of a key.
+    fn get(&self, col: Column, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+
+    /// Iterate the key-value pairs whose key starts with `prefix`, ordered by key.
+    fn iter<'a>(&'a self, col: Column, prefix: &'a [u8]) -> io::Result<KvIter<'a>>;
+
+    /// Read at most `limit` key-value pairs accepted by `keep`, whose key starts with `prefix` and is not
+    /// less than `start`, ordered by key. `start` must start with `prefix`.
+    fn iter_from(
+        &self,
+        col: Column,
+        prefix: &[u8],
+        start: &[u8],
+        limit: usize,
+        keep: impl Fn(&[u8]) -> bool,
+    ) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// Write all changes in one atomic commit, in order.
+    fn commit(&self, changes: Vec<Change>) -> io::Result<()>;
+}
@@ -19,9 +19,25 @@
 //! Implement persistent storage for SMT.  
 //! A single database can store multiple Merkle trees, and they do not interfere with each other.
 
+pub mod batch;
 pub mod cli;
 pub mod error;
+pub mod hashers;
+pub mod history;
+pub mod ingest;
+pub mod key_index;
+pub mod kv;
+pub mod locks;
+pub mod multi_store;
 pub mod parity;
+pub mod pool;
+pub mod registry;
+pub mod req;
 pub mod rocks;
+pub mod server;
+pub mod session;
+pub mod snapshot;
+pub mod traits;
+pub mod tree_store;
 pub use parity::*;
 pub use rocks::*;
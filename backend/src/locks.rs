@@ -0,0 +1,116 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-tree read/write locks.  
+//! Reads of a tree run concurrently, while a write of a tree waits for its readers and blocks new ones,
+//! so a reader never sees half of a commit. Operations on different trees never wait for each other.  
+//! The locks must not be taken twice by one thread, the backends only take them in the [`crate::traits::MSS`]
+//! methods and never call another locked method while holding one.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// Unused locks are dropped once there are this many.
+const MAX_IDLE_LOCKS: usize = 1024;
+
+/// The locks of the trees of a store, keyed by the tree prefix.
+#[derive(Debug, Default)]
+pub struct TreeLocks {
+    locks: Mutex<HashMap<Vec<u8>, TreeLock>>,
+}
+
+impl TreeLocks {
+    /// The lock of the tree with the given prefix.
+    pub fn get(&self, prefix: &[u8]) -> TreeLock {
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+        if locks.len() >= MAX_IDLE_LOCKS {
+            // only this map holds the lock, so nobody waits for it
+            locks.retain(|_, lock| Arc::strong_count(&lock.0) > 1);
+        }
+        locks.entry(prefix.to_vec()).or_default().clone()
+    }
+}
+
+/// The lock of one tree.
+#[derive(Debug, Default, Clone)]
+pub struct TreeLock(Arc<RwLock<()>>);
+
+impl TreeLock {
+    /// Wait until no write of the tree is running.
+    pub fn read(&self) -> RwLockReadGuard<'_, ()> {
+        // the lock protects no data, so a panic while holding it leaves nothing inconsistent
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Wait until no read or write of the tree is running.
+    pub fn write(&self) -> RwLockWriteGuard<'_, ()> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, thread, time::Duration};
+
+    #[test]
+    fn test_tree_locks() {
+        let locks = Arc::new(TreeLocks::default());
+        let tree1 = locks.get(b"tree1");
+
+        // read locks are shared
+        let same = locks.get(b"tree1");
+        let read1 = tree1.read();
+        let read2 = same.read();
+        drop((read1, read2));
+
+        // a write waits for the readers of its tree only
+        let read = tree1.read();
+        let (tx, rx) = mpsc::channel();
+        let handle = {
+            let locks = locks.clone();
+            thread::spawn(move || {
+                let tree2 = locks.get(b"tree2");
+                let _other = tree2.write();
+                tx.send("tree2").unwrap();
+                let tree1 = locks.get(b"tree1");
+                let _write = tree1.write();
+                tx.send("tree1").unwrap();
+            })
+        };
+        assert_eq!(rx.recv().unwrap(), "tree2");
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(read);
+        assert_eq!(rx.recv().unwrap(), "tree1");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_idle_locks() {
+        let locks = TreeLocks::default();
+        let held = locks.get(b"held");
+        for i in 0..MAX_IDLE_LOCKS * 2 {
+            locks.get(i.to_string().as_bytes());
+        }
+        let map = locks.locks.lock().unwrap();
+        assert!(map.len() <= MAX_IDLE_LOCKS);
+        assert!(map.contains_key(b"held".as_slice()));
+        drop(held);
+    }
+}
@@ -0,0 +1,622 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The trees, sessions, history and registry of a store, over any [`KvDb`].  
+//! A single database can store multiple Merkle trees, and they do not interfere with each other.
+
+use crate::{
+    error::{Error, Result},
+    hashers::{HasherKind, Leaf, PerTreeHasher},
+    history::{self, unknown_root, RootVersion, DEFAULT_HISTORY_DEPTH},
+    ingest::{self, not_empty, Progress, DEFAULT_CHUNK_SIZE},
+    key_index::{decode_key, index_prefix, is_index_key, missing_leaf, page_start, Page},
+    kv::{tree_prefix, Change, Column, KvDb},
+    locks::TreeLocks,
+    registry::{
+        decode_meta, tree_exists, unknown_tree, wrong_hasher, HasherName, TreeInfo, TreeMeta,
+    },
+    session::{Session, SessionId, Sessions},
+    snapshot::{hasher_mismatch, read_snapshot},
+    traits::{merkle_proof, MSS},
+    tree_store::TreeStore,
+};
+use codec::{Decode, Encode};
+use scale_info::TypeInfo;
+use serde::{Deserialize, Serialize};
+use smt_primitives::{
+    blake2b_hasher::Blake2b256Hasher,
+    keccak_hasher::Keccak256Hasher,
+    kv::LeafHash,
+    poseidon_hasher::PoseidonHasher,
+    sha256_hasher::Sha256Hasher,
+    verify::{BatchLeaf, BatchProof, Proof},
+};
+use sparse_merkle_tree::{
+    traits::{Hasher, Value},
+    SparseMerkleTree, H256,
+};
+use std::{
+    fmt::Debug,
+    io::{self, Read},
+    marker::PhantomData,
+    path::Path,
+    sync::Arc,
+};
+use utoipa::{ToSchema, __dev::ComposeSchema};
+
+type MultiSMT<V, H, D> = SparseMerkleTree<H, Leaf<V, H>, TreeStore<D>>;
+
+/// Multiple Merkle trees are stored in one [`KvDb`].
+pub struct MultiTreeStore<K, V, H, D> {
+    pub(crate) store: Arc<D>,
+    implicit_create: bool,
+    history_depth: u64,
+    sessions: Sessions<V, H, TreeStore<D>>,
+    pub(crate) locks: Arc<TreeLocks>,
+    v: PhantomData<(K, V, H)>,
+}
+
+impl<K, V, H, D: KvDb> MultiTreeStore<K, V, H, D> {
+    /// Open the database, create it if it does not exist, see [`KvDb::open`].
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let db = D::open(path.as_ref())?;
+        Ok(Self {
+            store: Arc::new(db),
+            implicit_create: true,
+            history_depth: DEFAULT_HISTORY_DEPTH,
+            sessions: Default::default(),
+            locks: Default::default(),
+            v: PhantomData,
+        })
+    }
+
+    /// Whether updating a tree that is not registered creates it (the default),
+    /// otherwise trees must be created with [`MSS::create_tree`] first.
+    pub fn with_implicit_create(mut self, implicit_create: bool) -> Self {
+        self.implicit_create = implicit_create;
+        self
+    }
+
+    /// The number of past versions of each tree that can still be proven against, 0 keeps none.
+    pub fn with_history_depth(mut self, history_depth: u64) -> Self {
+        self.history_depth = history_depth;
+        self
+    }
+
+    /// The store of the trees of the hasher `G` in the same database, with the same settings and the
+    /// same locks of the trees.
+    pub fn with_hasher<G: HasherName>(&self) -> MultiTreeStore<K, V, G, D> {
+        MultiTreeStore {
+            store: self.store.clone(),
+            implicit_create: self.implicit_create,
+            history_depth: self.history_depth,
+            sessions: Sessions::tagged(G::KIND.tag()),
+            locks: self.locks.clone(),
+            v: PhantomData,
+        }
+    }
+
+    fn read_meta(&self, prefix: &str) -> Result<Option<TreeMeta>> {
+        self.store
+            .get(Column::Trees, &tree_prefix(prefix))
+            .map_err(Error::store)?
+            .map(|v| decode_meta(&v))
+            .transpose()
+    }
+
+    fn get_node(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.store.get(Column::Nodes, key).map_err(Error::store)
+    }
+
+    /// Remove all nodes of a tree and reset its leaf count in one commit, the caller holds the write lock
+    /// of the tree.
+    fn clear_nodes(&self, prefix: &str) -> Result<()> {
+        let mut changes = vec![Change::DeletePrefix(Column::Nodes, tree_prefix(prefix))];
+        if let Some(mut meta) = self.read_meta(prefix)? {
+            meta.touch(-(meta.leaf_count as i64));
+            changes.push(Change::Put(
+                Column::Trees,
+                tree_prefix(prefix),
+                meta.encode(),
+            ));
+        }
+        self.store.commit(changes).map_err(Error::store)
+    }
+}
+
+impl<K, V, D: KvDb> MultiTreeStore<K, V, Keccak256Hasher, D> {
+    /// The store of the trees of all hashers in the same database, see [`PerTreeHasher`].
+    #[allow(clippy::type_complexity)]
+    pub fn with_all_hashers(
+        self,
+    ) -> PerTreeHasher<
+        Self,
+        MultiTreeStore<K, V, Blake2b256Hasher, D>,
+        MultiTreeStore<K, V, Sha256Hasher, D>,
+        MultiTreeStore<K, V, PoseidonHasher, D>,
+    > {
+        let (blake2b256, sha256, poseidon) =
+            (self.with_hasher(), self.with_hasher(), self.with_hasher());
+        PerTreeHasher::new(self, blake2b256, sha256, poseidon)
+    }
+}
+
+impl<K, V, H, D> MultiTreeStore<K, V, H, D>
+where
+    D: KvDb,
+    K: LeafHash + Encode,
+    V: LeafHash + Default + Into<Vec<u8>> + From<Vec<u8>>,
+    H: Hasher + Default,
+{
+    /// The current root of a tree, the caller holds a lock of the tree.
+    fn root(&self, prefix: &str) -> Result<H256> {
+        let tree = MultiSMT::<V, H, D>::new_with_store(TreeStore::new(self.store.clone(), prefix))?;
+        Ok(*tree.root())
+    }
+
+    /// Run `f` on a session under the read lock of its tree, if the tree is still at the root the session began on.
+    fn with_session<R>(
+        &self,
+        session: SessionId,
+        f: impl FnOnce(&mut Session<V, H, TreeStore<D>>) -> Result<R>,
+    ) -> Result<R> {
+        let name = self
+            .sessions
+            .with(session, |session| Ok(session.name().to_string()))?;
+        let lock = self.locks.get(&tree_prefix(&name));
+        let _read = lock.read();
+        let root = self.root(&name)?;
+        self.sessions.with(session, |session| {
+            session.check_base(root)?;
+            f(session)
+        })
+    }
+
+    /// The tree as it was when its root was `root`, either the current or a kept past version.
+    fn tree_at(&self, prefix: &str, root: H256) -> Result<MultiSMT<V, H, D>> {
+        let tree = MultiSMT::new_with_store(TreeStore::new(self.store.clone(), prefix))?;
+        if *tree.root() == root {
+            return Ok(tree);
+        }
+        let overlay = history::rollback_to(&tree_prefix(prefix), root, |key| self.get_node(key))?
+            .ok_or_else(|| unknown_root(prefix, &root))?;
+        Ok(MultiSMT::new_with_store(TreeStore::with_overlay(
+            self.store.clone(),
+            prefix,
+            overlay,
+        ))?)
+    }
+
+    /// Apply `kvs` to the tree and commit them together with `meta` as one version.
+    fn apply(
+        &self,
+        prefix: &str,
+        mut meta: TreeMeta,
+        kvs: Vec<(K, V)>,
+    ) -> Result<(H256, TreeMeta)> {
+        let (index, kvs): (Vec<_>, Vec<_>) = kvs
+            .into_iter()
+            .map(|(k, v)| {
+                let path = k.leaf_hash::<H>();
+                let indexed = (!v.leaf_hash::<H>().is_zero()).then(|| k.encode());
+                ((path, indexed), (path, Leaf::new(v)))
+            })
+            .unzip();
+
+        let mut tree =
+            MultiSMT::<V, H, D>::new_with_store(TreeStore::new(self.store.clone(), prefix))?;
+        let root = *tree.update_all(kvs)?;
+        for (path, key) in index {
+            tree.store_mut().index_key(path, key);
+        }
+        meta.touch(tree.store().leaf_delta());
+        tree.store_mut()
+            .commit_version(&meta, root, self.history_depth)?;
+        Ok((root, meta))
+    }
+}
+
+impl<K, V, H: HasherName, D: KvDb> MultiTreeStore<K, V, H, D> {
+    /// The registry entry an update of the tree starts from.
+    fn meta_for_update(&self, prefix: &str) -> Result<TreeMeta> {
+        match self.read_meta(prefix)? {
+            Some(meta) if meta.hasher != H::NAME => {
+                Err(wrong_hasher(prefix, &meta.hasher, H::NAME))
+            }
+            Some(meta) => Ok(meta),
+            None if self.implicit_create => Ok(TreeMeta::new(prefix, H::NAME)),
+            None => Err(unknown_tree(prefix)),
+        }
+    }
+}
+
+impl<K, V, H, D> MSS<String, K, V, H> for MultiTreeStore<K, V, H, D>
+where
+    D: KvDb,
+    K: Value
+        + LeafHash
+        + Clone
+        + Serialize
+        + ToSchema
+        + Deserialize<'static>
+        + ComposeSchema
+        + Debug
+        + TypeInfo
+        + Encode
+        + Decode,
+    V: Default
+        + Value
+        + LeafHash
+        + Into<Vec<u8>>
+        + From<Vec<u8>>
+        + ToSchema
+        + Serialize
+        + Deserialize<'static>
+        + ComposeSchema
+        + PartialEq
+        + Clone
+        + Debug
+        + TypeInfo,
+    H: Hasher + Default + HasherName,
+{
+    type Tree = MultiSMT<V, H, D>;
+
+    /// Create or open a new tree.
+    fn new_tree_with_store(&self, prefix: String) -> Result<MultiSMT<V, H, D>> {
+        let db = TreeStore::new(self.store.clone(), &prefix);
+        Ok(MultiSMT::new_with_store(db)?)
+    }
+
+    /// Insert a value into a specific Merkle tree, all changed nodes are written in one transaction.
+    fn update(&self, prefix: String, key: K, value: V) -> Result<H256> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _write = lock.write();
+        let mut meta = self.meta_for_update(&prefix)?;
+        let mut tree = self.new_tree_with_store(prefix)?;
+        let path = key.leaf_hash::<H>();
+        let indexed = (!value.leaf_hash::<H>().is_zero()).then(|| key.encode());
+        let h = *tree.update(path, Leaf::new(value))?;
+        tree.store_mut().index_key(path, indexed);
+        meta.touch(tree.store().leaf_delta());
+        tree.store_mut()
+            .commit_version(&meta, h, self.history_depth)?;
+        Ok(h)
+    }
+
+    /// Insert multiple values into a Merkle tree at once, all changes are written in one transaction.
+    fn update_all(&self, prefix: String, kvs: Vec<(K, V)>) -> Result<H256> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _write = lock.write();
+        let meta = self.meta_for_update(&prefix)?;
+        let (root, _) = self.apply(&prefix, meta, kvs)?;
+        Ok(root)
+    }
+
+    /// Get the root hash.
+    fn get_root(&self, prefix: String) -> Result<H256> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        let tree = self.new_tree_with_store(prefix)?;
+        Ok(*tree.root())
+    }
+
+    /// Get the value of a specific key in a particular tree.
+    fn get_value(&self, prefix: String, key: K) -> Result<V> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        let tree = self.new_tree_with_store(prefix)?;
+        let value = tree.get(&key.leaf_hash::<H>())?;
+        Ok(value.into_inner())
+    }
+
+    /// Get the Merkle proof.
+    /// If the key is not in the tree, this is a non-membership proof (the value is empty and `value_hash` is zero).
+    fn get_merkle_proof(&self, prefix: String, key: K) -> Result<Proof<K, V>> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        let tree = self.new_tree_with_store(prefix)?;
+        merkle_proof(&tree, key)
+    }
+
+    /// Get one Merkle proof for multiple keys, the siblings are shared by all keys.
+    /// Duplicate keys are proven once, and keys that are not in the tree are proven to be absent.
+    fn get_batch_merkle_proof(&self, prefix: String, keys: Vec<K>) -> Result<BatchProof<K, V>> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        let tree = self.new_tree_with_store(prefix)?;
+        let mut keys = keys
+            .into_iter()
+            .map(|k| (k.leaf_hash::<H>(), k))
+            .collect::<Vec<(H256, K)>>();
+        keys.sort_by_key(|(path, _)| *path);
+        keys.dedup_by_key(|(path, _)| *path);
+
+        let proof = tree.merkle_proof(keys.iter().map(|(path, _)| *path).collect())?;
+        let (leaves_bitmap, siblings) = proof.take();
+        let leaves = keys
+            .into_iter()
+            .map(|(path, key)| {
+                let value = tree.get(&path)?.into_inner();
+                Ok(BatchLeaf {
+                    key,
+                    value_hash: value.leaf_hash::<H>(),
+                    value,
+                    path,
+                })
+            })
+            .collect::<Result<Vec<BatchLeaf<K, V>>>>()?;
+
+        Ok(BatchProof {
+            leaves,
+            root: *tree.root(),
+            leaves_bitmap,
+            siblings,
+        })
+    }
+
+    /// The original keys of a tree ordered by their leaf keys, at most `limit` keys after the leaf key `start_after`.
+    fn keys(&self, prefix: String, start_after: Option<H256>, limit: usize) -> Result<Page<K>> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        let prefix = tree_prefix(&prefix);
+        let keys = self
+            .store
+            .iter_from(
+                Column::Nodes,
+                &index_prefix(&prefix),
+                &page_start(&prefix, start_after),
+                limit.saturating_add(1),
+                |key| is_index_key(&prefix, key),
+            )
+            .map_err(Error::store)?
+            .into_iter()
+            .map(|(_, value)| decode_key(&value))
+            .collect::<Result<Vec<K>>>()?;
+        Ok(Page::new(keys, limit, |key| key.leaf_hash::<H>()))
+    }
+
+    /// Call `f` with every entry of a tree, with one iterator over the key index.
+    fn for_each_entry(
+        &self,
+        prefix: String,
+        f: &mut dyn FnMut(K, Vec<u8>) -> Result<()>,
+    ) -> Result<Option<TreeInfo>> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        let Some(meta) = self.read_meta(&prefix)? else {
+            return Ok(None);
+        };
+        let root = self.root(&prefix)?;
+        let name = tree_prefix(&prefix);
+        let keys_prefix = index_prefix(&name);
+        let index = self
+            .store
+            .iter(Column::Nodes, &keys_prefix)
+            .map_err(Error::store)?;
+        for kv in index {
+            let (index_key, key) = kv.map_err(Error::store)?;
+            if !is_index_key(&name, &index_key) {
+                continue;
+            }
+            let leaf_key = [&name[..], &index_key[keys_prefix.len()..]].concat();
+            let value = self
+                .get_node(&leaf_key)?
+                .ok_or_else(|| missing_leaf(&prefix))?;
+            f(decode_key(&key)?, value)?;
+        }
+        Ok(Some(TreeInfo { meta, root }))
+    }
+
+    /// Begin a session of staged updates of a tree, the staged nodes are kept in memory.
+    fn begin_session(&self, prefix: String) -> Result<SessionId> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        self.meta_for_update(&prefix)?;
+        let session = Session::new(prefix.clone(), TreeStore::new(self.store.clone(), &prefix))?;
+        self.sessions.insert(session)
+    }
+
+    /// Stage a round of updates in a session, returns the staged root.
+    fn session_update(&self, session: SessionId, kvs: Vec<(K, V)>) -> Result<H256> {
+        self.with_session(session, |session| session.update(kvs))
+    }
+
+    /// Get the staged value of a key in a session.
+    fn session_value(&self, session: SessionId, key: K) -> Result<V> {
+        self.with_session(session, |session| session.get_value(&key))
+    }
+
+    /// Get the Merkle proof for a key against the staged root of a session.
+    fn session_merkle_proof(&self, session: SessionId, key: K) -> Result<Proof<K, V>> {
+        self.with_session(session, |session| session.merkle_proof(key))
+    }
+
+    /// Write the staged updates of a session as one version of its tree, all in one commit.
+    fn commit_session(&self, session: SessionId) -> Result<H256> {
+        let session = self.sessions.remove(session)?;
+        let lock = self.locks.get(&tree_prefix(session.name()));
+        let _write = lock.write();
+        let meta = self.meta_for_update(session.name())?;
+        let root = self.root(session.name())?;
+        session.commit(root, meta, self.history_depth)
+    }
+
+    /// Drop the staged updates of a session.
+    fn rollback_session(&self, session: SessionId) -> Result<()> {
+        self.sessions.remove(session).map(drop)
+    }
+
+    /// Get the Merkle proof, the return value is `Vec<u8>`, which is not developer-friendly and may be inefficient for on-chain gas or functionality.
+    fn get_merkle_proof_old(&self, prefix: String, keys: Vec<K>) -> Result<Vec<u8>> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        let tree = self.new_tree_with_store(prefix)?;
+        let keys = keys
+            .into_iter()
+            .map(|k| Ok(k.leaf_hash::<H>()))
+            .collect::<Result<Vec<H256>>>()?;
+
+        let proof = tree.merkle_proof(keys.clone())?;
+        let proof = proof.compile(keys)?;
+        Ok(proof.0)
+    }
+
+    /// The current root and the past roots that are still kept, newest first.
+    fn root_history(&self, prefix: String) -> Result<Vec<RootVersion>> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        if self.read_meta(&prefix)?.is_none() {
+            return Err(unknown_tree(&prefix));
+        }
+        let root = self.root(&prefix)?;
+        history::root_history(&tree_prefix(&prefix), root, |key| self.get_node(key))
+    }
+
+    /// Get the value of a key when the root of the tree was `root`.
+    fn get_value_at(&self, prefix: String, root: H256, key: K) -> Result<V> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        let tree = self.tree_at(&prefix, root)?;
+        Ok(tree.get(&key.leaf_hash::<H>())?.into_inner())
+    }
+
+    /// Get the Merkle proof of a key against the root `root` of the tree.
+    fn get_merkle_proof_at(&self, prefix: String, root: H256, key: K) -> Result<Proof<K, V>> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        let tree = self.tree_at(&prefix, root)?;
+        merkle_proof(&tree, key)
+    }
+
+    /// Import a snapshot as a new tree, the tree is only written if the rebuilt root is the recorded one.
+    fn import_tree(&self, prefix: String, reader: &mut dyn Read) -> Result<TreeMeta> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _write = lock.write();
+        let (header, entries) = read_snapshot::<K>(reader)?;
+        if header.hasher != H::NAME {
+            return Err(hasher_mismatch(&header.hasher, H::NAME));
+        }
+        if self.read_meta(&prefix)?.is_some() || self.root(&prefix)? != H256::zero() {
+            return Err(tree_exists(&prefix));
+        }
+        // the nodes of an interrupted import or ingest
+        self.clear_nodes(&prefix)?;
+        let mut entries = entries.map(|entry| entry.map(|(k, v)| (k, V::from(v))));
+        let mut store = TreeStore::new(self.store.clone(), &prefix);
+        let meta = TreeMeta::new(prefix.clone(), H::NAME);
+        ingest::write_tree::<K, V, H, _>(
+            &mut store,
+            meta,
+            &mut entries,
+            DEFAULT_CHUNK_SIZE,
+            Some(header.root),
+            &mut |_| {},
+        )
+        .map(|info| info.meta)
+        .or_else(|e| {
+            self.clear_nodes(&prefix)?;
+            Err(e)
+        })
+    }
+
+    /// Build an empty tree from a stream of entries, the nodes left by an interrupted ingest are removed first.
+    fn ingest(
+        &self,
+        prefix: String,
+        entries: &mut dyn Iterator<Item = Result<(K, V), Error>>,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<TreeInfo> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _write = lock.write();
+        let meta = self.meta_for_update(&prefix)?;
+        if self.root(&prefix)? != H256::zero() {
+            return Err(not_empty(&prefix));
+        }
+        self.clear_nodes(&prefix)?;
+        let mut store = TreeStore::new(self.store.clone(), &prefix);
+        ingest::write_tree::<K, V, H, _>(
+            &mut store,
+            meta,
+            entries,
+            DEFAULT_CHUNK_SIZE,
+            None,
+            progress,
+        )
+    }
+
+    /// Remove all leaves of a specific Merkle tree (and its past versions), a registered tree stays registered.
+    fn clear(&self, prefix: String) -> Result<()> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _write = lock.write();
+        self.clear_nodes(&prefix)
+    }
+
+    /// Register an empty tree, `hasher` must be the hasher of the store.
+    fn create_tree(&self, prefix: String, hasher: HasherKind) -> Result<TreeMeta> {
+        if hasher != H::KIND {
+            return Err(wrong_hasher(&prefix, hasher.name(), H::NAME));
+        }
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _write = lock.write();
+        if self.read_meta(&prefix)?.is_some() {
+            return Err(tree_exists(&prefix));
+        }
+        let meta = TreeMeta::new(prefix.clone(), H::NAME);
+        self.store
+            .commit(vec![Change::Put(
+                Column::Trees,
+                tree_prefix(&prefix),
+                meta.encode(),
+            )])
+            .map_err(Error::store)?;
+        Ok(meta)
+    }
+
+    /// The metadata of all registered trees.
+    fn trees(&self) -> Result<Vec<TreeMeta>> {
+        self.store
+            .iter(Column::Trees, &[])
+            .map_err(Error::store)?
+            .map(|kv| {
+                let (_, v) = kv.map_err(Error::store)?;
+                decode_meta(&v)
+            })
+            .collect()
+    }
+
+    /// The metadata of a tree, `None` if it is not registered.
+    fn tree_meta(&self, prefix: String) -> Result<Option<TreeMeta>> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _read = lock.read();
+        self.read_meta(&prefix)
+    }
+
+    /// Delete a tree with all of its nodes and its registry entry in one transaction.
+    fn delete_tree(&self, prefix: String) -> Result<()> {
+        let lock = self.locks.get(&tree_prefix(&prefix));
+        let _write = lock.write();
+        if self.read_meta(&prefix)?.is_none() {
+            return Err(unknown_tree(&prefix));
+        }
+        self.store
+            .commit(vec![
+                Change::DeletePrefix(Column::Nodes, tree_prefix(&prefix)),
+                Change::Delete(Column::Trees, tree_prefix(&prefix)),
+            ])
+            .map_err(Error::store)
+    }
+}
@@ -1,4 +1,3 @@
 pub mod parity_apis;
 pub mod parity_db;
-pub mod parity_req;
 pub mod parity_store;
@@ -1,178 +1,28 @@
-use crate::parity_db::ParityDb;
-use crate::parity_store::SMTParityStore;
-use scale_info::TypeInfo;
-use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
-use smt_primitives::{
-    // keccak_hasher::Keccak256Hasher,
-    verify::{verify as smt_verify, Proof},
-};
-use sparse_merkle_tree::{
-    error::{Error, Result as SMTResult},
-    // merge::MergeValue,
-    traits::{Hasher, Value},
-    CompiledMerkleProof,
-    SparseMerkleTree,
-    H256,
-};
-use std::{fmt::Debug, marker::PhantomData, path::Path, sync::Arc};
-use utoipa::{ToSchema, __dev::ComposeSchema};
-
-type MultiSMT<V, H> = SparseMerkleTree<H, V, SMTParityStore>;
-
-/// Multiple Merkle trees are stored in a ParityDb database
-pub struct MultiSMTParityStore<K, V, H> {
-    store: Arc<Mutex<ParityDb>>,
-    v: PhantomData<(K, V, H)>,
-}
+use crate::{multi_store::MultiTreeStore, parity_db::ParityDb};
 
-impl<
-        K: Value
-            + Clone
-            + Serialize
-            + ToSchema
-            + Deserialize<'static>
-            + ComposeSchema
-            + Debug
-            + TypeInfo,
-        V: Default
-            + Value
-            + Into<Vec<u8>>
-            + From<Vec<u8>>
-            + ToSchema
-            + Serialize
-            + Deserialize<'static>
-            + ComposeSchema
-            + PartialEq
-            + Clone
-            + Debug
-            + TypeInfo,
-        H: Hasher + Default,
-    > MultiSMTParityStore<K, V, H>
-{
-    /// Open the ParityDb database, create it if it does not exist.
-    pub fn open<P: AsRef<Path>>(path: P, num_columns: u8) -> std::io::Result<Self> {
-        let db = ParityDb::new(path.as_ref(), num_columns);
-        Ok(Self {
-            store: Arc::new(Mutex::new(db)),
-            v: Default::default(),
-        })
-    }
-
-    /// Create or open a new tree with specified column
-    pub fn new_tree_with_store(&self, col: u8) -> Result<MultiSMT<V, H>, Error> {
-        let db = SMTParityStore::new(self.store.clone(), col);
-        MultiSMT::new_with_store(db)
-    }
-
-    /// Insert a value into a specific Merkle tree
-    pub fn update(&self, col: u8, key: K, value: V) -> SMTResult<H256> {
-        let mut tree = self.new_tree_with_store(col)?;
-        let h = tree.update(key.to_h256(), value)?;
-        Ok(*h)
-    }
-
-    /// Insert multiple values into a Merkle tree at once
-    pub fn update_all(&self, col: u8, kvs: Vec<(K, V)>) -> SMTResult<H256> {
-        let kvs = kvs
-            .into_iter()
-            .map(|(k, v)| Ok((k.to_h256(), v)))
-            .collect::<Result<Vec<(H256, V)>, Error>>()?;
-
-        let mut tree = self.new_tree_with_store(col)?;
-        let root = tree.update_all(kvs)?;
-        Ok(*root)
-    }
-
-    /// Get the root hash
-    pub fn get_root(&self, col: u8) -> Result<H256, Error> {
-        let tree = self.new_tree_with_store(col)?;
-        Ok(*tree.root())
-    }
-
-    /// Get the value of a specific key in a particular tree
-    pub fn get_value(&self, col: u8, key: K) -> Result<V, Error> {
-        let tree = self.new_tree_with_store(col)?;
-        let value = tree.get(&key.to_h256())?;
-        Ok(value)
-    }
-
-    /// Get the Merkle proof
-    pub fn get_merkle_proof(&self, col: u8, key: K) -> Result<Proof<K, V>, Error> {
-        let tree = self.new_tree_with_store(col)?;
-        let proof = tree.merkle_proof(vec![key.to_h256()])?;
-        let leaves_bitmap = proof.leaves_bitmap();
-        let siblings = proof.merkle_path();
-        let leave_bitmap = leaves_bitmap[0];
-        let value = self.get_value(col, key.clone())?;
-
-        Ok(Proof {
-            key: key.clone(),
-            value: value.clone(),
-            path: key.to_h256(),
-            value_hash: value.to_h256(),
-            root: *tree.root(),
-            leave_bitmap,
-            siblings: siblings.clone(),
-        })
-    }
-
-    /// Get the Merkle proof, the return value is `Vec<u8>`
-    pub fn get_merkle_proof_old(&self, col: u8, keys: Vec<K>) -> SMTResult<Vec<u8>> {
-        let tree = self.new_tree_with_store(col)?;
-        let keys = keys
-            .into_iter()
-            .map(|k| Ok(k.to_h256()))
-            .collect::<Result<Vec<H256>, Error>>()?;
-
-        let proof = tree.merkle_proof(keys.clone())?;
-        let proof = proof.compile(keys)?;
-        Ok(proof.0)
-    }
-
-    /// Before data is updated, the future value of the root hash can be calculated in advance
-    pub fn get_next_root(&self, old_proof: Vec<u8>, next_kvs: Vec<(K, V)>) -> Result<H256, Error> {
-        let p = CompiledMerkleProof(old_proof);
-        let kvs = next_kvs
-            .into_iter()
-            .map(|(k, v)| Ok((k.to_h256(), v.to_h256())))
-            .collect::<Result<Vec<(H256, H256)>, Error>>()?;
-
-        let next_root = p.compute_root::<H>(kvs)?;
-        Ok(next_root)
-    }
-
-    /// Delete a specific Merkle tree by clearing its column
-    pub fn clear(&self, col: u8) -> Result<(), Error> {
-        self.store.lock().unwrap()
-            .clear_column(col)
-            .map_err(|e| Error::Store(e.to_string()))?;
-        Ok(())
-    }
-
-    /// Verify the Merkle proof
-    pub fn verify(&self, proof: Proof<K, V>) -> bool {
-        let mut res = false;
-        if proof.value != V::default() {
-            res = smt_verify::<H>(
-                proof.path,
-                proof.value_hash,
-                proof.leave_bitmap,
-                proof.siblings,
-                proof.root,
-            )
-        }
-        res
-    }
-}
+/// Multiple Merkle trees are stored in a ParityDb database, see [`MultiTreeStore`]
+/// All trees share a fixed set of columns, so the number of trees is unlimited
+pub type MultiSMTParityStore<K, V, H> = MultiTreeStore<K, V, H, ParityDb>;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        error::Error,
+        hashers::{HasherKind, Leaf},
+        history::RootVersion,
+        ingest,
+        key_index::index_prefix,
+        parity_store::{tree_prefix, COL_NODES},
+        registry::HasherName,
+        traits::MSS,
+    };
+    use codec::Encode;
     use smt_primitives::{
         keccak_hasher::Keccak256Hasher,
         kv::{SMTKey, SMTValue},
     };
+    use sparse_merkle_tree::{traits::Value, H256};
     use tempfile::tempdir;
 
     #[test]
@@ -180,19 +30,25 @@ mod tests {
         // Create multi_tree
         let temp_dir = tempdir().unwrap();
         let multi_tree =
-            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path(), 2)
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
                 .unwrap();
 
-        let tree1_col: u8 = 0;
-        let tree2_col: u8 = 1;
-        multi_tree.clear(tree1_col).unwrap();
-        multi_tree.clear(tree2_col).unwrap();
-        multi_tree.new_tree_with_store(tree1_col).unwrap();
-        multi_tree.new_tree_with_store(tree2_col).unwrap();
+        let tree1: &str = "tree1";
+        let tree2: &str = "tree2";
+        multi_tree.clear(tree1.to_string()).unwrap();
+        multi_tree.clear(tree2.to_string()).unwrap();
+        multi_tree.new_tree_with_store(tree1.to_string()).unwrap();
+        multi_tree.new_tree_with_store(tree2.to_string()).unwrap();
 
         // Get roots from both trees
-        assert_eq!(multi_tree.get_root(tree1_col).unwrap(), H256::zero());
-        assert_eq!(multi_tree.get_root(tree2_col).unwrap(), H256::zero());
+        assert_eq!(
+            multi_tree.get_root(tree1.to_string()).unwrap(),
+            H256::zero()
+        );
+        assert_eq!(
+            multi_tree.get_root(tree2.to_string()).unwrap(),
+            H256::zero()
+        );
 
         // Insert data into tree1
         let tree1_key1 = SMTKey {
@@ -211,57 +67,68 @@ mod tests {
         };
 
         assert_eq!(
-            multi_tree.get_value(tree1_col, tree1_key1.clone()).unwrap(),
+            multi_tree
+                .get_value(tree1.to_string(), tree1_key1.clone())
+                .unwrap(),
             SMTValue::default()
         );
-        assert_eq!(multi_tree.get_root(tree1_col).unwrap(), H256::zero());
+        assert_eq!(
+            multi_tree.get_root(tree1.to_string()).unwrap(),
+            H256::zero()
+        );
 
         // Update and verify
         multi_tree
-            .update(tree1_col, tree1_key1.clone(), tree1_value1.clone())
+            .update(tree1.to_string(), tree1_key1.clone(), tree1_value1.clone())
             .unwrap();
         assert_eq!(
-            multi_tree.get_value(tree1_col, tree1_key1.clone()).unwrap(),
+            multi_tree
+                .get_value(tree1.to_string(), tree1_key1.clone())
+                .unwrap(),
             tree1_value1.clone()
         );
 
         let proof = multi_tree
-            .get_merkle_proof(tree1_col, tree1_key1.clone())
+            .get_merkle_proof(tree1.to_string(), tree1_key1.clone())
             .unwrap();
         assert!(multi_tree.verify(proof));
 
         // Test remove
         multi_tree
-            .update(tree1_col, tree1_key1.clone(), SMTValue::default())
+            .update(tree1.to_string(), tree1_key1.clone(), SMTValue::default())
             .unwrap();
         assert_eq!(
-            multi_tree.get_value(tree1_col, tree1_key1.clone()).unwrap(),
+            multi_tree
+                .get_value(tree1.to_string(), tree1_key1.clone())
+                .unwrap(),
             SMTValue::default()
         );
 
         // Test multiple updates
         multi_tree
-            .update(tree1_col, tree1_key1.clone(), tree1_value1.clone())
+            .update(tree1.to_string(), tree1_key1.clone(), tree1_value1.clone())
             .unwrap();
-        let _tree1_root1 = multi_tree.get_root(tree1_col).unwrap();
+        let _tree1_root1 = multi_tree.get_root(tree1.to_string()).unwrap();
 
         let old_proof = multi_tree
-            .get_merkle_proof_old(tree1_col, vec![tree1_key2.clone()])
+            .get_merkle_proof_old(tree1.to_string(), vec![tree1_key2.clone()])
             .unwrap();
         let _next_root = multi_tree
             .get_next_root(old_proof, vec![(tree1_key2.clone(), tree1_value2.clone())])
             .unwrap();
 
         let tree2_root1 = multi_tree
-            .update(tree1_col, tree1_key2.clone(), tree1_value2.clone())
+            .update(tree1.to_string(), tree1_key2.clone(), tree1_value2.clone())
             .unwrap();
 
         assert_eq!(_next_root, tree2_root1);
 
         // Test clear
-        multi_tree.clear(tree1_col).unwrap();
+        multi_tree.clear(tree1.to_string()).unwrap();
         assert_eq!(
-            multi_tree.get_value(tree1_col, tree1_key1.clone()).unwrap(),
+            multi_tree
+                .get_value(tree1.to_string(), tree1_key1.clone())
+                .unwrap(),
             SMTValue::default()
         );
 
@@ -279,6 +146,723 @@ mod tests {
             ));
         }
 
-        multi_tree.update_all(tree1_col, kvs.clone()).unwrap();
+        multi_tree
+            .update_all(tree1.to_string(), kvs.clone())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_non_membership_proof() {
+        let temp_dir = tempdir().unwrap();
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap();
+        let tree: &str = "tree";
+        let key1 = SMTKey {
+            address: "1".to_string(),
+        };
+        let key2 = SMTKey {
+            address: "2".to_string(),
+        };
+
+        // empty tree
+        let proof = multi_tree
+            .get_merkle_proof(tree.to_string(), key1.clone())
+            .unwrap();
+        assert!(multi_tree.verify_non_membership(proof.clone()));
+        assert!(!multi_tree.verify(proof));
+
+        multi_tree
+            .update(
+                tree.to_string(),
+                key1.clone(),
+                SMTValue {
+                    nonce: 1,
+                    balance: 99,
+                },
+            )
+            .unwrap();
+
+        let proof = multi_tree
+            .get_merkle_proof(tree.to_string(), key2.clone())
+            .unwrap();
+        assert_eq!(proof.value, SMTValue::default());
+        assert!(proof.value_hash.is_zero());
+        assert!(multi_tree.verify_non_membership(proof));
+
+        let proof = multi_tree
+            .get_merkle_proof(tree.to_string(), key1.clone())
+            .unwrap();
+        assert!(!multi_tree.verify_non_membership(proof.clone()));
+        assert!(multi_tree.verify(proof));
+
+        // a removed key is absent again
+        multi_tree
+            .update(tree.to_string(), key1.clone(), SMTValue::default())
+            .unwrap();
+        let proof = multi_tree.get_merkle_proof(tree.to_string(), key1).unwrap();
+        assert!(multi_tree.verify_non_membership(proof));
+    }
+
+    #[test]
+    fn test_batch_merkle_proof() {
+        let temp_dir = tempdir().unwrap();
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap();
+        let tree: &str = "tree";
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let kvs: Vec<(SMTKey, SMTValue)> = (1..50)
+            .map(|i| {
+                (
+                    key(i),
+                    SMTValue {
+                        nonce: i,
+                        balance: i as u128,
+                    },
+                )
+            })
+            .collect();
+        let root = multi_tree.update_all(tree.to_string(), kvs).unwrap();
+
+        let keys = vec![key(3), key(1), key(49), key(3), key(100)];
+        let proof = multi_tree
+            .get_batch_merkle_proof(tree.to_string(), keys)
+            .unwrap();
+        assert_eq!(proof.root, root);
+        assert_eq!(proof.leaves.len(), 4);
+        assert_eq!(proof.leaves_bitmap.len(), 4);
+        assert!(proof.leaves.windows(2).all(|w| w[0].path < w[1].path));
+        let absent = proof
+            .leaves
+            .iter()
+            .find(|leaf| leaf.key == key(100))
+            .unwrap();
+        assert!(absent.value_hash.is_zero());
+        assert!(multi_tree.verify_batch(proof.clone()));
+
+        // tamper with a value
+        let mut wrong = proof.clone();
+        wrong.leaves[0].value_hash = SMTValue {
+            nonce: 1000,
+            balance: 1000,
+        }
+        .to_h256();
+        assert!(!multi_tree.verify_batch(wrong));
+
+        // against another root
+        multi_tree
+            .update(tree.to_string(), key(2), SMTValue::default())
+            .unwrap();
+        let mut stale = proof;
+        stale.root = multi_tree.get_root(tree.to_string()).unwrap();
+        assert!(!multi_tree.verify_batch(stale));
+    }
+
+    #[test]
+    fn test_interrupted_update() {
+        let temp_dir = tempdir().unwrap();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+        let root = {
+            let multi_tree =
+                MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                    .unwrap();
+            let root = multi_tree
+                .update_all(
+                    "tree".to_string(),
+                    (1..10).map(|i| (key(i), value(i))).collect(),
+                )
+                .unwrap();
+
+            // The write is killed halfway: the tree is changed but never committed
+            let mut tree = multi_tree.new_tree_with_store("tree".to_string()).unwrap();
+            tree.update_all(
+                (5..20)
+                    .map(|i| (key(i).to_h256(), Leaf::new(value(i + 1))))
+                    .collect(),
+            )
+            .unwrap();
+            assert_ne!(*tree.root(), root);
+            drop(tree.to_string());
+            root
+        };
+
+        // The old root is still intact after reopening the database
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap();
+        assert_eq!(multi_tree.get_root("tree".to_string()).unwrap(), root);
+        assert_eq!(
+            multi_tree.get_value("tree".to_string(), key(5)).unwrap(),
+            value(5)
+        );
+        assert_eq!(
+            multi_tree.get_value("tree".to_string(), key(15)).unwrap(),
+            SMTValue::default()
+        );
+        (1..10).for_each(|i| {
+            let proof = multi_tree
+                .get_merkle_proof("tree".to_string(), key(i))
+                .unwrap();
+            assert!(multi_tree.verify(proof));
+        });
+    }
+
+    #[test]
+    fn test_named_trees() {
+        let temp_dir = tempdir().unwrap();
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+
+        // more trees than the old column limit, with names that are prefixes of each other
+        let names: Vec<String> = (0..100)
+            .map(|i| "a".repeat(i % 5 + 1) + &(i / 5).to_string())
+            .collect();
+        let mut roots = vec![];
+        for (i, name) in names.iter().enumerate() {
+            let i = i as u64;
+            roots.push(
+                multi_tree
+                    .update_all(
+                        name.clone(),
+                        vec![(key(i), value(i)), (key(i + 1), value(i))],
+                    )
+                    .unwrap(),
+            );
+        }
+        for (i, name) in names.iter().enumerate() {
+            let i = i as u64;
+            assert_eq!(
+                multi_tree.get_root(name.clone()).unwrap(),
+                roots[i as usize]
+            );
+            assert_eq!(
+                multi_tree.get_value(name.clone(), key(i + 1)).unwrap(),
+                value(i)
+            );
+            assert_eq!(
+                multi_tree.get_value(name.clone(), key(i + 2)).unwrap(),
+                SMTValue::default()
+            );
+        }
+
+        // the registry
+        let trees = multi_tree.trees().unwrap();
+        assert_eq!(trees.len(), names.len());
+        assert!(names
+            .iter()
+            .all(|name| trees.iter().any(|meta| &meta.name == name)));
+        let meta = multi_tree.tree_meta("a0".to_string()).unwrap().unwrap();
+        assert_eq!(meta.name, "a0");
+        assert_eq!(meta.leaf_count, 2);
+        assert!(meta.updated_at >= meta.created_at);
+        assert_eq!(multi_tree.tree_meta("b".to_string()).unwrap(), None);
+        // reading a tree does not register it
+        assert_eq!(multi_tree.get_root("b".to_string()).unwrap(), H256::zero());
+        assert_eq!(multi_tree.tree_meta("b".to_string()).unwrap(), None);
+
+        // deleting a tree leaves the others untouched
+        multi_tree.delete_tree("a0".to_string()).unwrap();
+        assert_eq!(multi_tree.get_root("a0".to_string()).unwrap(), H256::zero());
+        assert_eq!(multi_tree.tree_meta("a0".to_string()).unwrap(), None);
+        assert_eq!(multi_tree.trees().unwrap().len(), names.len() - 1);
+        for (i, name) in names.iter().enumerate().skip(1) {
+            assert_eq!(multi_tree.get_root(name.clone()).unwrap(), roots[i]);
+        }
+    }
+
+    #[test]
+    fn test_tree_registry() {
+        let temp_dir = tempdir().unwrap();
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap()
+                .with_implicit_create(false);
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+
+        // the tree must be created first
+        assert!(multi_tree.update(tree.clone(), key(1), value(1)).is_err());
+        assert_eq!(multi_tree.describe_tree(tree.clone()).unwrap(), None);
+        let meta = multi_tree
+            .create_tree(tree.clone(), HasherKind::Keccak256)
+            .unwrap();
+        assert_eq!(meta.hasher, Keccak256Hasher::NAME);
+        assert_eq!(meta.leaf_count, 0);
+        assert!(multi_tree
+            .create_tree(tree.clone(), HasherKind::Keccak256)
+            .is_err());
+        assert_eq!(multi_tree.trees().unwrap(), vec![meta]);
+
+        // the leaves are counted
+        multi_tree
+            .update_all(tree.clone(), (1..11).map(|i| (key(i), value(i))).collect())
+            .unwrap();
+        multi_tree.update(tree.clone(), key(1), value(2)).unwrap();
+        multi_tree
+            .update(tree.clone(), key(2), SMTValue::default())
+            .unwrap();
+        let root = multi_tree
+            .update(tree.clone(), key(100), SMTValue::default())
+            .unwrap();
+        let info = multi_tree.describe_tree(tree.clone()).unwrap().unwrap();
+        assert_eq!(info.meta.leaf_count, 9);
+        assert_eq!(info.root, root);
+
+        // clear keeps the tree registered
+        multi_tree.clear(tree.clone()).unwrap();
+        let info = multi_tree.describe_tree(tree.clone()).unwrap().unwrap();
+        assert_eq!(info.meta.leaf_count, 0);
+        assert_eq!(info.root, H256::zero());
+
+        // delete removes it
+        multi_tree.delete_tree(tree.clone()).unwrap();
+        assert_eq!(multi_tree.describe_tree(tree.clone()).unwrap(), None);
+        assert!(multi_tree.trees().unwrap().is_empty());
+        assert!(multi_tree.delete_tree(tree.clone()).is_err());
+        assert!(multi_tree.update(tree, key(1), value(1)).is_err());
+    }
+
+    #[test]
+    fn test_root_history() {
+        let temp_dir = tempdir().unwrap();
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap()
+                .with_history_depth(3);
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+
+        // version i adds key i, version 6 changes key 1
+        let roots = (1..6)
+            .map(|i| multi_tree.update(tree.clone(), key(i), value(i)).unwrap())
+            .collect::<Vec<H256>>();
+        let root = multi_tree.update(tree.clone(), key(1), value(100)).unwrap();
+
+        // only the last 3 versions are kept
+        let history = multi_tree.root_history(tree.clone()).unwrap();
+        assert_eq!(
+            history,
+            vec![
+                RootVersion { version: 6, root },
+                RootVersion {
+                    version: 5,
+                    root: roots[4]
+                },
+                RootVersion {
+                    version: 4,
+                    root: roots[3]
+                },
+            ]
+        );
+
+        // read and prove against version 4
+        assert_eq!(
+            multi_tree
+                .get_value_at(tree.clone(), roots[3], key(1))
+                .unwrap(),
+            value(1)
+        );
+        assert_eq!(
+            multi_tree
+                .get_value_at(tree.clone(), roots[3], key(5))
+                .unwrap(),
+            SMTValue::default()
+        );
+        let proof = multi_tree
+            .get_merkle_proof_at(tree.clone(), roots[3], key(1))
+            .unwrap();
+        assert_eq!(proof.root, roots[3]);
+        assert!(multi_tree.verify(proof));
+        let proof = multi_tree
+            .get_merkle_proof_at(tree.clone(), roots[3], key(5))
+            .unwrap();
+        assert!(multi_tree.verify_non_membership(proof));
+
+        // the current root is always known, expired roots are not
+        assert_eq!(
+            multi_tree
+                .get_merkle_proof_at(tree.clone(), root, key(1))
+                .unwrap(),
+            multi_tree.get_merkle_proof(tree.clone(), key(1)).unwrap()
+        );
+        assert!(multi_tree
+            .get_value_at(tree.clone(), roots[2], key(1))
+            .is_err());
+        assert_eq!(
+            multi_tree.get_value(tree.clone(), key(1)).unwrap(),
+            value(100)
+        );
+
+        // clear drops the history
+        multi_tree.clear(tree.clone()).unwrap();
+        assert_eq!(
+            multi_tree.root_history(tree.clone()).unwrap(),
+            vec![RootVersion {
+                version: 0,
+                root: H256::zero()
+            }]
+        );
+        assert!(multi_tree.get_value_at(tree, roots[4], key(1)).is_err());
+    }
+
+    #[test]
+    fn test_key_index() {
+        let temp_dir = tempdir().unwrap();
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap();
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+
+        multi_tree
+            .update_all(tree.clone(), (1..26).map(|i| (key(i), value(i))).collect())
+            .unwrap();
+        multi_tree.update(tree.clone(), key(30), value(30)).unwrap();
+        // removed leaves are not listed
+        multi_tree
+            .update(tree.clone(), key(1), SMTValue::default())
+            .unwrap();
+        multi_tree.update(tree.clone(), key(2), value(200)).unwrap();
+
+        // a leaf whose key starts with the tag of the index is not listed
+        let node_key = [index_prefix(&tree_prefix(&tree)), vec![7u8; 28]].concat();
+        multi_tree
+            .store
+            .insert(COL_NODES, &node_key, b"leaf")
+            .unwrap();
+
+        // page through all keys
+        let mut keys = vec![];
+        let mut start_after = None;
+        loop {
+            let page = multi_tree.keys(tree.clone(), start_after, 10).unwrap();
+            assert!(page.items.len() <= 10);
+            keys.extend(page.items);
+            match page.next {
+                Some(next) => start_after = Some(next),
+                None => break,
+            }
+        }
+        let mut expected = (2..26).chain([30]).map(key).collect::<Vec<SMTKey>>();
+        expected.sort_by_key(|k| k.to_h256().encode());
+        assert_eq!(keys, expected);
+
+        let entries = multi_tree.entries(tree.clone(), None, 100).unwrap();
+        assert_eq!(entries.next, None);
+        assert_eq!(entries.items.len(), 25);
+        for entry in entries.items {
+            let i: u64 = entry.key.address.parse().unwrap();
+            let expected = if i == 2 { value(200) } else { value(i) };
+            assert_eq!(entry.value, expected);
+        }
+
+        // the index belongs to the tree and is cleared with it
+        assert!(multi_tree
+            .keys("other".to_string(), None, 10)
+            .unwrap()
+            .items
+            .is_empty());
+        multi_tree.clear(tree.clone()).unwrap();
+        assert!(multi_tree.keys(tree, None, 10).unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn test_ingest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+        // the last entry of a key wins, empty values are deletions
+        let kvs = (1..300)
+            .map(|i| (key(i), value(i)))
+            .chain([(key(5), value(500)), (key(7), SMTValue::default())])
+            .collect::<Vec<_>>();
+
+        let mut reports = vec![];
+        let info = multi_tree
+            .ingest(
+                "ingested".to_string(),
+                &mut kvs.clone().into_iter().map(Ok),
+                &mut |p| reports.push(p),
+            )
+            .unwrap();
+        let root = multi_tree
+            .update_all("updated".to_string(), kvs.clone())
+            .unwrap();
+        assert_eq!(info.root, root);
+        assert_eq!(info.meta.leaf_count, 298);
+        assert_eq!(
+            multi_tree.tree_meta("ingested".to_string()).unwrap(),
+            Some(info.meta)
+        );
+        let last = reports.last().unwrap();
+        assert_eq!(last.stage, ingest::Stage::Branches);
+        assert_eq!(last.entries, 301);
+        assert_eq!(
+            multi_tree
+                .get_value("ingested".to_string(), key(5))
+                .unwrap(),
+            value(500)
+        );
+        let proof = multi_tree
+            .get_merkle_proof("ingested".to_string(), key(8))
+            .unwrap();
+        assert!(multi_tree.verify(proof));
+        assert_eq!(
+            multi_tree
+                .keys("ingested".to_string(), None, 1000)
+                .unwrap()
+                .items
+                .len(),
+            298
+        );
+
+        // the ingested branches are the ones `update_all` writes, so later updates agree
+        for tree in ["ingested", "updated"] {
+            multi_tree
+                .update(tree.to_string(), key(7), value(7))
+                .unwrap();
+            multi_tree
+                .update(tree.to_string(), key(1), SMTValue::default())
+                .unwrap();
+        }
+        assert_eq!(
+            multi_tree.get_root("ingested".to_string()).unwrap(),
+            multi_tree.get_root("updated".to_string()).unwrap()
+        );
+
+        // only empty trees are ingested
+        assert!(multi_tree
+            .ingest(
+                "ingested".to_string(),
+                &mut kvs.clone().into_iter().map(Ok),
+                &mut |_| {}
+            )
+            .is_err());
+
+        // a failed ingest leaves an empty tree that can be ingested again
+        let mut failing = kvs
+            .clone()
+            .into_iter()
+            .map(Ok)
+            .chain([Err(Error::store("broken upload"))]);
+        assert!(multi_tree
+            .ingest("retried".to_string(), &mut failing, &mut |_| {})
+            .is_err());
+        assert_eq!(
+            multi_tree.get_root("retried".to_string()).unwrap(),
+            H256::zero()
+        );
+        let info = multi_tree
+            .ingest(
+                "retried".to_string(),
+                &mut kvs[..10].to_vec().into_iter().map(Ok),
+                &mut |_| {},
+            )
+            .unwrap();
+        assert_eq!(info.meta.leaf_count, 10);
+        assert_eq!(
+            multi_tree
+                .keys("retried".to_string(), None, 1000)
+                .unwrap()
+                .items
+                .len(),
+            10
+        );
+    }
+
+    #[test]
+    fn test_next_root_batch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap();
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+        let root = multi_tree
+            .update_all(tree.clone(), (1..10).map(|i| (key(i), value(i))).collect())
+            .unwrap();
+        assert_eq!(multi_tree.next_root(tree.clone(), vec![]).unwrap(), root);
+
+        // inserted, updated, removed and repeated keys
+        let kvs = vec![
+            (key(20), value(20)),
+            (key(2), value(200)),
+            (key(3), SMTValue::default()),
+            (key(20), value(21)),
+        ];
+        let next_root = multi_tree.next_root(tree.clone(), kvs.clone()).unwrap();
+        assert_ne!(next_root, root);
+        // nothing is written
+        assert_eq!(multi_tree.get_root(tree.clone()).unwrap(), root);
+        assert_eq!(multi_tree.update_all(tree.clone(), kvs).unwrap(), next_root);
+    }
+
+    #[test]
+    fn test_session() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap();
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+        let root = multi_tree
+            .update_all(tree.clone(), (1..10).map(|i| (key(i), value(i))).collect())
+            .unwrap();
+
+        // several rounds of staged updates, the committed tree is unchanged
+        let session = multi_tree.begin_session(tree.clone()).unwrap();
+        multi_tree
+            .session_update(session, vec![(key(20), value(20)), (key(2), value(200))])
+            .unwrap();
+        let staged_root = multi_tree
+            .session_update(session, vec![(key(3), SMTValue::default())])
+            .unwrap();
+        assert_eq!(multi_tree.get_root(tree.clone()).unwrap(), root);
+        assert_eq!(
+            multi_tree.get_value(tree.clone(), key(2)).unwrap(),
+            value(2)
+        );
+        assert_eq!(
+            multi_tree.session_value(session, key(2)).unwrap(),
+            value(200)
+        );
+        assert_eq!(
+            multi_tree.session_value(session, key(3)).unwrap(),
+            SMTValue::default()
+        );
+        let proof = multi_tree.session_merkle_proof(session, key(20)).unwrap();
+        assert_eq!(proof.root, staged_root);
+        assert!(multi_tree.verify(proof));
+
+        // the staged root is the one of the same updates written directly
+        let expected = multi_tree
+            .next_root(
+                tree.clone(),
+                vec![
+                    (key(20), value(20)),
+                    (key(2), value(200)),
+                    (key(3), SMTValue::default()),
+                ],
+            )
+            .unwrap();
+        assert_eq!(staged_root, expected);
+        assert_eq!(multi_tree.commit_session(session).unwrap(), staged_root);
+        assert_eq!(multi_tree.get_root(tree.clone()).unwrap(), staged_root);
+        let meta = multi_tree.tree_meta(tree.clone()).unwrap().unwrap();
+        assert_eq!(meta.leaf_count, 9);
+        assert_eq!(multi_tree.root_history(tree.clone()).unwrap()[1].root, root);
+        // a closed session can't be used
+        assert!(multi_tree.session_value(session, key(2)).is_err());
+        assert!(multi_tree.commit_session(session).is_err());
+
+        // rollback
+        let session = multi_tree.begin_session(tree.clone()).unwrap();
+        multi_tree
+            .session_update(session, vec![(key(30), value(30))])
+            .unwrap();
+        multi_tree.rollback_session(session).unwrap();
+        assert!(multi_tree.rollback_session(session).is_err());
+        assert_eq!(multi_tree.get_root(tree.clone()).unwrap(), staged_root);
+
+        // a session of a tree that changed in the meantime can't be used or committed
+        let session = multi_tree.begin_session(tree.clone()).unwrap();
+        multi_tree
+            .session_update(session, vec![(key(40), value(40))])
+            .unwrap();
+        let other = multi_tree.begin_session(tree.clone()).unwrap();
+        assert_ne!(other, session);
+        let root = multi_tree.update(tree.clone(), key(41), value(41)).unwrap();
+        assert!(multi_tree.session_value(session, key(40)).is_err());
+        assert!(multi_tree.session_merkle_proof(session, key(2)).is_err());
+        assert!(multi_tree
+            .session_update(session, vec![(key(42), value(42))])
+            .is_err());
+        assert!(multi_tree.commit_session(session).is_err());
+        multi_tree.rollback_session(other).unwrap();
+        assert_eq!(multi_tree.get_root(tree).unwrap(), root);
+    }
+
+    #[test]
+    fn test_open_legacy() {
+        // a database of the layout with one column per tree
+        let temp_dir = tempdir().unwrap();
+        let legacy = ParityDb::new(temp_dir.path(), 20).unwrap();
+        legacy.insert(3, &[1u8; 32], b"leaf").unwrap();
+        drop(legacy);
+
+        let err = MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("20 columns"));
+
+        // the database is left as it is
+        let legacy = ParityDb::new(temp_dir.path(), 20).unwrap();
+        assert_eq!(legacy.get(3, &[1u8; 32]).unwrap(), Some(b"leaf".to_vec()));
+
+        // a database of the current layout opens again
+        let temp_dir = tempdir().unwrap();
+        drop(
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+                .unwrap(),
+        );
+        assert!(
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).is_ok()
+        );
     }
 }
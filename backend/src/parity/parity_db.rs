@@ -1,16 +1,18 @@
 use parity_db::{clear_column, Db, Options};
-use std::{fmt, path::PathBuf};
+use std::{fmt, io, path::PathBuf};
 
 pub struct ParityDb {
     db: Option<Db>,
     path: PathBuf,
     num_columns: u8,
+    options: Options,
 }
 
 #[derive(Debug)]
 pub enum StoreError {
     DbError(parity_db::Error),
     InvalidColumnId,
+    Closed,
 }
 
 // Display trait
@@ -19,10 +21,19 @@ impl fmt::Display for StoreError {
         match self {
             StoreError::DbError(e) => write!(f, "Database error: {:?}", e),
             StoreError::InvalidColumnId => write!(f, "Invalid column ID"),
+            StoreError::Closed => write!(f, "Database is closed"),
         }
     }
 }
 
+impl std::error::Error for StoreError {}
+
+impl From<StoreError> for io::Error {
+    fn from(error: StoreError) -> Self {
+        io::Error::other(error)
+    }
+}
+
 impl From<parity_db::Error> for StoreError {
     fn from(error: parity_db::Error) -> Self {
         StoreError::DbError(error)
@@ -31,29 +42,31 @@ impl From<parity_db::Error> for StoreError {
 
 impl ParityDb {
     /// create a new ParityDb instance
-    pub fn new(path: impl Into<PathBuf> + Clone, num_columns: u8) -> Self {
-        let options = Options::with_columns(&path.clone().into(), num_columns);
-        let db = Db::open_or_create(&options).unwrap();
-        Self {
-            path: path.into(),
-            num_columns,
-            db: Some(db),
-        }
+    pub fn new(path: impl Into<PathBuf> + Clone, num_columns: u8) -> Result<Self, StoreError> {
+        let path: PathBuf = path.into();
+        Self::with_options(Options::with_columns(&path, num_columns))
     }
 
-    fn ensure_db_exsist(&mut self) -> Result<(), StoreError> {
-        if self.db.is_none() {
-            let db = self.open_or_create()?;
-            self.db = Some(db);
-        }
+    /// create a new ParityDb instance with custom options, e.g. btree indexed columns
+    /// Fails if the database exists with other columns
+    pub fn with_options(options: Options) -> Result<Self, StoreError> {
+        let db = Db::open_or_create(&options)?;
+        Ok(Self {
+            path: options.path.clone(),
+            num_columns: options.columns.len() as u8,
+            db: Some(db),
+            options,
+        })
+    }
 
-        Ok(())
+    /// The open database, all operations only need `&self`, so it can be shared by concurrent readers and writers
+    fn db(&self) -> Result<&Db, StoreError> {
+        self.db.as_ref().ok_or(StoreError::Closed)
     }
 
     /// Opens an existing database or creates a new one if it doesn't exist
     pub fn open_or_create(&self) -> Result<Db, StoreError> {
-        let options = Options::with_columns(&self.path, self.num_columns);
-        let db = Db::open_or_create(&options)?;
+        let db = Db::open_or_create(&self.options)?;
         Ok(db)
     }
 
@@ -65,29 +78,105 @@ impl ParityDb {
     }
 
     /// Insert a value into the specified column
-    pub fn insert(&mut self, column: u8, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+    pub fn insert(&self, column: u8, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
         self.check_column(column)?;
-        self.ensure_db_exsist()?;
-        // let db = self.open_or_create()?;
-        self.db.as_mut().unwrap().commit(vec![(column, key.to_vec(), Some(value.to_vec()))])?;
+        self.db()?.commit(vec![(column, key.to_vec(), Some(value.to_vec()))])?;
         Ok(())
     }
 
     /// Delete a value from the specified column
-    pub fn delete(&mut self, column: u8, key: &[u8]) -> Result<(), StoreError> {
+    pub fn delete(&self, column: u8, key: &[u8]) -> Result<(), StoreError> {
         self.check_column(column)?;
-        self.ensure_db_exsist()?;
-        // let db = self.open_or_create()?;
-        self.db.as_mut().unwrap().commit(vec![(column, key.to_vec(), None)])?;
+        self.db()?.commit(vec![(column, key.to_vec(), None)])?;
+        Ok(())
+    }
+
+    /// Write multiple changes in one commit, `None` deletes the key
+    /// Either all changes are written or none of them
+    pub fn commit(
+        &self,
+        changes: Vec<(u8, Vec<u8>, Option<Vec<u8>>)>,
+    ) -> Result<(), StoreError> {
+        for (column, _, _) in changes.iter() {
+            self.check_column(*column)?;
+        }
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.db()?.commit(changes)?;
         Ok(())
     }
 
     /// Get a value from the specified column
-    pub fn get(&mut self, column: u8, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+    pub fn get(&self, column: u8, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        self.check_column(column)?;
+        Ok(self.db()?.get(column, key)?)
+    }
+
+    /// Get all key-value pairs whose key starts with `prefix`, ordered by key
+    /// The column must be btree indexed
+    pub fn iter_prefix(
+        &self,
+        column: u8,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, StoreError> {
+        self.iter_prefix_from(column, prefix, prefix, usize::MAX, |_| true)
+    }
+
+    /// Get at most `limit` key-value pairs accepted by `keep` whose key starts with `prefix` and is not less than `from`, ordered by key
+    /// The column must be btree indexed
+    pub fn iter_prefix_from(
+        &self,
+        column: u8,
+        prefix: &[u8],
+        from: &[u8],
+        limit: usize,
+        keep: impl Fn(&[u8]) -> bool,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, StoreError> {
+        self.check_column(column)?;
+        let mut iter = self.db()?.iter(column)?;
+        iter.seek(from.max(prefix))?;
+        let mut kvs = vec![];
+        while kvs.len() < limit {
+            match iter.next()? {
+                Some((key, value)) if key.starts_with(prefix) => {
+                    if keep(&key) {
+                        kvs.push((key, value))
+                    }
+                }
+                _ => break,
+            }
+        }
+        Ok(kvs)
+    }
+
+    /// Iterate the key-value pairs whose key starts with `prefix`, ordered by key, without collecting them
+    /// The column must be btree indexed
+    pub fn prefix_iter<'a>(
+        &'a self,
+        column: u8,
+        prefix: &'a [u8],
+    ) -> Result<impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), StoreError>> + 'a, StoreError> {
         self.check_column(column)?;
-        self.ensure_db_exsist()?;
-        // let db = self.open_or_create()?;
-        Ok(self.db.as_mut().unwrap().get(column, key)?)
+        let mut iter = self.db()?.iter(column)?;
+        iter.seek(prefix)?;
+        let mut done = false;
+        Ok(std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            match iter.next() {
+                Ok(Some((key, value))) if key.starts_with(prefix) => Some(Ok((key, value))),
+                Ok(_) => {
+                    done = true;
+                    None
+                }
+                Err(e) => {
+                    done = true;
+                    Some(Err(e.into()))
+                }
+            }
+        }))
     }
 
     /// Delete the entire database by removing all files
@@ -108,11 +197,12 @@ impl ParityDb {
         Ok(())
     }
 
-    /// Clear all data in a column without recreating it
+    /// Clear all data in a column without recreating it, the database is reopened afterwards
     pub fn clear_column(&mut self, column: u8) -> Result<(), StoreError> {
         self.check_column(column)?;
         self.ensure_closed()?;
         clear_column(&self.path, column)?;
+        self.db = Some(self.open_or_create()?);
         Ok(())
     }
 }
@@ -125,7 +215,7 @@ mod tests {
     #[test]
     fn test_basic_operations() {
         let temp_dir = tempdir().unwrap();
-        let mut store = ParityDb::new(temp_dir.path(), 2);
+        let store = ParityDb::new(temp_dir.path(), 2).unwrap();
 
         // Test insert
         let key = b"test_key";
@@ -149,7 +239,7 @@ mod tests {
     #[test]
     fn test_reset_column() {
         let temp_dir = tempdir().unwrap();
-        let mut store = ParityDb::new(temp_dir.path(), 2);
+        let mut store = ParityDb::new(temp_dir.path(), 2).unwrap();
 
         // Insert data in both columns
         store.insert(0, b"key1", b"value1").unwrap();
@@ -166,7 +256,7 @@ mod tests {
     #[test]
     fn test_clear_column() {
         let temp_dir = tempdir().unwrap();
-        let mut store = ParityDb::new(temp_dir.path(), 2);
+        let mut store = ParityDb::new(temp_dir.path(), 2).unwrap();
 
         // Insert some test data
         for i in 0..100 {
@@ -187,10 +277,83 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_commit() {
+        let temp_dir = tempdir().unwrap();
+        let store = ParityDb::new(temp_dir.path(), 2).unwrap();
+        store.insert(0, b"key1", b"value1").unwrap();
+
+        store
+            .commit(vec![
+                (0, b"key1".to_vec(), None),
+                (0, b"key2".to_vec(), Some(b"value2".to_vec())),
+                (1, b"key3".to_vec(), Some(b"value3".to_vec())),
+            ])
+            .unwrap();
+        assert_eq!(store.get(0, b"key1").unwrap(), None);
+        assert_eq!(store.get(0, b"key2").unwrap(), Some(b"value2".to_vec()));
+        assert_eq!(store.get(1, b"key3").unwrap(), Some(b"value3".to_vec()));
+
+        // Nothing is written if one of the columns is invalid
+        let result = store.commit(vec![
+            (0, b"key4".to_vec(), Some(b"value4".to_vec())),
+            (2, b"key5".to_vec(), Some(b"value5".to_vec())),
+        ]);
+        assert!(result.is_err());
+        assert_eq!(store.get(0, b"key4").unwrap(), None);
+    }
+
+    #[test]
+    fn test_iter_prefix() {
+        let temp_dir = tempdir().unwrap();
+        let mut options = Options::with_columns(temp_dir.path(), 1);
+        options.columns[0].btree_index = true;
+        let store = ParityDb::with_options(options).unwrap();
+
+        store.insert(0, b"a1", b"value1").unwrap();
+        store.insert(0, b"ab", b"value2").unwrap();
+        store.insert(0, b"b1", b"value3").unwrap();
+        store.insert(0, b"a0", b"value4").unwrap();
+
+        assert_eq!(
+            store.iter_prefix(0, b"a").unwrap(),
+            vec![
+                (b"a0".to_vec(), b"value4".to_vec()),
+                (b"a1".to_vec(), b"value1".to_vec()),
+                (b"ab".to_vec(), b"value2".to_vec()),
+            ]
+        );
+        assert_eq!(store.iter_prefix(0, b"c").unwrap(), vec![]);
+        assert_eq!(
+            store
+                .prefix_iter(0, b"a")
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            store.iter_prefix(0, b"a").unwrap()
+        );
+        assert_eq!(store.iter_prefix(0, b"").unwrap().len(), 4);
+
+        // pages
+        assert_eq!(
+            store.iter_prefix_from(0, b"a", b"a1", 1, |_| true).unwrap(),
+            vec![(b"a1".to_vec(), b"value1".to_vec())]
+        );
+        assert_eq!(
+            store.iter_prefix_from(0, b"a", b"a10", 5, |_| true).unwrap(),
+            vec![(b"ab".to_vec(), b"value2".to_vec())]
+        );
+        assert_eq!(store.iter_prefix_from(0, b"a", b"", 5, |_| true).unwrap().len(), 3);
+        assert_eq!(
+            store.iter_prefix_from(0, b"a", b"", 5, |key| key != b"a1").unwrap().len(),
+            2
+        );
+    }
+
     #[test]
     fn test_column_bounds() {
         let temp_dir = tempdir().unwrap();
-        let mut store = ParityDb::new(temp_dir.path(), 2);
+        let store = ParityDb::new(temp_dir.path(), 2).unwrap();
 
         // Test inserting to invalid column
         let result = store.insert(2, b"key", b"value");
@@ -1,88 +1,127 @@
-use codec::{Decode, Encode};
-use sparse_merkle_tree::{
-    error::Error,
-    traits::{StoreReadOps, StoreWriteOps, Value},
-    BranchKey, BranchNode, H256,
+use parity_db::Options;
+use std::{io, path::Path};
+
+use crate::{
+    kv::{Change, Column, KvDb, KvIter},
+    parity_db::ParityDb,
+    tree_store::TreeStore,
 };
-use std::sync::Arc;
 
-use crate::parity_db::ParityDb;
-use std::sync::Mutex;
+pub use crate::kv::tree_prefix;
 
-pub struct SMTParityStore {
-    inner: Arc<Mutex<ParityDb>>,
-    col: u8,
-}
+/// The column of the tree nodes, btree indexed so that the nodes of a tree can be iterated
+pub const COL_NODES: u8 = 0;
+/// The column of the tree registry, keyed by the tree prefix
+pub const COL_TREES: u8 = 1;
+/// The number of columns of the database
+pub const NUM_COLUMNS: u8 = 2;
+
+/// The nodes of one tree in ParityDB, see [`TreeStore`]
+pub type SMTParityStore = TreeStore<ParityDb>;
 
-impl SMTParityStore {
-    pub fn new(db: Arc<Mutex<ParityDb>>, col: u8) -> Self {
-        SMTParityStore { inner: db, col }
+/// The ParityDB column of a column of the stores
+fn column(col: Column) -> u8 {
+    match col {
+        Column::Nodes => COL_NODES,
+        Column::Trees => COL_TREES,
     }
 }
 
-impl<V> StoreWriteOps<V> for SMTParityStore
-where
-    V: Value + Into<Vec<u8>>,
-{
-    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
-        self.inner.lock().unwrap()
-            .insert(self.col, &node_key.encode(), &branch.encode())
-            .map_err(|e| Error::Store(e.to_string()))
+impl KvDb for ParityDb {
+    /// Open the database with all columns btree indexed, so the number of trees is unlimited
+    /// A database of an older version (one column per tree) is refused, see [`check_layout`]
+    fn open(path: &Path) -> io::Result<Self> {
+        check_layout(path)?;
+        let mut options = Options::with_columns(path, NUM_COLUMNS);
+        for column in options.columns.iter_mut() {
+            column.btree_index = true;
+        }
+        Ok(ParityDb::with_options(options)?)
     }
 
-    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
-        self.inner.lock().unwrap()
-            .insert(self.col, &leaf_key.encode(), &leaf.into())
-            .map_err(|e| Error::Store(e.to_string()))
+    fn get(&self, col: Column, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(ParityDb::get(self, column(col), key)?)
     }
 
-    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
-        self.inner.lock().unwrap()
-            .delete(self.col, &node_key.encode())
-            .map_err(|e| Error::Store(e.to_string()))
+    fn iter<'a>(&'a self, col: Column, prefix: &'a [u8]) -> io::Result<KvIter<'a>> {
+        let iter = self.prefix_iter(column(col), prefix)?;
+        Ok(Box::new(iter.map(|kv| kv.map_err(io::Error::from))))
     }
 
-    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
-        self.inner.lock().unwrap()
-            .delete(self.col, &leaf_key.encode())
-            .map_err(|e| Error::Store(e.to_string()))
+    fn iter_from(
+        &self,
+        col: Column,
+        prefix: &[u8],
+        start: &[u8],
+        limit: usize,
+        keep: impl Fn(&[u8]) -> bool,
+    ) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self.iter_prefix_from(column(col), prefix, start, limit, keep)?)
     }
-}
 
-impl<V> StoreReadOps<V> for SMTParityStore
-where
-    V: Value + From<Vec<u8>>,
-{
-    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
-        self.inner.lock().unwrap()
-            .get(self.col, &branch_key.encode())
-            .map_err(|e| Error::Store(e.to_string()))?
-            .map(|v| BranchNode::decode(&mut v.as_slice()).unwrap())
-            .map_or(Ok(None), |v| Ok(Some(v)))
+    /// ParityDB cannot delete a range, the keys under a deleted prefix are read and deleted one by one
+    fn commit(&self, changes: Vec<Change>) -> io::Result<()> {
+        let mut commit = vec![];
+        for change in changes {
+            match change {
+                Change::Put(col, key, value) => commit.push((column(col), key, Some(value))),
+                Change::Delete(col, key) => commit.push((column(col), key, None)),
+                Change::DeletePrefix(col, prefix) => commit.extend(
+                    self.iter_prefix(column(col), &prefix)?
+                        .into_iter()
+                        .map(|(key, _)| (column(col), key, None)),
+                ),
+            }
+        }
+        Ok(ParityDb::commit(self, commit)?)
     }
+}
 
-    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
-        self.inner.lock().unwrap()
-            .get(self.col, &leaf_key.encode())
-            .map_err(|e| Error::Store(e.to_string()))?
-            .map(|v| v.into())
-            .map_or(Ok(None), |v| Ok(Some(v)))
+/// Refuse a database written by a version that kept each tree in its own hash indexed column
+/// (`--paritydb-columns`, 20 by default)
+/// ParityDB only stores salted hashes of the keys of such columns, so the leaf and branch keys cannot be
+/// read back and the trees cannot be migrated, they have to be rebuilt from their data with `/ingest`
+fn check_layout(path: &Path) -> io::Result<()> {
+    let Some(metadata) =
+        Options::load_metadata(path).map_err(|e| io::Error::other(e.to_string()))?
+    else {
+        return Ok(());
+    };
+    let columns = metadata.columns;
+    if columns.len() == NUM_COLUMNS as usize && columns.iter().all(|c| c.btree_index) {
+        return Ok(());
     }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "ParityDB at {} has {} columns and was written by an older version with one column per tree, \
+             its trees cannot be migrated, rebuild them in a new database",
+            path.display(),
+            columns.len()
+        ),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{history, registry::TreeMeta};
+    use codec::{Decode, Encode};
     use smt_primitives::kv::SMTValue;
-    use sparse_merkle_tree::merge::MergeValue;
+    use sparse_merkle_tree::{
+        merge::MergeValue,
+        traits::{StoreReadOps, StoreWriteOps},
+        BranchKey, BranchNode, H256,
+    };
+    use std::sync::Arc;
     use tempfile::tempdir;
 
     #[test]
     fn test_store() {
         // 创建数据库实例
         let temp_dir = tempdir().unwrap();
-        let db = Arc::new(Mutex::new(ParityDb::new(temp_dir.path(), 2)));
-        let mut store = SMTParityStore::new(db, 0);
+        let db = Arc::new(ParityDb::new(temp_dir.path(), NUM_COLUMNS).unwrap());
+        let mut store = SMTParityStore::new(db, "test");
 
         // 插入叶子
         let leaf1_key: H256 = [1u8; 32].to_vec().into();
@@ -122,4 +161,74 @@ mod tests {
             None::<BranchNode>
         );
     }
+
+    #[test]
+    fn test_commit() {
+        let temp_dir = tempdir().unwrap();
+        let db = Arc::new(ParityDb::new(temp_dir.path(), NUM_COLUMNS).unwrap());
+        let mut store = SMTParityStore::new(db.clone(), "test");
+        let other = SMTParityStore::new(db.clone(), "test");
+
+        let leaf1_key: H256 = [1u8; 32].to_vec().into();
+        let leaf1 = SMTValue {
+            nonce: 1,
+            balance: 99,
+        };
+        let node1_key: BranchKey = BranchKey::new(100, [2u8; 32].into());
+        let node1: BranchNode = BranchNode {
+            left: MergeValue::from_h256([3u8; 32].into()),
+            right: MergeValue::from_h256([4u8; 32].into()),
+        };
+        store.insert_leaf(leaf1_key, leaf1.clone()).unwrap();
+        <SMTParityStore as StoreWriteOps<SMTValue>>::insert_branch(
+            &mut store,
+            node1_key.clone(),
+            node1.clone(),
+        )
+        .unwrap();
+
+        // nothing is written before the commit
+        assert_eq!(other.get_leaf(&leaf1_key).unwrap(), None::<SMTValue>);
+        assert_eq!(
+            <SMTParityStore as StoreReadOps<SMTValue>>::get_branch(&other, &node1_key).unwrap(),
+            None::<BranchNode>
+        );
+
+        assert_eq!(store.leaf_delta(), 1);
+
+        store.commit().unwrap();
+        assert_eq!(store.leaf_delta(), 0);
+        assert_eq!(other.get_leaf(&leaf1_key).unwrap(), Some(leaf1.clone()));
+        assert_eq!(
+            <SMTParityStore as StoreReadOps<SMTValue>>::get_branch(&other, &node1_key).unwrap(),
+            Some(node1)
+        );
+
+        // the registry entry is committed with the nodes
+        <SMTParityStore as StoreWriteOps<SMTValue>>::remove_leaf(&mut store, &leaf1_key).unwrap();
+        assert_eq!(store.leaf_delta(), -1);
+        let meta = TreeMeta::new("test", "keccak256");
+        store.commit_version(&meta, H256::zero(), 1).unwrap();
+        assert_eq!(other.get_leaf(&leaf1_key).unwrap(), None::<SMTValue>);
+        let value = db.get(COL_TREES, &tree_prefix("test")).unwrap().unwrap();
+        assert_eq!(TreeMeta::decode(&mut value.as_slice()).unwrap(), meta);
+
+        // removed leaves are recorded in the undo log of the new version
+        let prefix = tree_prefix("test");
+        let version = history::current_version(&prefix, |key| Ok(db.get(COL_NODES, key).unwrap()));
+        assert_eq!(version.unwrap(), 1);
+        let entry = db
+            .get(COL_NODES, &history::history_key(&prefix, 1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            history::HistoryEntry::decode(&mut entry.as_slice())
+                .unwrap()
+                .undo,
+            vec![(
+                [prefix.as_slice(), &leaf1_key.encode()].concat(),
+                Some(Vec::<u8>::from(leaf1))
+            )]
+        );
+    }
 }
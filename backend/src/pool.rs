@@ -0,0 +1,169 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! An async facade over a store: store operations run on a dedicated pool of threads, so they never block
+//! the executor threads of the server.  
+//! The operations waiting for a thread are bounded, when the queue is full an operation is rejected at once
+//! with [`PoolError::Busy`] (a 503 of the server) instead of stalling the callers.
+
+use std::{
+    future::Future,
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+};
+use thiserror::Error as ThisError;
+use tokio::sync::oneshot;
+
+/// The default number of store operations that may wait for a thread.
+pub const DEFAULT_QUEUE_SIZE: usize = 1024;
+
+/// An operation queued for the pool.
+type Job<S> = Box<dyn FnOnce(&S) + Send>;
+
+/// Why an operation did not run.
+#[derive(Debug, ThisError, PartialEq, Eq)]
+pub enum PoolError {
+    #[error("The store is busy, retry later")]
+    Busy,
+
+    #[error("The store operation panicked")]
+    Panicked,
+}
+
+/// The default number of threads of a pool, one per core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// A store shared by a pool of threads that run its operations.
+/// The threads stop once the pool is dropped and the queued operations have run.
+pub struct StorePool<S> {
+    store: Arc<S>,
+    queue: SyncSender<Job<S>>,
+}
+
+impl<S: Send + Sync + 'static> StorePool<S> {
+    /// Start `threads` threads for `store`, at most `queue_size` operations wait for one of them.
+    pub fn new(store: S, threads: usize, queue_size: usize) -> io::Result<Self> {
+        let store = Arc::new(store);
+        let (queue, jobs) = mpsc::sync_channel::<Job<S>>(queue_size);
+        let jobs = Arc::new(Mutex::new(jobs));
+        for i in 0..threads.max(1) {
+            let (store, jobs) = (store.clone(), jobs.clone());
+            thread::Builder::new()
+                .name(format!("smt-store-{}", i))
+                .spawn(move || work(&store, &jobs))?;
+        }
+        Ok(StorePool { store, queue })
+    }
+
+    /// Run `f` on a thread of the pool.  
+    /// `f` is queued when this is called, not when the future is first polled, and it runs to the end
+    /// even if the future is dropped.
+    pub fn run<R, F>(&self, f: F) -> impl Future<Output = Result<R, PoolError>>
+    where
+        F: FnOnce(&S) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let queued = self
+            .queue
+            .try_send(Box::new(move |store: &S| {
+                // the caller may be gone, the result is dropped then
+                let _ = tx.send(f(store));
+            }))
+            .map_err(|e| match e {
+                TrySendError::Full(_) => PoolError::Busy,
+                // the threads only stop when the pool is dropped
+                TrySendError::Disconnected(_) => PoolError::Panicked,
+            });
+        async move {
+            queued?;
+            rx.await.map_err(|_| PoolError::Panicked)
+        }
+    }
+
+    /// The store, for work that is done by the caller.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+/// Run the queued operations until the pool is dropped.
+fn work<S>(store: &S, jobs: &Mutex<Receiver<Job<S>>>) {
+    loop {
+        let job = jobs.lock().unwrap_or_else(PoisonError::into_inner).recv();
+        let Ok(job) = job else {
+            return;
+        };
+        // a panic drops the result sender, so the caller gets `PoolError::Panicked` and the thread goes on
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| job(store)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[tokio::test]
+    async fn test_run() {
+        let pool = StorePool::new(vec![1, 2, 3], 2, 4).unwrap();
+        assert_eq!(pool.run(|v| v.iter().sum::<i32>()).await, Ok(6));
+
+        let results = futures_util::future::join_all((0..4).map(|i| pool.run(move |v| v[i % 3])));
+        assert_eq!(results.await, vec![Ok(1), Ok(2), Ok(3), Ok(1)]);
+        assert_eq!(pool.store().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_busy() {
+        let pool = StorePool::new((), 1, 1).unwrap();
+        let (started_tx, started) = channel();
+        let (release, release_rx) = channel::<()>();
+        let running = pool.run(move |_| {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            1
+        });
+        started.recv().unwrap();
+
+        // the thread is busy and the queue is full, so the next operation is rejected at once
+        let queued = pool.run(|_| 2);
+        assert_eq!(pool.run(|_| 3).await, Err(PoolError::Busy));
+
+        release.send(()).unwrap();
+        assert_eq!(running.await, Ok(1));
+        assert_eq!(queued.await, Ok(2));
+        assert_eq!(pool.run(|_| 4).await, Ok(4));
+    }
+
+    #[tokio::test]
+    async fn test_panic() {
+        let pool = StorePool::new((), 1, 1).unwrap();
+        assert_eq!(
+            pool.run(|_| -> i32 { panic!("store failure") }).await,
+            Err(PoolError::Panicked)
+        );
+        // the thread survives the panic
+        assert_eq!(pool.run(|_| 1).await, Ok(1));
+    }
+}
@@ -0,0 +1,155 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The registry of the trees in a store, mapping tree names to their metadata.
+
+use crate::{
+    error::{Error, ErrorCode},
+    hashers::HasherKind,
+};
+use codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use smt_primitives::{
+    blake2b_hasher::Blake2b256Hasher, keccak_hasher::Keccak256Hasher,
+    poseidon_hasher::PoseidonHasher, sha256_hasher::Sha256Hasher,
+};
+use sparse_merkle_tree::H256;
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+/// The name of a hasher, recorded in the metadata of the trees.
+pub trait HasherName {
+    const NAME: &'static str;
+    const KIND: HasherKind;
+}
+
+impl HasherName for Keccak256Hasher {
+    const NAME: &'static str = "keccak256";
+    const KIND: HasherKind = HasherKind::Keccak256;
+}
+
+impl HasherName for Blake2b256Hasher {
+    const NAME: &'static str = "blake2b256";
+    const KIND: HasherKind = HasherKind::Blake2b256;
+}
+
+impl HasherName for Sha256Hasher {
+    const NAME: &'static str = "sha256";
+    const KIND: HasherKind = HasherKind::Sha256;
+}
+
+impl HasherName for PoseidonHasher {
+    const NAME: &'static str = "poseidon-bn254";
+    const KIND: HasherKind = HasherKind::PoseidonBn254;
+}
+
+/// Metadata of a tree.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize, ToSchema)]
+pub struct TreeMeta {
+    /// The name of the tree.
+    pub name: String,
+    /// The hasher of the tree, see [`HasherName`].
+    pub hasher: String,
+    /// The number of non-empty leaves.
+    pub leaf_count: u64,
+    /// When the tree was created, in seconds since the Unix epoch.
+    pub created_at: u64,
+    /// When the tree was last updated, in seconds since the Unix epoch.
+    pub updated_at: u64,
+}
+
+impl TreeMeta {
+    /// The metadata of an empty tree created now.
+    pub fn new(name: impl Into<String>, hasher: impl Into<String>) -> Self {
+        let now = now();
+        Self {
+            name: name.into(),
+            hasher: hasher.into(),
+            leaf_count: 0,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Mark the tree as updated now, `leaf_delta` leaves were added (or removed if negative).
+    pub fn touch(&mut self, leaf_delta: i64) {
+        self.leaf_count = self.leaf_count.saturating_add_signed(leaf_delta);
+        self.updated_at = now();
+    }
+}
+
+/// Metadata and the current root of a tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TreeInfo {
+    #[serde(flatten)]
+    pub meta: TreeMeta,
+    /// The current root.
+    pub root: H256,
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The error of an operation on a tree that is not registered.
+pub fn unknown_tree(name: &str) -> Error {
+    ErrorCode::UnknownTree.store_error(format!("Unknown tree: {}", name))
+}
+
+/// The error of creating a tree that is already registered.
+pub fn tree_exists(name: &str) -> Error {
+    ErrorCode::Conflict.store_error(format!("Tree already exists: {}", name))
+}
+
+/// The error of an operation on a tree that is built with another hasher than the trees of the store.
+pub fn wrong_hasher(name: &str, hasher: &str, store: &str) -> Error {
+    ErrorCode::BadRequest.store_error(format!(
+        "Tree {} uses the hasher {}, but the store builds trees with {}",
+        name, hasher, store
+    ))
+}
+
+/// Decode the metadata of a tree as it is stored in the registry.
+pub fn decode_meta(value: &[u8]) -> Result<TreeMeta, Error> {
+    TreeMeta::decode(&mut &value[..]).map_err(|e| ErrorCode::Corruption.store_error(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_meta() {
+        let mut meta = TreeMeta::new("tree", Keccak256Hasher::NAME);
+        assert_eq!(meta.created_at, meta.updated_at);
+        assert_eq!(meta.leaf_count, 0);
+        meta.touch(3);
+        meta.touch(-1);
+        assert_eq!(meta.leaf_count, 2);
+        meta.touch(-5);
+        assert_eq!(meta.leaf_count, 0);
+        assert!(meta.updated_at >= meta.created_at);
+        assert_eq!(
+            TreeMeta::decode(&mut meta.encode().as_slice()).unwrap(),
+            meta
+        );
+    }
+}
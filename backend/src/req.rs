@@ -0,0 +1,138 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Request bodies of the HTTP APIs.  
+//! `T` is the type the backend identifies a tree by, i.e. the `String` name (`prefix`) of the tree.
+
+#![allow(unused_imports)]
+use crate::{hashers::HasherKind, session::SessionId};
+use serde::{self, Deserialize, Serialize};
+use smt_primitives::kv::{SMTKey, SMTValue};
+use sparse_merkle_tree::H256;
+use std::{fmt::Debug, marker::PhantomData};
+use utoipa::{IntoParams, ToSchema, __dev::ComposeSchema};
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqUpdate<T, K, V> {
+    pub prefix: T,
+    #[serde(flatten)]
+    pub key: K,
+    #[serde(flatten)]
+    pub value: V,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqByKey<T, K> {
+    pub prefix: T,
+    #[serde(flatten)]
+    pub key: K,
+}
+
+/// A key of a tree as it was when the tree had the given root.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqByKeyAt<T, K> {
+    pub prefix: T,
+    pub root: H256,
+    #[serde(flatten)]
+    pub key: K,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqByKeys<T, K> {
+    pub prefix: T,
+    pub keys: Vec<K>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqByKVs<T, KVPair> {
+    pub prefix: T,
+    #[serde(flatten)]
+    pub kv: KVPair,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct KVPair<K, V> {
+    #[serde(flatten)]
+    pub key: K,
+    #[serde(flatten)]
+    pub value: V,
+}
+
+/// Entries written to one tree at once, an entry with the default value deletes its key.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqUpdateBatch<T, K, V> {
+    pub prefix: T,
+    pub kvs: Vec<KVPair<K, V>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqByPrefix<T> {
+    pub prefix: T,
+}
+
+/// A tree to create, see [`crate::hashers`].
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqCreateTree<T> {
+    pub prefix: T,
+    /// The hasher of the tree, `keccak256` by default.
+    #[serde(default)]
+    pub hasher: HasherKind,
+}
+
+/// The hasher a proof is verified with, it is the hasher of the tree of the proof.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReqHasher {
+    /// `keccak256` by default.
+    #[serde(default)]
+    pub hasher: HasherKind,
+}
+
+/// A page of a listing of a tree, see [`crate::key_index::Page`].
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqPage<T> {
+    pub prefix: T,
+    /// The `next` of the previous page, omitted for the first page.
+    #[serde(default)]
+    pub start_after: Option<H256>,
+    /// The number of items of the page, 100 by default and at most 1000.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// A session of staged updates, see [`crate::session`].
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqSession {
+    #[schema(value_type = u64)]
+    pub session: SessionId,
+}
+
+/// A round of updates staged in a session, like [`ReqUpdateBatch`].
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqSessionUpdate<K, V> {
+    #[schema(value_type = u64)]
+    pub session: SessionId,
+    pub kvs: Vec<KVPair<K, V>>,
+}
+
+/// A key of the staged tree of a session.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReqSessionKey<K> {
+    #[schema(value_type = u64)]
+    pub session: SessionId,
+    #[serde(flatten)]
+    pub key: K,
+}
@@ -20,177 +20,56 @@
 #![allow(dead_code)]
 #![allow(unused_imports)]
 
-use crate::store::SMTStore;
+use crate::{
+    error::{Error, Result},
+    hashers::{HasherKind, Leaf, PerTreeHasher},
+    history::{self, unknown_root, RootVersion, DEFAULT_HISTORY_DEPTH},
+    ingest::{self, not_empty, Progress, DEFAULT_CHUNK_SIZE},
+    key_index::{decode_key, index_prefix, is_index_key, missing_leaf, page_start, Page},
+    locks::TreeLocks,
+    migration::open_database,
+    multi_store::MultiTreeStore,
+    registry::{
+        decode_meta, tree_exists, unknown_tree, wrong_hasher, HasherName, TreeInfo, TreeMeta,
+    },
+    session::{Session, SessionId, Sessions},
+    snapshot::{hasher_mismatch, read_snapshot},
+    store::{read_from, tree_prefix, SMTStore, COL_NODES, COL_TREES},
+    traits::{merkle_proof, MSS},
+};
+use codec::{Decode, Encode};
 use ethers::core::k256::sha2::digest::Key;
+use kvdb::KeyValueDB;
 use kvdb_rocksdb::Database;
 use smt_primitives::{
+    blake2b_hasher::Blake2b256Hasher,
     keccak_hasher::Keccak256Hasher,
-    verify::{verify as smt_verify, Proof},
+    kv::LeafHash,
+    poseidon_hasher::PoseidonHasher,
+    sha256_hasher::Sha256Hasher,
+    verify::{BatchLeaf, BatchProof, Proof},
 };
 use sparse_merkle_tree::{
     merge::MergeValue,
     traits::{Hasher, Value},
     SparseMerkleTree, H256,
 };
-use std::{fmt::Debug, io, marker::PhantomData, path::Path};
+use std::{
+    fmt::Debug,
+    io::{self, Read},
+    marker::PhantomData,
+    path::Path,
+};
 
 use scale_info::TypeInfo;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
-use sparse_merkle_tree::{
-    error::{Error, Result, Result as SMTResult},
-    CompiledMerkleProof,
-};
 use std::{convert::AsRef, sync::Arc};
 use utoipa::{ToSchema, __dev::ComposeSchema};
-// use crate::traits::MSS;
-
-type MultiSMT<V, H> = SparseMerkleTree<H, V, SMTStore>;
-
-/// Multiple Merkle trees are stored in a KV database.
-pub struct MultiSMTStore<K, V, H> {
-    store: Arc<Database>,
-    v: PhantomData<(K, V, H)>,
-}
-
-impl<K, V, H> MultiSMTStore<K, V, H>
-where
-    K: Value
-        + Clone
-        + Serialize
-        + ToSchema
-        + Deserialize<'static>
-        + ComposeSchema
-        + Debug
-        + TypeInfo,
-    V: Default
-        + Value
-        + Into<Vec<u8>>
-        + From<Vec<u8>>
-        + ToSchema
-        + Serialize
-        + Deserialize<'static>
-        + ComposeSchema
-        + PartialEq
-        + Clone
-        + Debug
-        + TypeInfo,
-    H: Hasher + Default,
-{
-    /// Open the KV database, create it if it does not exist.
-    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let db = Database::open(&Default::default(), path)?;
-        Ok(Self {
-            store: Arc::new(db),
-            v: PhantomData,
-        })
-    }
-
-    /// Create or open a new tree.
-    pub fn new_tree_with_store(&self, prefix: String) -> Result<MultiSMT<V, H>> {
-        let db = SMTStore::new(self.store.clone(), prefix);
-        MultiSMT::new_with_store(db)
-    }
-
-    /// Insert a value into a specific Merkle tree.
-    pub fn update(&self, prefix: String, key: K, value: V) -> SMTResult<H256> {
-        let mut tree = self.new_tree_with_store(prefix)?;
-        let h = tree.update(key.to_h256(), value)?;
-        Ok(*h)
-    }
-
-    /// Insert multiple values into a Merkle tree at once.
-    pub fn update_all(&self, prefix: String, kvs: Vec<(K, V)>) -> SMTResult<H256> {
-        let kvs = kvs
-            .into_iter()
-            .map(|(k, v)| Ok((k.to_h256(), v)))
-            .collect::<Result<Vec<(H256, V)>>>()?;
-
-        let mut tree = self.new_tree_with_store(prefix)?;
-        let root = tree.update_all(kvs)?;
-        Ok(*root)
-    }
-
-    /// Get the root hash.
-    pub fn get_root(&self, prefix: String) -> Result<H256> {
-        let tree = self.new_tree_with_store(prefix)?;
-        Ok(*tree.root())
-    }
-
-    /// Get the value of a specific key in a particular tree.
-    pub fn get_value(&self, prefix: String, key: K) -> Result<V> {
-        let tree = self.new_tree_with_store(prefix)?;
-        let value = tree.get(&key.to_h256())?;
-        Ok(value)
-    }
-
-    /// Get the Merkle proof.
-    pub fn get_merkle_proof(&self, prefix: String, key: K) -> Result<Proof<K, V>> {
-        let tree = self.new_tree_with_store(prefix.clone())?;
-        let proof = tree.merkle_proof(vec![key.to_h256()])?;
-        let leaves_bitmap = proof.leaves_bitmap();
-        let siblings = proof.merkle_path();
-        let leave_bitmap = leaves_bitmap[0];
-        let value = self.get_value(prefix, key.clone())?;
-
-        Ok(Proof {
-            key: key.clone(),
-            value: value.clone(),
-            path: key.to_h256(),
-            value_hash: value.to_h256(),
-            root: *tree.root(),
-            leave_bitmap,
-            siblings: siblings.clone(),
-        })
-    }
-
-    /// Get the Merkle proof, the return value is `Vec<u8>`, which is not developer-friendly and may be inefficient for on-chain gas or functionality.
-    pub fn get_merkle_proof_old(&self, prefix: String, keys: Vec<K>) -> SMTResult<Vec<u8>> {
-        let tree = self.new_tree_with_store(prefix)?;
-        let keys = keys
-            .into_iter()
-            .map(|k| Ok(k.to_h256()))
-            .collect::<Result<Vec<H256>>>()?;
-
-        let proof = tree.merkle_proof(keys.clone())?;
-        let proof = proof.compile(keys)?;
-        Ok(proof.0)
-    }
-
-    /// Before data is updated, the future value of the root hash can be calculated in advance.
-    pub fn get_next_root(&self, old_proof: Vec<u8>, next_kvs: Vec<(K, V)>) -> Result<H256> {
-        let p = CompiledMerkleProof(old_proof);
-        let kvs = next_kvs
-            .into_iter()
-            .map(|(k, v)| Ok((k.to_h256(), v.to_h256())))
-            .collect::<Result<Vec<(H256, H256)>>>()?;
-
-        let next_root = p.compute_root::<H>(kvs)?;
-        Ok(next_root)
-    }
-
-    /// Delete a specific Merkle tree.
-    pub fn clear(&self, prefix: String) {
-        let mut tx = self.store.transaction();
-        tx.delete_prefix(Default::default(), prefix.as_bytes());
-        self.store.write(tx).unwrap();
-    }
 
-    /// Verify the Merkle proof.
-    pub fn verify(&self, proof: Proof<K, V>) -> bool {
-        let mut res = false;
-        if proof.value != V::default() {
-            res = smt_verify::<H>(
-                proof.path,
-                proof.value_hash,
-                proof.leave_bitmap,
-                proof.siblings,
-                proof.root,
-            )
-        }
-        res
-    }
-}
+/// Multiple Merkle trees are stored in a RocksDB database, see [`MultiTreeStore`].  
+/// A database written by an older version is migrated to the current key layout when it is opened.
+pub type MultiSMTStore<K, V, H> = MultiTreeStore<K, V, H, Database>;
 
 #[cfg(test)]
 pub mod test {
@@ -207,8 +86,8 @@ pub mod test {
 
         let tree1: &str = "tree1";
         let tree2: &str = "tree2";
-        multi_tree.clear(tree1.to_string());
-        multi_tree.clear(tree2.to_string());
+        multi_tree.clear(tree1.to_string()).unwrap();
+        multi_tree.clear(tree2.to_string()).unwrap();
         multi_tree.new_tree_with_store(tree1.to_string()).unwrap();
         multi_tree.new_tree_with_store(tree2.to_string()).unwrap();
 
@@ -315,7 +194,7 @@ pub mod test {
         assert_eq!(multi_tree.verify(tree2_proof1), true);
 
         // clear
-        multi_tree.clear(tree1.to_string());
+        multi_tree.clear(tree1.to_string()).unwrap();
         assert_eq!(
             multi_tree
                 .get_value(tree1.to_string(), tree1_key2.clone())
@@ -328,7 +207,7 @@ pub mod test {
                 .unwrap(),
             tree1_value2.clone()
         );
-        multi_tree.clear(tree2.to_string());
+        multi_tree.clear(tree2.to_string()).unwrap();
         assert_eq!(
             multi_tree
                 .get_value(tree2.to_string(), tree1_key2.clone())
@@ -369,7 +248,7 @@ pub mod test {
             assert_eq!(multi_tree.verify(p), true);
         }
 
-        multi_tree.clear(tree1.to_string());
+        multi_tree.clear(tree1.to_string()).unwrap();
         for kv in kvs.clone() {
             multi_tree
                 .update_all(tree1.to_string(), vec![(kv.0.clone(), kv.1.clone())])
@@ -387,4 +266,652 @@ pub mod test {
             .update_all(tree1.to_string(), kvs.clone())
             .unwrap();
     }
+
+    #[test]
+    fn test_non_membership_proof() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        let tree = "tree".to_string();
+        let key1 = SMTKey {
+            address: "1".to_string(),
+        };
+        let key2 = SMTKey {
+            address: "2".to_string(),
+        };
+
+        // empty tree
+        let proof = multi_tree
+            .get_merkle_proof(tree.clone(), key1.clone())
+            .unwrap();
+        assert!(multi_tree.verify_non_membership(proof.clone()));
+        assert!(!multi_tree.verify(proof));
+
+        multi_tree
+            .update(
+                tree.clone(),
+                key1.clone(),
+                SMTValue {
+                    nonce: 1,
+                    balance: 99,
+                },
+            )
+            .unwrap();
+
+        let proof = multi_tree
+            .get_merkle_proof(tree.clone(), key2.clone())
+            .unwrap();
+        assert_eq!(proof.value, SMTValue::default());
+        assert!(proof.value_hash.is_zero());
+        assert!(multi_tree.verify_non_membership(proof));
+
+        let proof = multi_tree
+            .get_merkle_proof(tree.clone(), key1.clone())
+            .unwrap();
+        assert!(!multi_tree.verify_non_membership(proof.clone()));
+        assert!(multi_tree.verify(proof));
+
+        // a removed key is absent again
+        multi_tree
+            .update(tree.clone(), key1.clone(), SMTValue::default())
+            .unwrap();
+        let proof = multi_tree.get_merkle_proof(tree, key1).unwrap();
+        assert!(multi_tree.verify_non_membership(proof));
+    }
+
+    #[test]
+    fn test_batch_merkle_proof() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let kvs: Vec<(SMTKey, SMTValue)> = (1..50)
+            .map(|i| {
+                (
+                    key(i),
+                    SMTValue {
+                        nonce: i,
+                        balance: i as u128,
+                    },
+                )
+            })
+            .collect();
+        let root = multi_tree.update_all(tree.clone(), kvs).unwrap();
+
+        let keys = vec![key(3), key(1), key(49), key(3), key(100)];
+        let proof = multi_tree
+            .get_batch_merkle_proof(tree.clone(), keys)
+            .unwrap();
+        assert_eq!(proof.root, root);
+        assert_eq!(proof.leaves.len(), 4);
+        assert_eq!(proof.leaves_bitmap.len(), 4);
+        assert!(proof.leaves.windows(2).all(|w| w[0].path < w[1].path));
+        let absent = proof
+            .leaves
+            .iter()
+            .find(|leaf| leaf.key == key(100))
+            .unwrap();
+        assert!(absent.value_hash.is_zero());
+        assert!(multi_tree.verify_batch(proof.clone()));
+
+        // tamper with a value
+        let mut wrong = proof.clone();
+        wrong.leaves[0].value_hash = SMTValue {
+            nonce: 1000,
+            balance: 1000,
+        }
+        .to_h256();
+        assert!(!multi_tree.verify_batch(wrong));
+
+        // against another root
+        multi_tree
+            .update(tree.clone(), key(2), SMTValue::default())
+            .unwrap();
+        let mut stale = proof;
+        stale.root = multi_tree.get_root(tree.clone()).unwrap();
+        assert!(!multi_tree.verify_batch(stale));
+    }
+
+    #[test]
+    fn test_uncommitted_update() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+        let root = multi_tree
+            .update_all(tree.clone(), (1..10).map(|i| (key(i), value(i))).collect())
+            .unwrap();
+
+        // an update that never reaches its commit (e.g. the process is killed) changes nothing
+        let mut smt = multi_tree.new_tree_with_store(tree.clone()).unwrap();
+        smt.update(key(100).to_h256(), Leaf::new(value(100)))
+            .unwrap();
+        smt.update(key(1).to_h256(), Leaf::new(SMTValue::default()))
+            .unwrap();
+        assert_ne!(*smt.root(), root);
+        drop(smt);
+        assert_eq!(multi_tree.get_root(tree.clone()).unwrap(), root);
+        assert_eq!(
+            multi_tree.get_value(tree.clone(), key(1)).unwrap(),
+            value(1)
+        );
+        assert_eq!(
+            multi_tree.get_value(tree.clone(), key(100)).unwrap(),
+            SMTValue::default()
+        );
+        let proof = multi_tree.get_merkle_proof(tree.clone(), key(5)).unwrap();
+        assert!(multi_tree.verify(proof));
+
+        // the same update through the store is committed
+        let new_root = multi_tree
+            .update(tree.clone(), key(100), value(100))
+            .unwrap();
+        assert_eq!(multi_tree.get_root(tree).unwrap(), new_root);
+    }
+
+    #[test]
+    fn test_overlapping_tree_names() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        let isolated_dir = tempfile::tempdir().unwrap();
+        let isolated =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(isolated_dir.path()).unwrap();
+        let kvs = |n: u64| {
+            (0..20)
+                .map(|i| {
+                    (
+                        SMTKey {
+                            address: (i * n).to_string(),
+                        },
+                        SMTValue {
+                            nonce: i,
+                            balance: n as u128,
+                        },
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // "a" is a prefix of "ab", and the height of a branch node of "a" is the byte 'b'
+        let names = ["a", "ab", "a\u{0}"];
+        for (n, name) in names.iter().enumerate() {
+            multi_tree
+                .update_all(name.to_string(), kvs(n as u64 + 1))
+                .unwrap();
+        }
+        for (n, name) in names.iter().enumerate() {
+            let root = isolated
+                .update_all(name.to_string(), kvs(n as u64 + 1))
+                .unwrap();
+            assert_eq!(multi_tree.get_root(name.to_string()).unwrap(), root);
+        }
+
+        // clearing "a" leaves the other trees untouched
+        let root_ab = multi_tree.get_root("ab".to_string()).unwrap();
+        multi_tree.clear("a".to_string()).unwrap();
+        assert_eq!(multi_tree.get_root("a".to_string()).unwrap(), H256::zero());
+        assert_eq!(multi_tree.get_root("ab".to_string()).unwrap(), root_ab);
+        for (key, value) in kvs(2) {
+            assert_eq!(
+                multi_tree.get_value("ab".to_string(), key.clone()).unwrap(),
+                value
+            );
+            let proof = multi_tree.get_merkle_proof("ab".to_string(), key).unwrap();
+            assert!(multi_tree.verify(proof));
+        }
+    }
+
+    #[test]
+    fn test_tree_registry() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree = MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+            .unwrap()
+            .with_implicit_create(false);
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+
+        // the tree must be created first
+        assert!(multi_tree.update(tree.clone(), key(1), value(1)).is_err());
+        assert_eq!(multi_tree.describe_tree(tree.clone()).unwrap(), None);
+        let meta = multi_tree
+            .create_tree(tree.clone(), HasherKind::Keccak256)
+            .unwrap();
+        assert_eq!(meta.hasher, Keccak256Hasher::NAME);
+        assert_eq!(meta.leaf_count, 0);
+        assert!(multi_tree
+            .create_tree(tree.clone(), HasherKind::Keccak256)
+            .is_err());
+        assert_eq!(multi_tree.trees().unwrap(), vec![meta]);
+
+        // the leaves are counted
+        multi_tree
+            .update_all(tree.clone(), (1..11).map(|i| (key(i), value(i))).collect())
+            .unwrap();
+        multi_tree.update(tree.clone(), key(1), value(2)).unwrap();
+        multi_tree
+            .update(tree.clone(), key(2), SMTValue::default())
+            .unwrap();
+        let root = multi_tree
+            .update(tree.clone(), key(100), SMTValue::default())
+            .unwrap();
+        let info = multi_tree.describe_tree(tree.clone()).unwrap().unwrap();
+        assert_eq!(info.meta.leaf_count, 9);
+        assert_eq!(info.root, root);
+
+        // clear keeps the tree registered
+        multi_tree.clear(tree.clone()).unwrap();
+        let info = multi_tree.describe_tree(tree.clone()).unwrap().unwrap();
+        assert_eq!(info.meta.leaf_count, 0);
+        assert_eq!(info.root, H256::zero());
+
+        // delete removes it
+        multi_tree.delete_tree(tree.clone()).unwrap();
+        assert_eq!(multi_tree.describe_tree(tree.clone()).unwrap(), None);
+        assert!(multi_tree.trees().unwrap().is_empty());
+        assert!(multi_tree.delete_tree(tree.clone()).is_err());
+        assert!(multi_tree.update(tree, key(1), value(1)).is_err());
+    }
+
+    #[test]
+    fn test_root_history() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree = MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path())
+            .unwrap()
+            .with_history_depth(3);
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+
+        // version i adds key i, version 6 changes key 1
+        let roots = (1..6)
+            .map(|i| multi_tree.update(tree.clone(), key(i), value(i)).unwrap())
+            .collect::<Vec<H256>>();
+        let root = multi_tree.update(tree.clone(), key(1), value(100)).unwrap();
+
+        // only the last 3 versions are kept
+        let history = multi_tree.root_history(tree.clone()).unwrap();
+        assert_eq!(
+            history,
+            vec![
+                RootVersion { version: 6, root },
+                RootVersion {
+                    version: 5,
+                    root: roots[4]
+                },
+                RootVersion {
+                    version: 4,
+                    root: roots[3]
+                },
+            ]
+        );
+
+        // read and prove against version 4
+        assert_eq!(
+            multi_tree
+                .get_value_at(tree.clone(), roots[3], key(1))
+                .unwrap(),
+            value(1)
+        );
+        assert_eq!(
+            multi_tree
+                .get_value_at(tree.clone(), roots[3], key(5))
+                .unwrap(),
+            SMTValue::default()
+        );
+        let proof = multi_tree
+            .get_merkle_proof_at(tree.clone(), roots[3], key(1))
+            .unwrap();
+        assert_eq!(proof.root, roots[3]);
+        assert!(multi_tree.verify(proof));
+        let proof = multi_tree
+            .get_merkle_proof_at(tree.clone(), roots[3], key(5))
+            .unwrap();
+        assert!(multi_tree.verify_non_membership(proof));
+
+        // the current root is always known, expired roots are not
+        assert_eq!(
+            multi_tree
+                .get_merkle_proof_at(tree.clone(), root, key(1))
+                .unwrap(),
+            multi_tree.get_merkle_proof(tree.clone(), key(1)).unwrap()
+        );
+        assert!(multi_tree
+            .get_value_at(tree.clone(), roots[2], key(1))
+            .is_err());
+        assert_eq!(
+            multi_tree.get_value(tree.clone(), key(1)).unwrap(),
+            value(100)
+        );
+
+        // clear drops the history
+        multi_tree.clear(tree.clone()).unwrap();
+        assert_eq!(
+            multi_tree.root_history(tree.clone()).unwrap(),
+            vec![RootVersion {
+                version: 0,
+                root: H256::zero()
+            }]
+        );
+        assert!(multi_tree.get_value_at(tree, roots[4], key(1)).is_err());
+    }
+
+    #[test]
+    fn test_key_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+
+        multi_tree
+            .update_all(tree.clone(), (1..26).map(|i| (key(i), value(i))).collect())
+            .unwrap();
+        multi_tree.update(tree.clone(), key(30), value(30)).unwrap();
+        // removed leaves are not listed
+        multi_tree
+            .update(tree.clone(), key(1), SMTValue::default())
+            .unwrap();
+        multi_tree.update(tree.clone(), key(2), value(200)).unwrap();
+
+        // a leaf whose key starts with the tag of the index is not listed
+        let mut tx = multi_tree.store.transaction();
+        let node_key = [index_prefix(&tree_prefix(&tree)), vec![7u8; 28]].concat();
+        tx.put(COL_NODES, &node_key, b"leaf");
+        multi_tree.store.write(tx).unwrap();
+
+        // page through all keys
+        let mut keys = vec![];
+        let mut start_after = None;
+        loop {
+            let page = multi_tree.keys(tree.clone(), start_after, 10).unwrap();
+            assert!(page.items.len() <= 10);
+            keys.extend(page.items);
+            match page.next {
+                Some(next) => start_after = Some(next),
+                None => break,
+            }
+        }
+        let mut expected = (2..26).chain([30]).map(key).collect::<Vec<SMTKey>>();
+        expected.sort_by_key(|k| k.to_h256().encode());
+        assert_eq!(keys, expected);
+
+        let entries = multi_tree.entries(tree.clone(), None, 100).unwrap();
+        assert_eq!(entries.next, None);
+        assert_eq!(entries.items.len(), 25);
+        for entry in entries.items {
+            let i: u64 = entry.key.address.parse().unwrap();
+            let expected = if i == 2 { value(200) } else { value(i) };
+            assert_eq!(entry.value, expected);
+        }
+
+        // the index belongs to the tree and is cleared with it
+        assert!(multi_tree
+            .keys("other".to_string(), None, 10)
+            .unwrap()
+            .items
+            .is_empty());
+        multi_tree.clear(tree.clone()).unwrap();
+        assert!(multi_tree.keys(tree, None, 10).unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn test_ingest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+        // the last entry of a key wins, empty values are deletions
+        let kvs = (1..300)
+            .map(|i| (key(i), value(i)))
+            .chain([(key(5), value(500)), (key(7), SMTValue::default())])
+            .collect::<Vec<_>>();
+
+        let mut reports = vec![];
+        let info = multi_tree
+            .ingest(
+                "ingested".to_string(),
+                &mut kvs.clone().into_iter().map(Ok),
+                &mut |p| reports.push(p),
+            )
+            .unwrap();
+        let root = multi_tree
+            .update_all("updated".to_string(), kvs.clone())
+            .unwrap();
+        assert_eq!(info.root, root);
+        assert_eq!(info.meta.leaf_count, 298);
+        assert_eq!(
+            multi_tree.tree_meta("ingested".to_string()).unwrap(),
+            Some(info.meta)
+        );
+        let last = reports.last().unwrap();
+        assert_eq!(last.stage, ingest::Stage::Branches);
+        assert_eq!(last.entries, 301);
+        assert_eq!(
+            multi_tree
+                .get_value("ingested".to_string(), key(5))
+                .unwrap(),
+            value(500)
+        );
+        let proof = multi_tree
+            .get_merkle_proof("ingested".to_string(), key(8))
+            .unwrap();
+        assert!(multi_tree.verify(proof));
+        assert_eq!(
+            multi_tree
+                .keys("ingested".to_string(), None, 1000)
+                .unwrap()
+                .items
+                .len(),
+            298
+        );
+
+        // the ingested branches are the ones `update_all` writes, so later updates agree
+        for tree in ["ingested", "updated"] {
+            multi_tree
+                .update(tree.to_string(), key(7), value(7))
+                .unwrap();
+            multi_tree
+                .update(tree.to_string(), key(1), SMTValue::default())
+                .unwrap();
+        }
+        assert_eq!(
+            multi_tree.get_root("ingested".to_string()).unwrap(),
+            multi_tree.get_root("updated".to_string()).unwrap()
+        );
+
+        // only empty trees are ingested
+        assert!(multi_tree
+            .ingest(
+                "ingested".to_string(),
+                &mut kvs.clone().into_iter().map(Ok),
+                &mut |_| {}
+            )
+            .is_err());
+
+        // a failed ingest leaves an empty tree that can be ingested again
+        let mut failing = kvs
+            .clone()
+            .into_iter()
+            .map(Ok)
+            .chain([Err(Error::store("broken upload"))]);
+        assert!(multi_tree
+            .ingest("retried".to_string(), &mut failing, &mut |_| {})
+            .is_err());
+        assert_eq!(
+            multi_tree.get_root("retried".to_string()).unwrap(),
+            H256::zero()
+        );
+        let info = multi_tree
+            .ingest(
+                "retried".to_string(),
+                &mut kvs[..10].to_vec().into_iter().map(Ok),
+                &mut |_| {},
+            )
+            .unwrap();
+        assert_eq!(info.meta.leaf_count, 10);
+        assert_eq!(
+            multi_tree
+                .keys("retried".to_string(), None, 1000)
+                .unwrap()
+                .items
+                .len(),
+            10
+        );
+    }
+
+    #[test]
+    fn test_next_root_batch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+        let root = multi_tree
+            .update_all(tree.clone(), (1..10).map(|i| (key(i), value(i))).collect())
+            .unwrap();
+        assert_eq!(multi_tree.next_root(tree.clone(), vec![]).unwrap(), root);
+
+        // inserted, updated, removed and repeated keys
+        let kvs = vec![
+            (key(20), value(20)),
+            (key(2), value(200)),
+            (key(3), SMTValue::default()),
+            (key(20), value(21)),
+        ];
+        let next_root = multi_tree.next_root(tree.clone(), kvs.clone()).unwrap();
+        assert_ne!(next_root, root);
+        // nothing is written
+        assert_eq!(multi_tree.get_root(tree.clone()).unwrap(), root);
+        assert_eq!(multi_tree.update_all(tree.clone(), kvs).unwrap(), next_root);
+    }
+
+    #[test]
+    fn test_session() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        let tree = "tree".to_string();
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+        let root = multi_tree
+            .update_all(tree.clone(), (1..10).map(|i| (key(i), value(i))).collect())
+            .unwrap();
+
+        // several rounds of staged updates, the committed tree is unchanged
+        let session = multi_tree.begin_session(tree.clone()).unwrap();
+        multi_tree
+            .session_update(session, vec![(key(20), value(20)), (key(2), value(200))])
+            .unwrap();
+        let staged_root = multi_tree
+            .session_update(session, vec![(key(3), SMTValue::default())])
+            .unwrap();
+        assert_eq!(multi_tree.get_root(tree.clone()).unwrap(), root);
+        assert_eq!(
+            multi_tree.get_value(tree.clone(), key(2)).unwrap(),
+            value(2)
+        );
+        assert_eq!(
+            multi_tree.session_value(session, key(2)).unwrap(),
+            value(200)
+        );
+        assert_eq!(
+            multi_tree.session_value(session, key(3)).unwrap(),
+            SMTValue::default()
+        );
+        let proof = multi_tree.session_merkle_proof(session, key(20)).unwrap();
+        assert_eq!(proof.root, staged_root);
+        assert!(multi_tree.verify(proof));
+
+        // the staged root is the one of the same updates written directly
+        let expected = multi_tree
+            .next_root(
+                tree.clone(),
+                vec![
+                    (key(20), value(20)),
+                    (key(2), value(200)),
+                    (key(3), SMTValue::default()),
+                ],
+            )
+            .unwrap();
+        assert_eq!(staged_root, expected);
+        assert_eq!(multi_tree.commit_session(session).unwrap(), staged_root);
+        assert_eq!(multi_tree.get_root(tree.clone()).unwrap(), staged_root);
+        let meta = multi_tree.tree_meta(tree.clone()).unwrap().unwrap();
+        assert_eq!(meta.leaf_count, 9);
+        assert_eq!(multi_tree.root_history(tree.clone()).unwrap()[1].root, root);
+        // a closed session can't be used
+        assert!(multi_tree.session_value(session, key(2)).is_err());
+        assert!(multi_tree.commit_session(session).is_err());
+
+        // rollback
+        let session = multi_tree.begin_session(tree.clone()).unwrap();
+        multi_tree
+            .session_update(session, vec![(key(30), value(30))])
+            .unwrap();
+        multi_tree.rollback_session(session).unwrap();
+        assert!(multi_tree.rollback_session(session).is_err());
+        assert_eq!(multi_tree.get_root(tree.clone()).unwrap(), staged_root);
+
+        // a session of a tree that changed in the meantime can't be used or committed
+        let session = multi_tree.begin_session(tree.clone()).unwrap();
+        multi_tree
+            .session_update(session, vec![(key(40), value(40))])
+            .unwrap();
+        let other = multi_tree.begin_session(tree.clone()).unwrap();
+        assert_ne!(other, session);
+        let root = multi_tree.update(tree.clone(), key(41), value(41)).unwrap();
+        assert!(multi_tree.session_value(session, key(40)).is_err());
+        assert!(multi_tree.session_merkle_proof(session, key(2)).is_err());
+        assert!(multi_tree
+            .session_update(session, vec![(key(42), value(42))])
+            .is_err());
+        assert!(multi_tree.commit_session(session).is_err());
+        multi_tree.rollback_session(other).unwrap();
+        assert_eq!(multi_tree.get_root(tree).unwrap(), root);
+    }
 }
@@ -0,0 +1,522 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Open the RocksDB database and migrate databases written by older versions.
+//!
+//! Version 0 (no schema version stored) used the raw tree name as the key prefix, so the keys of
+//! tree "a" and tree "ab" overlapped. Version 1 length-prefixes the name, see [`tree_prefix`].
+//! Version 2 adds the tree registry.
+//!
+//! The migration to version 1 re-keys the nodes in bounded transactions and records its progress under
+//! [`MIGRATION_V1_KEY`], so a migration that is interrupted resumes the next time the database is opened.
+
+use crate::{
+    registry::{HasherName, TreeMeta},
+    store::{tree_prefix, COL_META, COL_NODES, COL_TREES, NUM_COLUMNS},
+};
+use codec::{Decode, Encode};
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use smt_primitives::keccak_hasher::Keccak256Hasher;
+use sparse_merkle_tree::{BranchKey, BranchNode, H256};
+use std::{collections::BTreeMap, io, mem, path::Path};
+
+/// The key of the schema version in the meta column.
+pub const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+/// The current schema version.
+pub const SCHEMA_VERSION: u32 = 2;
+/// The key of the progress of the migration to version 1 in the meta column, see [`MigrationV1`].
+pub const MIGRATION_V1_KEY: &[u8] = b"migration_v1";
+/// The number of nodes re-keyed by one transaction of the migration to version 1.
+const MIGRATION_BATCH: usize = 10_000;
+
+/// Open the database, create it if it does not exist, and migrate it to the current schema.
+pub fn open_database<P: AsRef<Path>>(path: P) -> io::Result<Database> {
+    let db = open_columns(path)?;
+    migrate(&db)?;
+    Ok(db)
+}
+
+/// Open the database with all columns of the current schema, without migrating it.
+fn open_columns<P: AsRef<Path>>(path: P) -> io::Result<Database> {
+    let path = path.as_ref();
+    let db = match Database::open(&DatabaseConfig::with_columns(NUM_COLUMNS), path) {
+        Ok(db) => db,
+        // a database of an older version has fewer columns
+        Err(e) => {
+            let mut db = (1..NUM_COLUMNS)
+                .rev()
+                .find_map(|columns| {
+                    Database::open(&DatabaseConfig::with_columns(columns), path).ok()
+                })
+                .ok_or(e)?;
+            while db.num_columns() < NUM_COLUMNS {
+                db.add_column()?;
+            }
+            db
+        }
+    };
+    Ok(db)
+}
+
+/// The schema version of the database, 0 if it is not stored.
+pub fn schema_version(db: &Database) -> io::Result<u32> {
+    match db.get(COL_META, SCHEMA_VERSION_KEY)? {
+        Some(v) => u32::decode(&mut v.as_slice())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        None => Ok(0),
+    }
+}
+
+/// Migrate the database to the current schema. The step to version 1 is written in batches, the others
+/// in one transaction each.
+pub fn migrate(db: &Database) -> io::Result<()> {
+    let version = schema_version(db)?;
+    if version > SCHEMA_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported schema version {}", version),
+        ));
+    }
+    if version < 1 {
+        migrate_v1(db)?;
+    }
+    if version < 2 {
+        migrate_v2(db)?;
+    }
+    Ok(())
+}
+
+/// The progress of the migration to version 1, stored until the migration is complete.
+#[derive(Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub struct MigrationV1 {
+    /// The trees of version 0, found before any node was re-keyed.
+    pub names: Vec<String>,
+    /// The number of trees at the start of `names` whose nodes are all re-keyed.
+    pub done: u32,
+}
+
+/// 0 -> 1: re-key the nodes of every tree with the length-prefixed name.
+/// Nodes that are not reachable from the root of a tree are garbage and are dropped.
+fn migrate_v1(db: &Database) -> io::Result<()> {
+    migrate_v1_in_batches(db, MIGRATION_BATCH)
+}
+
+/// Re-key the nodes of every tree in transactions of at most `batch_size` nodes, from where an
+/// interrupted migration stopped.
+fn migrate_v1_in_batches(db: &Database, batch_size: usize) -> io::Result<()> {
+    let mut progress = migration_v1_progress(db)?;
+    while let Some(name) = progress.names.get(progress.done as usize).cloned() {
+        let mut walk = LegacyWalk::new(db, &name);
+        loop {
+            let batch = walk
+                .by_ref()
+                .take(batch_size)
+                .collect::<io::Result<Vec<_>>>()?;
+            if batch.is_empty() {
+                break;
+            }
+            rekey(db, &name, batch)?;
+        }
+        progress.done += 1;
+        let mut tx = db.transaction();
+        tx.put_vec(COL_META, MIGRATION_V1_KEY, progress.encode());
+        db.write(tx)?;
+    }
+    drop_legacy_garbage(db, &progress.names, batch_size)?;
+
+    let mut tx = db.transaction();
+    tx.delete(COL_META, MIGRATION_V1_KEY);
+    tx.put_vec(COL_META, SCHEMA_VERSION_KEY, 1u32.encode());
+    db.write(tx)
+}
+
+/// The progress of an interrupted migration to version 1, or the start of a new one, which is stored
+/// before any node is re-keyed: the length-prefixed keys of the re-keyed trees could be taken for trees
+/// of version 0 afterwards.
+fn migration_v1_progress(db: &Database) -> io::Result<MigrationV1> {
+    if let Some(v) = db.get(COL_META, MIGRATION_V1_KEY)? {
+        return MigrationV1::decode(&mut v.as_slice())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+    }
+    let progress = MigrationV1 {
+        names: legacy_tree_names(db)?,
+        done: 0,
+    };
+    let mut tx = db.transaction();
+    tx.put_vec(COL_META, MIGRATION_V1_KEY, progress.encode());
+    db.write(tx)?;
+    Ok(progress)
+}
+
+/// Move nodes of the tree `name` from their key of version 0 to their key of version 1 in one transaction.
+fn rekey(db: &Database, name: &str, nodes: Vec<(Vec<u8>, Vec<u8>)>) -> io::Result<()> {
+    let prefix = tree_prefix(name);
+    let mut tx = db.transaction();
+    for (node_key, value) in nodes {
+        tx.delete(COL_NODES, &[name.as_bytes(), &node_key].concat());
+        tx.put_vec(COL_NODES, &[prefix.as_slice(), &node_key].concat(), value);
+    }
+    db.write(tx)
+}
+
+/// Delete the nodes of version 0 that are left once every tree is re-keyed, they were not reachable
+/// from the root of any tree.
+fn drop_legacy_garbage(db: &Database, names: &[String], batch_size: usize) -> io::Result<()> {
+    let prefixes = names
+        .iter()
+        .map(|name| tree_prefix(name))
+        .collect::<Vec<_>>();
+    let mut tx = db.transaction();
+    let mut count = 0;
+    for kv in db.iter(COL_NODES) {
+        let (key, _) = kv?;
+        if prefixes.iter().any(|prefix| key.starts_with(prefix)) {
+            continue;
+        }
+        tx.delete(COL_NODES, &key);
+        count += 1;
+        if count == batch_size {
+            db.write(mem::replace(&mut tx, db.transaction()))?;
+            count = 0;
+        }
+    }
+    db.write(tx)
+}
+
+/// 1 -> 2: register every non-empty tree, all trees so far were hashed with Keccak256.
+fn migrate_v2(db: &Database) -> io::Result<()> {
+    let root_key = root_branch_key();
+    let mut trees = BTreeMap::<String, (bool, u64)>::new();
+    for kv in db.iter(COL_NODES) {
+        let (key, _) = kv?;
+        let mut node_key = &key[..];
+        let Ok(name) = String::decode(&mut node_key) else {
+            continue;
+        };
+        let (has_root, leaf_count) = trees.entry(name).or_default();
+        if node_key == root_key.as_slice() {
+            *has_root = true;
+        } else if node_key.len() == 32 {
+            *leaf_count += 1;
+        }
+    }
+
+    let mut tx = db.transaction();
+    for (name, (_, leaf_count)) in trees.into_iter().filter(|(_, (has_root, _))| *has_root) {
+        let mut meta = TreeMeta::new(name.clone(), Keccak256Hasher::NAME);
+        meta.leaf_count = leaf_count;
+        tx.put_vec(COL_TREES, &tree_prefix(&name), meta.encode());
+    }
+    tx.put_vec(COL_META, SCHEMA_VERSION_KEY, 2u32.encode());
+    db.write(tx)
+}
+
+/// The key of the root branch, every non-empty tree has one.
+fn root_branch_key() -> Vec<u8> {
+    BranchKey::new(u8::MAX, H256::zero()).encode()
+}
+
+/// Find the names of all non-empty trees of version 0 by their root branches.
+fn legacy_tree_names(db: &Database) -> io::Result<Vec<String>> {
+    let root_key = root_branch_key();
+    let mut names = vec![];
+    for kv in db.iter(COL_NODES) {
+        let (key, _) = kv?;
+        if let Some(name) = key.strip_suffix(root_key.as_slice()) {
+            // tree names are strings, anything else is not a root branch
+            if let Ok(name) = String::from_utf8(name.to_vec()) {
+                names.push(name);
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// A step of [`LegacyWalk`].
+enum Visit {
+    /// A branch whose children are not visited yet.
+    Branch(u8, H256),
+    /// A node whose descendants are all yielded.
+    Node(Vec<u8>, Vec<u8>),
+}
+
+/// Walk a tree of version 0 from its root and yield its nodes, keyed without the name.
+/// A node is yielded after all of its descendants, so the nodes that are left after the yielded ones were
+/// re-keyed are still reachable from the root, and only the nodes of the path of the walk are in memory.
+struct LegacyWalk<'a> {
+    db: &'a Database,
+    name: &'a str,
+    stack: Vec<Visit>,
+}
+
+impl<'a> LegacyWalk<'a> {
+    fn new(db: &'a Database, name: &'a str) -> Self {
+        LegacyWalk {
+            db,
+            name,
+            stack: vec![Visit::Branch(u8::MAX, H256::zero())],
+        }
+    }
+
+    fn get(&self, node_key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        self.db
+            .get(COL_NODES, &[self.name.as_bytes(), node_key].concat())
+    }
+
+    /// Push a branch and then its children, so they are yielded before it.
+    fn visit(&mut self, height: u8, node_key: H256) -> io::Result<()> {
+        let branch_key = BranchKey::new(height, node_key).encode();
+        let Some(value) = self.get(&branch_key)? else {
+            return Ok(());
+        };
+        let branch = BranchNode::decode(&mut value.as_slice())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        self.stack.push(Visit::Node(branch_key, value));
+
+        let mut right_key = node_key;
+        right_key.set_bit(height);
+        for (child, child_key) in [(branch.left, node_key), (branch.right, right_key)] {
+            if child.is_zero() {
+                continue;
+            }
+            if height == 0 {
+                let leaf_key = child_key.encode();
+                if let Some(leaf) = self.get(&leaf_key)? {
+                    self.stack.push(Visit::Node(leaf_key, leaf));
+                }
+            } else {
+                self.stack.push(Visit::Branch(height - 1, child_key));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for LegacyWalk<'_> {
+    type Item = io::Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(visit) = self.stack.pop() {
+            match visit {
+                Visit::Node(node_key, value) => return Some(Ok((node_key, value))),
+                Visit::Branch(height, node_key) => {
+                    if let Err(e) = self.visit(height, node_key) {
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apis::MultiSMTStore, traits::MSS};
+    use smt_primitives::{
+        keccak_hasher::Keccak256Hasher,
+        kv::{SMTKey, SMTValue},
+    };
+    use sparse_merkle_tree::{default_store::DefaultStore, traits::Value, SparseMerkleTree};
+
+    type Tree = SparseMerkleTree<Keccak256Hasher, SMTValue, DefaultStore<SMTValue>>;
+
+    /// Write a tree with the key layout of version 0.
+    fn write_legacy_tree(db: &Database, name: &str, tree: &Tree) {
+        let mut tx = db.transaction();
+        for (branch_key, branch) in tree.store().branches_map() {
+            tx.put_vec(
+                COL_NODES,
+                &[name.as_bytes(), &branch_key.encode()].concat(),
+                branch.encode(),
+            );
+        }
+        for (leaf_key, leaf) in tree.store().leaves_map() {
+            tx.put_vec(
+                COL_NODES,
+                &[name.as_bytes(), &leaf_key.encode()].concat(),
+                leaf.clone().into(),
+            );
+        }
+        db.write(tx).unwrap();
+    }
+
+    fn key(i: u64) -> SMTKey {
+        SMTKey {
+            address: i.to_string(),
+        }
+    }
+
+    fn value(i: u64) -> SMTValue {
+        SMTValue {
+            nonce: i,
+            balance: i as u128,
+        }
+    }
+
+    /// Write the trees "tree1" and "tree2" with the key layout of version 0.
+    fn write_legacy_database(path: &Path) -> (Tree, Tree) {
+        let mut tree1 = Tree::default();
+        let mut tree2 = Tree::default();
+        tree1
+            .update_all((1..20).map(|i| (key(i).to_h256(), value(i))).collect())
+            .unwrap();
+        tree2
+            .update_all((10..30).map(|i| (key(i).to_h256(), value(i * 2))).collect())
+            .unwrap();
+        let db = Database::open(&DatabaseConfig::with_columns(1), path).unwrap();
+        write_legacy_tree(&db, "tree1", &tree1);
+        write_legacy_tree(&db, "tree2", &tree2);
+        (tree1, tree2)
+    }
+
+    #[test]
+    fn test_migrate_legacy_database() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (tree1, tree2) = write_legacy_database(temp_dir.path());
+
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        assert_eq!(
+            multi_tree.get_root("tree1".to_string()).unwrap(),
+            *tree1.root()
+        );
+        assert_eq!(
+            multi_tree.get_root("tree2".to_string()).unwrap(),
+            *tree2.root()
+        );
+        assert_eq!(
+            multi_tree.get_value("tree1".to_string(), key(5)).unwrap(),
+            value(5)
+        );
+        assert_eq!(
+            multi_tree.get_value("tree2".to_string(), key(25)).unwrap(),
+            value(50)
+        );
+        let proof = multi_tree
+            .get_merkle_proof("tree2".to_string(), key(15))
+            .unwrap();
+        assert!(multi_tree.verify(proof));
+
+        // the existing trees are registered
+        let trees = multi_tree.trees().unwrap();
+        assert_eq!(trees.len(), 2);
+        assert_eq!(trees[0].name, "tree1");
+        assert_eq!(trees[0].leaf_count, 19);
+        assert_eq!(trees[0].hasher, Keccak256Hasher::NAME);
+        assert_eq!(trees[1].name, "tree2");
+        assert_eq!(trees[1].leaf_count, 20);
+        drop(multi_tree);
+
+        // the migration runs only once
+        let db = open_database(temp_dir.path()).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), SCHEMA_VERSION);
+        assert_eq!(
+            db.get(
+                COL_NODES,
+                &[b"tree1".as_slice(), &root_branch_key()].concat()
+            )
+            .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_resume_migration() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (tree1, tree2) = write_legacy_database(temp_dir.path());
+        let garbage = [b"tree1".as_slice(), &[7u8; 32]].concat();
+        {
+            let db = open_columns(temp_dir.path()).unwrap();
+            let mut tx = db.transaction();
+            tx.put(COL_NODES, &garbage, b"garbage");
+            db.write(tx).unwrap();
+
+            // a migration that stops after two batches of the first tree
+            let progress = migration_v1_progress(&db).unwrap();
+            assert_eq!(progress.names, ["tree1", "tree2"]);
+            let mut walk = LegacyWalk::new(&db, "tree1");
+            for _ in 0..2 {
+                let batch = walk
+                    .by_ref()
+                    .take(3)
+                    .collect::<io::Result<Vec<_>>>()
+                    .unwrap();
+                rekey(&db, "tree1", batch).unwrap();
+            }
+            assert_eq!(schema_version(&db).unwrap(), 0);
+        }
+
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        assert_eq!(
+            multi_tree.get_root("tree1".to_string()).unwrap(),
+            *tree1.root()
+        );
+        assert_eq!(
+            multi_tree.get_root("tree2".to_string()).unwrap(),
+            *tree2.root()
+        );
+        let proof = multi_tree
+            .get_merkle_proof("tree1".to_string(), key(3))
+            .unwrap();
+        assert!(multi_tree.verify(proof));
+        assert_eq!(multi_tree.trees().unwrap()[0].leaf_count, 19);
+        drop(multi_tree);
+
+        // every node has its key of version 1 and the progress is gone
+        let db = open_database(temp_dir.path()).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), SCHEMA_VERSION);
+        assert_eq!(db.get(COL_META, MIGRATION_V1_KEY).unwrap(), None);
+        let (prefix1, prefix2) = (tree_prefix("tree1"), tree_prefix("tree2"));
+        for kv in db.iter(COL_NODES) {
+            let (key, _) = kv.unwrap();
+            assert!(key.starts_with(&prefix1) || key.starts_with(&prefix2));
+        }
+        assert_eq!(db.get(COL_NODES, &garbage).unwrap(), None);
+    }
+
+    #[test]
+    fn test_migrate_in_batches() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (tree1, _) = write_legacy_database(temp_dir.path());
+        let db = open_columns(temp_dir.path()).unwrap();
+        migrate_v1_in_batches(&db, 1).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), 1);
+        drop(db);
+
+        let multi_tree =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(temp_dir.path()).unwrap();
+        assert_eq!(
+            multi_tree.get_root("tree1".to_string()).unwrap(),
+            *tree1.root()
+        );
+        assert_eq!(
+            multi_tree.get_value("tree1".to_string(), key(5)).unwrap(),
+            value(5)
+        );
+    }
+
+    #[test]
+    fn test_new_database() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = open_database(temp_dir.path()).unwrap();
+        assert_eq!(db.num_columns(), NUM_COLUMNS);
+        assert_eq!(schema_version(&db).unwrap(), SCHEMA_VERSION);
+    }
+}
@@ -1,3 +1,3 @@
 pub mod apis;
-pub mod req;
+pub mod migration;
 pub mod store;
@@ -19,106 +19,149 @@
 
 #![allow(unused_imports)]
 #![allow(clippy::needless_lifetimes)]
-use sparse_merkle_tree::{
-    error::Error,
-    traits::{StoreReadOps, StoreWriteOps, Value},
-    BranchKey, BranchNode, H256,
+use crate::{
+    kv::{Change, Column, KvDb, KvIter},
+    migration::open_database,
+    tree_store::TreeStore,
 };
-use std::{marker::PhantomData, sync::Arc};
+use std::{io, path::Path};
 
-use codec::{Decode, Encode};
 // use rocksdb::{DBCommon, DB, DBWithThreadMode, OptimisticTransactionDB, ThreadMode};
+use kvdb::{DBKeyValue, KeyValueDB};
 use kvdb_rocksdb::Database;
 
-pub struct SMTStore {
-    inner: Arc<Database>,
-    prefix: String,
-}
+pub use crate::kv::tree_prefix;
+
+/// The column of the tree nodes.
+pub const COL_NODES: u32 = 0;
+/// The column of the database metadata, e.g. the schema version.
+pub const COL_META: u32 = 1;
+/// The column of the tree registry, keyed by the tree prefix.
+pub const COL_TREES: u32 = 2;
+/// The number of columns of the database.
+pub const NUM_COLUMNS: u32 = 3;
 
-impl SMTStore {
-    pub fn new(db: Arc<Database>, prefix: impl Into<String>) -> Self {
-        SMTStore {
-            inner: db,
-            // col,
-            prefix: prefix.into(),
+/// The number of keys a ranged read skips through under one prefix before it reads the prefix one byte value
+/// at a time, see [`read_from`].
+const MAX_SKIP: usize = 256;
+
+/// Read at most `limit` entries of `col` accepted by `keep`, whose keys start with `prefix` and are not less
+/// than `start`, ordered by key. `start` must start with `prefix`.  
+/// RocksDB can only be iterated from the start of a prefix here, so the range is split by the bytes of
+/// `start`: the keys under `start` itself, then for each shorter prefix `start[..i]` the keys under it whose
+/// byte `i` is greater than `start[i]`. A prefix with more than [`MAX_SKIP`] keys before those is read one
+/// byte value at a time instead, so a page never scans the keys before `start`.
+pub fn read_from(
+    db: &Database,
+    col: u32,
+    prefix: &[u8],
+    start: &[u8],
+    limit: usize,
+    keep: impl Fn(&[u8]) -> bool,
+) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut kvs = vec![];
+    let read = |kvs: &mut Vec<_>, iter: &mut dyn Iterator<Item = io::Result<DBKeyValue>>| {
+        while kvs.len() < limit {
+            let Some(kv) = iter.next() else {
+                break;
+            };
+            let (key, value) = kv?;
+            if keep(&key) {
+                kvs.push((key.to_vec(), value));
+            }
+        }
+        Ok::<(), io::Error>(())
+    };
+
+    read(&mut kvs, &mut KeyValueDB::iter_with_prefix(db, col, start))?;
+    for i in (prefix.len()..start.len()).rev() {
+        if kvs.len() >= limit {
+            break;
+        }
+        let (head, byte) = (&start[..i], start[i]);
+        let mut iter = KeyValueDB::iter_with_prefix(db, col, head);
+        let mut skipped = 0;
+        let first = loop {
+            match iter.next().transpose()? {
+                // the keys under `head ++ byte` were read at the longer prefixes
+                Some((key, _)) if key.get(i).map_or(true, |b| *b <= byte) => skipped += 1,
+                first => break first,
+            }
+            if skipped == MAX_SKIP {
+                break None;
+            }
+        };
+        match first {
+            Some((key, value)) => {
+                if keep(&key) {
+                    kvs.push((key.to_vec(), value));
+                }
+                read(&mut kvs, &mut iter)?;
+            }
+            None if skipped == MAX_SKIP => {
+                for next in (byte..=u8::MAX).skip(1) {
+                    if kvs.len() >= limit {
+                        break;
+                    }
+                    let next = [head, &[next]].concat();
+                    read(&mut kvs, &mut KeyValueDB::iter_with_prefix(db, col, &next))?;
+                }
+            }
+            None => {}
         }
     }
+    Ok(kvs)
 }
 
-impl<V> StoreWriteOps<V> for SMTStore
-where
-    V: Value + Into<Vec<u8>>,
-{
-    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
-        let mut tx = self.inner.transaction();
-        tx.put(
-            Default::default(),
-            &[self.prefix.as_bytes(), &node_key.encode()].concat(),
-            &branch.encode(),
-        );
-        self.inner
-            .write(tx)
-            .map_err(|e| Error::Store(e.to_string()))
+/// The nodes of one tree in RocksDB, see [`TreeStore`].
+pub type SMTStore = TreeStore<Database>;
+
+/// The RocksDB column of a column of the stores.
+fn column(col: Column) -> u32 {
+    match col {
+        Column::Nodes => COL_NODES,
+        Column::Trees => COL_TREES,
     }
+}
 
-    // 叶子就是数据
-    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
-        let mut tx = self.inner.transaction();
-        tx.put(
-            Default::default(),
-            &[self.prefix.as_bytes(), &leaf_key.encode()].concat(),
-            &leaf.into(),
-        );
-        self.inner
-            .write(tx)
-            .map_err(|e| Error::Store(e.to_string()))
+impl KvDb for Database {
+    /// Open the database, a database written by an older version is migrated to the current key layout.
+    fn open(path: &Path) -> io::Result<Self> {
+        open_database(path)
     }
 
-    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
-        let mut tx = self.inner.transaction();
-        tx.delete(
-            Default::default(),
-            &[self.prefix.as_bytes(), &node_key.encode()].concat(),
-        );
-        self.inner
-            .write(tx)
-            .map_err(|e| Error::Store(e.to_string()))
+    fn get(&self, col: Column, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        KeyValueDB::get(self, column(col), key)
     }
 
-    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
-        let mut tx = self.inner.transaction();
-        tx.delete(
-            Default::default(),
-            &[self.prefix.as_bytes(), &leaf_key.encode()].concat(),
-        );
-        self.inner
-            .write(tx)
-            .map_err(|e| Error::Store(e.to_string()))
+    fn iter<'a>(&'a self, col: Column, prefix: &'a [u8]) -> io::Result<KvIter<'a>> {
+        Ok(Box::new(
+            KeyValueDB::iter_with_prefix(self, column(col), prefix)
+                .map(|kv| kv.map(|(key, value)| (key.to_vec(), value))),
+        ))
     }
-}
 
-impl<V> StoreReadOps<V> for SMTStore
-where
-    V: Value + From<Vec<u8>>,
-{
-    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
-        self.inner
-            .get(
-                Default::default(),
-                &[self.prefix.as_bytes(), &branch_key.encode()].concat(),
-            )
-            .map(|s| s.map(|v| BranchNode::decode(&mut v.as_slice()).unwrap()))
-            .map_err(|e| Error::Store(e.to_string()))
+    fn iter_from(
+        &self,
+        col: Column,
+        prefix: &[u8],
+        start: &[u8],
+        limit: usize,
+        keep: impl Fn(&[u8]) -> bool,
+    ) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        read_from(self, column(col), prefix, start, limit, keep)
     }
 
-    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
-        self.inner
-            .get(
-                Default::default(),
-                &[self.prefix.as_bytes(), leaf_key.as_slice()].concat(),
-            )
-            .map(|s| s.map(|v| v.into()))
-            .map_err(|e| Error::Store(e.to_string()))
+    fn commit(&self, changes: Vec<Change>) -> io::Result<()> {
+        let mut tx = self.transaction();
+        for change in changes {
+            match change {
+                Change::Put(col, key, value) => tx.put_vec(column(col), &key, value),
+                Change::Delete(col, key) => tx.delete(column(col), &key),
+                Change::DeletePrefix(col, prefix) => tx.delete_prefix(column(col), &prefix),
+            }
+        }
+        self.write(tx)
     }
 }
 
@@ -126,8 +169,12 @@ where
 pub mod test {
     use super::*;
     use smt_primitives::kv::{SMTKey, SMTValue};
-    use sparse_merkle_tree::{merge::MergeValue, traits::Value};
-    use std::path::Path;
+    use sparse_merkle_tree::{
+        merge::MergeValue,
+        traits::{StoreReadOps, StoreWriteOps, Value},
+        BranchKey, BranchNode, H256,
+    };
+    use std::{path::Path, sync::Arc};
 
     #[test]
     fn test_store() {
@@ -175,4 +222,98 @@ pub mod test {
             None::<BranchNode>
         );
     }
+
+    #[test]
+    fn test_commit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::open(&Default::default(), temp_dir.path()).unwrap());
+        let mut store = SMTStore::new(db.clone(), "test");
+
+        let leaf1_key: H256 = [1u8; 32].to_vec().into();
+        let leaf1 = SMTValue {
+            nonce: 1,
+            balance: 99,
+        };
+        let node1_key: BranchKey = BranchKey::new(100, [2u8; 32].into());
+        let node1: BranchNode = BranchNode {
+            left: MergeValue::from_h256([3u8; 32].into()),
+            right: MergeValue::from_h256([4u8; 32].into()),
+        };
+        store.insert_leaf(leaf1_key, leaf1.clone()).unwrap();
+        store.insert_leaf(leaf1_key, leaf1.clone()).unwrap();
+        assert_eq!(store.leaf_delta(), 1);
+        <SMTStore as StoreWriteOps<SMTValue>>::insert_branch(
+            &mut store,
+            node1_key.clone(),
+            node1.clone(),
+        )
+        .unwrap();
+
+        // nothing is written before the commit
+        let other = SMTStore::new(db.clone(), "test");
+        assert_eq!(other.get_leaf(&leaf1_key).unwrap(), None::<SMTValue>);
+        assert_eq!(
+            <SMTStore as StoreReadOps<SMTValue>>::get_branch(&other, &node1_key).unwrap(),
+            None::<BranchNode>
+        );
+
+        store.commit().unwrap();
+        assert_eq!(store.leaf_delta(), 0);
+        assert_eq!(other.get_leaf(&leaf1_key).unwrap(), Some(leaf1));
+        assert_eq!(
+            <SMTStore as StoreReadOps<SMTValue>>::get_branch(&other, &node1_key).unwrap(),
+            Some(node1)
+        );
+
+        <SMTStore as StoreWriteOps<SMTValue>>::remove_leaf(&mut store, &leaf1_key).unwrap();
+        assert_eq!(store.leaf_delta(), -1);
+        <SMTStore as StoreWriteOps<SMTValue>>::remove_branch(&mut store, &node1_key).unwrap();
+        store.commit().unwrap();
+        assert_eq!(other.get_leaf(&leaf1_key).unwrap(), None::<SMTValue>);
+        assert_eq!(
+            <SMTStore as StoreReadOps<SMTValue>>::get_branch(&other, &node1_key).unwrap(),
+            None::<BranchNode>
+        );
+    }
+
+    #[test]
+    fn test_read_from() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(&Default::default(), temp_dir.path()).unwrap();
+        let mut keys = vec![b"p".to_vec(), b"q1".to_vec(), b"o9".to_vec()];
+        for i in 0..3000u32 {
+            let n = i.wrapping_mul(2_654_435_761).to_be_bytes();
+            keys.push([&b"p"[..], &n[..1 + i as usize % 3]].concat());
+        }
+        let mut tx = db.transaction();
+        for key in keys.iter() {
+            tx.put(COL_NODES, key, key);
+        }
+        db.write(tx).unwrap();
+        keys.sort();
+        keys.dedup();
+
+        let keep = |key: &[u8]| key.len() != 3;
+        for start in [
+            &b"p"[..],
+            b"p\x00",
+            b"p\x80\x00",
+            b"p\x80\xff\xff",
+            b"p\xff",
+            b"p\xff\xff\xff",
+        ] {
+            for limit in [0, 1, 10, 1000, usize::MAX] {
+                let expected = keys
+                    .iter()
+                    .filter(|key| key.starts_with(b"p") && key.as_slice() >= start && keep(key))
+                    .take(limit)
+                    .map(|key| (key.clone(), key.clone()))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    read_from(&db, COL_NODES, b"p", start, limit, keep).unwrap(),
+                    expected
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,1789 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! HTTP APIs based on Actix and Swagger-UI, shared by all storage backends.  
+//! The handlers are generic over [`MSS`], so adding or fixing an API only happens here.  
+//! The store operations run on a [`StorePool`], a request that finds its queue full gets a 503.
+
+use crate::{
+    error::{json_error, query_error, Error, ErrorCode},
+    hashers::HasherKind,
+    history::RootVersion,
+    ingest::parse_line,
+    key_index::{Page, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT},
+    pool::StorePool,
+    registry::{TreeInfo, TreeMeta},
+    req::{
+        KVPair, ReqByKVs, ReqByKey, ReqByKeyAt, ReqByKeys, ReqByPrefix, ReqCreateTree, ReqHasher,
+        ReqPage, ReqSession, ReqSessionKey, ReqSessionUpdate, ReqUpdate, ReqUpdateBatch,
+    },
+    traits::MSS,
+};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use flexi_logger::{
+    Age, Cleanup, Criterion, FileSpec, FlexiLoggerError, Logger, LoggerHandle, Naming,
+};
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use smt_primitives::{
+    keccak_hasher::Keccak256Hasher,
+    kv::{SMTKey, SMTValue},
+    verify::{BatchProof, Proof},
+};
+use sparse_merkle_tree::H256;
+use std::{
+    fmt::Debug,
+    future::Future,
+    io::{BufRead, BufReader, Seek},
+};
+use tokio::{io::AsyncWriteExt, signal::ctrl_c};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+pub const SMT_API: &str = "SMT API";
+
+/// The default number of entries one `/update_batch` request may carry.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 10_000;
+
+/// The default size of the JSON body of one `/update_batch` request, in bytes.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// The default size of one `/ingest` upload, in bytes.
+pub const DEFAULT_MAX_INGEST_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Limits on the size of batch requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchLimits {
+    /// The most entries of one request.
+    pub max_entries: usize,
+    /// The largest JSON body of one request, in bytes.
+    pub max_body_size: usize,
+    /// The largest `/ingest` upload, in bytes.
+    pub max_ingest_size: u64,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_BATCH_SIZE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_ingest_size: DEFAULT_MAX_INGEST_SIZE,
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        update_value,
+        update_batch,
+        ingest,
+        remove_value,
+        get_merkle_proof,
+        get_batch_merkle_proof,
+        get_next_root,
+        get_next_root_batch,
+        get_root,
+        get_value,
+        verify,
+        verify_non_membership,
+        verify_batch,
+        clear,
+        list_trees,
+        create_tree,
+        describe_tree,
+        delete_tree,
+        root_history,
+        get_value_at,
+        get_merkle_proof_at,
+        list_keys,
+        list_entries,
+        begin_session,
+        session_update,
+        session_value,
+        session_merkle_proof,
+        commit_session,
+        rollback_session,
+    ),
+    components(schemas(Error, ErrorCode, HasherKind)),
+    tags(
+        (name = "SMT API", description = "Provides sparse Merkel tree related APIs")
+    ),
+)]
+pub struct ApiDoc;
+
+/// The type a backend identifies a tree by, as it is sent in the request bodies.
+pub trait TreeId: Clone + Debug + DeserializeOwned + Send + 'static {}
+
+impl<T: Clone + Debug + DeserializeOwned + Send + 'static> TreeId for T {}
+
+/// Insert a value into a specific Merkle tree.
+#[utoipa::path(
+    post,
+    path = "/update",
+    tag = SMT_API,
+    request_body = ReqUpdate<String, SMTKey, SMTValue>,
+    responses(
+        (status = 200, description = "Insert a value into a specific Merkle tree.", body = [H256]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn update_value<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqUpdate<T, SMTKey, SMTValue>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, key, value) = (info.prefix.clone(), info.key.clone(), info.value.clone());
+    let root = run(&multi_tree, move |store| store.update(prefix, key, value)).await?;
+    log::info!(
+        "{:#?}",
+        format!("[Update] info: {:#?}, root: {:?}", info, root)
+    );
+    Ok(HttpResponse::Ok().json(root))
+}
+
+/// Write multiple entries to a specific Merkle tree at once, entries with the default value are removed.
+/// Either all entries are written or none, the new root is returned.
+#[utoipa::path(
+    post,
+    path = "/update_batch",
+    tag = SMT_API,
+    request_body = ReqUpdateBatch<String, SMTKey, SMTValue>,
+    responses(
+        (status = 200, description = "Write multiple entries to a specific Merkle tree at once.", body = [H256]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 413, description = "The request body is too large.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn update_batch<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    limits: web::Data<BatchLimits>,
+    info: web::Json<ReqUpdateBatch<T, SMTKey, SMTValue>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    check_batch(info.kvs.len(), &limits)?;
+    let info = info.into_inner();
+    let count = info.kvs.len();
+    let kvs = info.kvs.into_iter().map(|kv| (kv.key, kv.value)).collect();
+    let prefix = info.prefix.clone();
+    let root = run(&multi_tree, move |store| store.update_all(prefix, kvs)).await?;
+    log::info!(
+        "{:?}",
+        format!(
+            "[Update Batch] prefix: {:?}, entries: {}, root: {:?}",
+            info.prefix, count, root
+        )
+    );
+    Ok(HttpResponse::Ok().json(root))
+}
+
+/// Build an empty tree from an NDJSON upload, one key/value object per line, see [`crate::ingest`].
+/// The upload is spooled to a temporary file while it is received and then streamed into the store, so it
+/// is bounded by `limits.max_ingest_size` rather than by the JSON limit of `/update_batch`.
+#[utoipa::path(
+    post,
+    path = "/ingest",
+    tag = SMT_API,
+    params(("prefix" = String, Query, description = "The name of the tree")),
+    request_body(content = String, content_type = "application/x-ndjson", description = "One `{\"address\", \"nonce\", \"balance\"}` object per line"),
+    responses(
+        (status = 200, description = "Build an empty tree from an NDJSON upload.", body = [TreeInfo]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 409, description = "The request conflicts with the state of the tree.", body = Error),
+        (status = 413, description = "The upload is too large.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn ingest<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    query: web::Query<ReqByPrefix<T>>,
+    limits: web::Data<BatchLimits>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let prefix = query.into_inner().prefix;
+    // the upload is spooled before the tree is locked, a slow client holds neither the tree nor the pool
+    let mut spool = tokio::fs::File::from_std(tempfile::tempfile().map_err(Error::internal)?);
+    let mut size = 0u64;
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| ErrorCode::BadRequest.store_error(e))?;
+        size += chunk.len() as u64;
+        if size > limits.max_ingest_size {
+            return Err(ErrorCode::TooLarge.store_error(format!(
+                "Upload too large, at most {} bytes",
+                limits.max_ingest_size
+            )));
+        }
+        spool.write_all(&chunk).await.map_err(Error::internal)?;
+    }
+    spool.flush().await.map_err(Error::internal)?;
+    let mut spool = spool.into_std().await;
+
+    let tree = prefix.clone();
+    let info = run(&multi_tree, move |store| {
+        spool.rewind().map_err(Error::internal)?;
+        let mut entries = BufReader::new(spool)
+            .split(b'\n')
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => return Some(Err(Error::internal(e))),
+                };
+                parse_line::<SMTKey, SMTValue>(&line)
+                    .map_err(|e| {
+                        ErrorCode::BadRequest.store_error(format!("Line {}: {}", i + 1, e))
+                    })
+                    .transpose()
+            });
+        store.ingest(tree.clone(), &mut entries, &mut |progress| {
+            log::info!(
+                "{:?}",
+                format!("[Ingest] prefix: {:?}, progress: {:?}", tree, progress)
+            )
+        })
+    })
+    .await?;
+    log::info!(
+        "{:?}",
+        format!("[Ingest] prefix: {:?}, info: {:?}", prefix, info)
+    );
+    Ok(HttpResponse::Ok().json(info))
+}
+
+/// Remove a value by key
+#[utoipa::path(
+    post,
+    path = "/remove",
+    tag = SMT_API,
+    request_body = ReqByKey<String, SMTKey>,
+    responses(
+        (status = 200, description = "Remove a value by key", body = [H256]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn remove_value<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByKey<T, SMTKey>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, key) = (info.prefix.clone(), info.key.clone());
+    let root = run(&multi_tree, move |store| {
+        store.update(prefix, key, Default::default())
+    })
+    .await?;
+    log::info!(
+        "{:#?}",
+        format!("[Remove] info: {:#?}, root: {:?}", info, root)
+    );
+    Ok(HttpResponse::Ok().json(root))
+}
+
+/// Get the Merkle proof.
+#[utoipa::path(
+    post,
+    path = "/merkle_proof",
+    tag = SMT_API,
+    request_body = ReqByKey<String, SMTKey>,
+    responses(
+        (status = 200, description = "Get the Merkle proof.", body = [Proof<SMTKey, SMTValue>]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn get_merkle_proof<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByKey<T, SMTKey>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, key) = (info.prefix.clone(), info.key.clone());
+    let proof = run(&multi_tree, move |store| {
+        store.get_merkle_proof(prefix, key)
+    })
+    .await?;
+    log::info!(
+        "{:?}",
+        format!("[Get Merkle Proof] info: {:?}, proof: {:?}", info, proof)
+    );
+    Ok(HttpResponse::Ok().json(proof))
+}
+
+/// Get one Merkle proof for multiple keys.
+#[utoipa::path(
+    post,
+    path = "/batch_merkle_proof",
+    tag = SMT_API,
+    request_body = ReqByKeys<String, SMTKey>,
+    responses(
+        (status = 200, description = "Get one Merkle proof for multiple keys.", body = [BatchProof<SMTKey, SMTValue>]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn get_batch_merkle_proof<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByKeys<T, SMTKey>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, keys) = (info.prefix.clone(), info.keys.clone());
+    let proof = run(&multi_tree, move |store| {
+        store.get_batch_merkle_proof(prefix, keys)
+    })
+    .await?;
+    log::info!(
+        "{:?}",
+        format!(
+            "[Get Batch Merkle Proof] info: {:?}, proof: {:?}",
+            info, proof
+        )
+    );
+    Ok(HttpResponse::Ok().json(proof))
+}
+
+/// Before data is updated, the future value of the root hash can be calculated in advance.
+#[utoipa::path(
+    post,
+    path = "/next_root",
+    tag = SMT_API,
+    request_body = ReqByKVs<String, KVPair<SMTKey, SMTValue>>,
+    responses(
+        (status = 200, description = "Before data is updated, the future value of the root hash can be calculated in advance.", body = [H256]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 422, description = "A proof does not verify.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn get_next_root<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByKVs<T, KVPair<SMTKey, SMTValue>>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, key, value) = (
+        info.prefix.clone(),
+        info.kv.key.clone(),
+        info.kv.value.clone(),
+    );
+    let next_root = run(&multi_tree, move |store| {
+        store.next_root(prefix, vec![(key, value)])
+    })
+    .await?;
+    log::info!(
+        "{:?}",
+        format!(
+            "[Get Next Root] info: {:?}, next root: {:?}",
+            info, next_root
+        )
+    );
+    Ok(HttpResponse::Ok().json(next_root))
+}
+
+/// Calculate the root hash after multiple updates and deletions in advance, nothing is written.
+#[utoipa::path(
+    post,
+    path = "/next_root_batch",
+    tag = SMT_API,
+    request_body = ReqUpdateBatch<String, SMTKey, SMTValue>,
+    responses(
+        (status = 200, description = "Calculate the root hash after multiple updates and deletions in advance.", body = [H256]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 413, description = "The request body is too large.", body = Error),
+        (status = 422, description = "A proof does not verify.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn get_next_root_batch<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    limits: web::Data<BatchLimits>,
+    info: web::Json<ReqUpdateBatch<T, SMTKey, SMTValue>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    check_batch(info.kvs.len(), &limits)?;
+    let info = info.into_inner();
+    let count = info.kvs.len();
+    let kvs = info.kvs.into_iter().map(|kv| (kv.key, kv.value)).collect();
+    let prefix = info.prefix.clone();
+    let next_root = run(&multi_tree, move |store| store.next_root(prefix, kvs)).await?;
+    log::info!(
+        "{:?}",
+        format!(
+            "[Get Next Root Batch] prefix: {:?}, entries: {}, next root: {:?}",
+            info.prefix, count, next_root
+        )
+    );
+    Ok(HttpResponse::Ok().json(next_root))
+}
+
+/// Get the root hash.
+#[utoipa::path(
+    post,
+    path = "/root",
+    tag = SMT_API,
+    request_body = ReqByPrefix<String>,
+    responses(
+        (status = 200, description = "Get the root hash.", body = [H256]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn get_root<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByPrefix<T>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let prefix = info.prefix.clone();
+    let root = run(&multi_tree, move |store| store.get_root(prefix)).await?;
+    log::info!(
+        "{:?}",
+        format!(
+            "[Get Root] info: {:?}, root: {:?}",
+            info,
+            serde_json::to_string(&root)
+        )
+    );
+    Ok(HttpResponse::Ok().json(root))
+}
+
+/// Get the value of a specific key in a particular tree.
+#[utoipa::path(
+    post,
+    path = "/value",
+    tag = SMT_API,
+    request_body = ReqByKey<String, SMTKey>,
+    responses(
+        (status = 200, description = "Get the value of a specific key in a particular tree.", body = [SMTValue]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn get_value<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByKey<T, SMTKey>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, key) = (info.prefix.clone(), info.key.clone());
+    let value = run(&multi_tree, move |store| store.get_value(prefix, key)).await?;
+    log::info!(
+        "{:?}",
+        format!("[Get Value] info: {:?}, value: {:?}", info, value)
+    );
+    Ok(HttpResponse::Ok().json(value))
+}
+
+/// Verify the Merkle proof.
+#[utoipa::path(
+    post,
+    path = "/verify",
+    tag = SMT_API,
+    params(("hasher" = Option<HasherKind>, Query, description = "The hasher of the tree, keccak256 by default")),
+    request_body = Proof<SMTKey, SMTValue>,
+    responses(
+        (status = 200, description = "Verify the Merkle proof.", body = [bool]),
+        (status = 400, description = "The request is invalid.", body = Error)
+    )
+)]
+pub async fn verify(
+    query: web::Query<ReqHasher>,
+    info: web::Json<Proof<SMTKey, SMTValue>>,
+) -> Result<HttpResponse, Error> {
+    let hasher = query.hasher;
+    let res = hasher.verify(Proof {
+        key: info.key.clone(),
+        value: info.value.clone(),
+        path: hasher.leaf_hash(&info.key),
+        value_hash: hasher.leaf_hash(&info.value),
+        leave_bitmap: info.leave_bitmap,
+        siblings: info.siblings.clone(),
+        root: info.root,
+    });
+    log::info!("{:?}", format!("[Verify] info: {:?}, res: {:?}", info, res));
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Verify the non-membership proof, i.e. that the key is not in the tree.
+#[utoipa::path(
+    post,
+    path = "/verify_non_membership",
+    tag = SMT_API,
+    params(("hasher" = Option<HasherKind>, Query, description = "The hasher of the tree, keccak256 by default")),
+    request_body = Proof<SMTKey, SMTValue>,
+    responses(
+        (status = 200, description = "Verify the non-membership proof, i.e. that the key is not in the tree.", body = [bool]),
+        (status = 400, description = "The request is invalid.", body = Error)
+    )
+)]
+pub async fn verify_non_membership(
+    query: web::Query<ReqHasher>,
+    info: web::Json<Proof<SMTKey, SMTValue>>,
+) -> Result<HttpResponse, Error> {
+    let hasher = query.hasher;
+    let res = hasher.verify_non_membership(Proof {
+        key: info.key.clone(),
+        value: info.value.clone(),
+        path: hasher.leaf_hash(&info.key),
+        value_hash: hasher.leaf_hash(&info.value),
+        leave_bitmap: info.leave_bitmap,
+        siblings: info.siblings.clone(),
+        root: info.root,
+    });
+    log::info!(
+        "{:?}",
+        format!("[Verify Non-membership] info: {:?}, res: {:?}", info, res)
+    );
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Verify the batch Merkle proof.
+#[utoipa::path(
+    post,
+    path = "/verify_batch",
+    tag = SMT_API,
+    params(("hasher" = Option<HasherKind>, Query, description = "The hasher of the tree, keccak256 by default")),
+    request_body = BatchProof<SMTKey, SMTValue>,
+    responses(
+        (status = 200, description = "Verify the batch Merkle proof.", body = [bool]),
+        (status = 400, description = "The request is invalid.", body = Error)
+    )
+)]
+pub async fn verify_batch(
+    query: web::Query<ReqHasher>,
+    info: web::Json<BatchProof<SMTKey, SMTValue>>,
+) -> Result<HttpResponse, Error> {
+    let hasher = query.hasher;
+    let mut proof = info.0.clone();
+    proof.leaves.iter_mut().for_each(|leaf| {
+        leaf.path = hasher.leaf_hash(&leaf.key);
+        leaf.value_hash = hasher.leaf_hash(&leaf.value);
+    });
+    let res = hasher.verify_batch(proof);
+    log::info!(
+        "{:?}",
+        format!("[Verify Batch] info: {:?}, res: {:?}", info, res)
+    );
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Remove all leaves of a specific Merkle tree, a registered tree stays registered.
+#[utoipa::path(
+    post,
+    path = "/clear",
+    tag = SMT_API,
+    request_body = ReqByPrefix<String>,
+    responses(
+        (status = 200, description = "Remove all leaves of a specific Merkle tree.", body = [H256]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn clear<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByPrefix<T>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let prefix = info.prefix.clone();
+    let root = run(&multi_tree, move |store| {
+        store.clear(prefix.clone())?;
+        store.get_root(prefix)
+    })
+    .await?;
+    log::info!("{:?}", format!("[Clear] info: {:?}, res: {:?}", info, root));
+    Ok(HttpResponse::Ok().json(root))
+}
+
+/// List the metadata of all registered trees.
+#[utoipa::path(
+    post,
+    path = "/trees",
+    tag = SMT_API,
+    responses(
+        (status = 200, description = "List the metadata of all registered trees.", body = [TreeMeta]),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn list_trees<S, T>(multi_tree: web::Data<StorePool<S>>) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let trees = run(&multi_tree, |store| store.trees()).await?;
+    log::info!("{:?}", format!("[List Trees] res: {:?}", trees));
+    Ok(HttpResponse::Ok().json(trees))
+}
+
+/// Create an empty tree with the given hasher, see [`crate::hashers`].
+#[utoipa::path(
+    post,
+    path = "/trees/create",
+    tag = SMT_API,
+    request_body = ReqCreateTree<String>,
+    responses(
+        (status = 200, description = "Create an empty tree with the given hasher.", body = [TreeMeta]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 409, description = "The request conflicts with the state of the tree.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn create_tree<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqCreateTree<T>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, hasher) = (info.prefix.clone(), info.hasher);
+    let meta = run(&multi_tree, move |store| store.create_tree(prefix, hasher)).await?;
+    log::info!(
+        "{:?}",
+        format!("[Create Tree] info: {:?}, res: {:?}", info, meta)
+    );
+    Ok(HttpResponse::Ok().json(meta))
+}
+
+/// Get the metadata and the root of a tree.
+#[utoipa::path(
+    post,
+    path = "/trees/describe",
+    tag = SMT_API,
+    request_body = ReqByPrefix<String>,
+    responses(
+        (status = 200, description = "Get the metadata and the root of a tree.", body = [TreeInfo]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn describe_tree<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByPrefix<T>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let prefix = info.prefix.clone();
+    let tree = run(&multi_tree, move |store| store.describe_tree(prefix))
+        .await?
+        .ok_or_else(|| {
+            Error::new(
+                ErrorCode::UnknownTree,
+                format!("Unknown tree: {:?}", info.prefix),
+            )
+        })?;
+    log::info!(
+        "{:?}",
+        format!("[Describe Tree] info: {:?}, res: {:?}", info, tree)
+    );
+    Ok(HttpResponse::Ok().json(tree))
+}
+
+/// Delete a tree with all of its leaves.
+#[utoipa::path(
+    post,
+    path = "/trees/delete",
+    tag = SMT_API,
+    request_body = ReqByPrefix<String>,
+    responses(
+        (status = 200, description = "Delete a tree with all of its leaves.", body = [bool]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn delete_tree<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByPrefix<T>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let prefix = info.prefix.clone();
+    run(&multi_tree, move |store| store.delete_tree(prefix)).await?;
+    log::info!("{:?}", format!("[Delete Tree] info: {:?}", info));
+    Ok(HttpResponse::Ok().json(true))
+}
+
+/// List the current root and the past roots of a tree that can still be proven against, newest first.
+#[utoipa::path(
+    post,
+    path = "/root_history",
+    tag = SMT_API,
+    request_body = ReqByPrefix<String>,
+    responses(
+        (status = 200, description = "List the current root and the past roots of a tree, newest first.", body = [RootVersion]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn root_history<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByPrefix<T>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let prefix = info.prefix.clone();
+    let roots = run(&multi_tree, move |store| store.root_history(prefix)).await?;
+    log::info!(
+        "{:?}",
+        format!("[Root History] info: {:?}, res: {:?}", info, roots)
+    );
+    Ok(HttpResponse::Ok().json(roots))
+}
+
+/// Get the value of a key when the tree had the given root.
+#[utoipa::path(
+    post,
+    path = "/value_at",
+    tag = SMT_API,
+    request_body = ReqByKeyAt<String, SMTKey>,
+    responses(
+        (status = 200, description = "Get the value of a key when the tree had the given root.", body = [SMTValue]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn get_value_at<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByKeyAt<T, SMTKey>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, root, key) = (info.prefix.clone(), info.root, info.key.clone());
+    let value = run(&multi_tree, move |store| {
+        store.get_value_at(prefix, root, key)
+    })
+    .await?;
+    log::info!(
+        "{:?}",
+        format!("[Get Value At] info: {:?}, value: {:?}", info, value)
+    );
+    Ok(HttpResponse::Ok().json(value))
+}
+
+/// Get the Merkle proof against a past root of the tree.
+#[utoipa::path(
+    post,
+    path = "/merkle_proof_at",
+    tag = SMT_API,
+    request_body = ReqByKeyAt<String, SMTKey>,
+    responses(
+        (status = 200, description = "Get the Merkle proof against a past root of the tree.", body = [Proof<SMTKey, SMTValue>]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn get_merkle_proof_at<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByKeyAt<T, SMTKey>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, root, key) = (info.prefix.clone(), info.root, info.key.clone());
+    let proof = run(&multi_tree, move |store| {
+        store.get_merkle_proof_at(prefix, root, key)
+    })
+    .await?;
+    log::info!(
+        "{:?}",
+        format!("[Get Merkle Proof At] info: {:?}, proof: {:?}", info, proof)
+    );
+    Ok(HttpResponse::Ok().json(proof))
+}
+
+/// The number of items of a requested page.
+fn page_limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT)
+}
+
+/// List the keys of a tree, ordered by their leaf keys.
+#[utoipa::path(
+    post,
+    path = "/keys",
+    tag = SMT_API,
+    request_body = ReqPage<String>,
+    responses(
+        (status = 200, description = "List the keys of a tree, ordered by their leaf keys.", body = [Page<SMTKey>]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn list_keys<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqPage<T>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, start_after, limit) = (
+        info.prefix.clone(),
+        info.start_after,
+        page_limit(info.limit),
+    );
+    let page = run(&multi_tree, move |store| {
+        store.keys(prefix, start_after, limit)
+    })
+    .await?;
+    log::info!(
+        "{:?}",
+        format!("[List Keys] info: {:?}, res: {:?}", info, page)
+    );
+    Ok(HttpResponse::Ok().json(page))
+}
+
+/// List the keys and values of a tree, ordered by their leaf keys.
+#[utoipa::path(
+    post,
+    path = "/entries",
+    tag = SMT_API,
+    request_body = ReqPage<String>,
+    responses(
+        (status = 200, description = "List the keys and values of a tree, ordered by their leaf keys.", body = [Page<KVPair<SMTKey, SMTValue>>]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn list_entries<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqPage<T>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (prefix, start_after, limit) = (
+        info.prefix.clone(),
+        info.start_after,
+        page_limit(info.limit),
+    );
+    let page = run(&multi_tree, move |store| {
+        store.entries(prefix, start_after, limit)
+    })
+    .await?;
+    log::info!(
+        "{:?}",
+        format!("[List Entries] info: {:?}, res: {:?}", info, page)
+    );
+    Ok(HttpResponse::Ok().json(page))
+}
+
+/// Begin a session of staged updates of a tree, returns the id of the session.
+/// Sessions that are not used for 10 minutes are dropped.
+#[utoipa::path(
+    post,
+    path = "/session/begin",
+    tag = SMT_API,
+    request_body = ReqByPrefix<String>,
+    responses(
+        (status = 200, description = "Begin a session of staged updates of a tree.", body = [u64]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn begin_session<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqByPrefix<T>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let prefix = info.prefix.clone();
+    let session = run(&multi_tree, move |store| store.begin_session(prefix)).await?;
+    log::info!(
+        "{:?}",
+        format!("[Begin Session] info: {:?}, session: {:?}", info, session)
+    );
+    Ok(HttpResponse::Ok().json(session))
+}
+
+/// Stage a round of updates in a session, entries with the default value are removed. Returns the staged root.
+#[utoipa::path(
+    post,
+    path = "/session/update",
+    tag = SMT_API,
+    request_body = ReqSessionUpdate<SMTKey, SMTValue>,
+    responses(
+        (status = 200, description = "Stage a round of updates in a session.", body = [H256]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 409, description = "The request conflicts with the state of the tree.", body = Error),
+        (status = 413, description = "The request body is too large.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn session_update<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    limits: web::Data<BatchLimits>,
+    info: web::Json<ReqSessionUpdate<SMTKey, SMTValue>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    check_batch(info.kvs.len(), &limits)?;
+    let info = info.into_inner();
+    let count = info.kvs.len();
+    let kvs = info.kvs.into_iter().map(|kv| (kv.key, kv.value)).collect();
+    let session = info.session;
+    let root = run(&multi_tree, move |store| store.session_update(session, kvs)).await?;
+    log::info!(
+        "{:?}",
+        format!(
+            "[Session Update] session: {:?}, entries: {}, root: {:?}",
+            info.session, count, root
+        )
+    );
+    Ok(HttpResponse::Ok().json(root))
+}
+
+/// Get the staged value of a key in a session.
+#[utoipa::path(
+    post,
+    path = "/session/value",
+    tag = SMT_API,
+    request_body = ReqSessionKey<SMTKey>,
+    responses(
+        (status = 200, description = "Get the staged value of a key in a session.", body = [SMTValue]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 409, description = "The request conflicts with the state of the tree.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn session_value<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqSessionKey<SMTKey>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (session, key) = (info.session, info.key.clone());
+    let value = run(&multi_tree, move |store| store.session_value(session, key)).await?;
+    log::info!(
+        "{:?}",
+        format!("[Session Value] info: {:?}, value: {:?}", info, value)
+    );
+    Ok(HttpResponse::Ok().json(value))
+}
+
+/// Get the Merkle proof of a key against the staged root of a session.
+#[utoipa::path(
+    post,
+    path = "/session/merkle_proof",
+    tag = SMT_API,
+    request_body = ReqSessionKey<SMTKey>,
+    responses(
+        (status = 200, description = "Get the Merkle proof of a key against the staged root of a session.", body = [Proof<SMTKey, SMTValue>]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 409, description = "The request conflicts with the state of the tree.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn session_merkle_proof<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqSessionKey<SMTKey>>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let (session, key) = (info.session, info.key.clone());
+    let proof = run(&multi_tree, move |store| {
+        store.session_merkle_proof(session, key)
+    })
+    .await?;
+    log::info!(
+        "{:?}",
+        format!(
+            "[Session Merkle Proof] info: {:?}, proof: {:?}",
+            info, proof
+        )
+    );
+    Ok(HttpResponse::Ok().json(proof))
+}
+
+/// Write the staged updates of a session as one version of its tree and close the session, returns the new root.
+/// Fails if the tree changed since the session began, the session is closed anyway.
+#[utoipa::path(
+    post,
+    path = "/session/commit",
+    tag = SMT_API,
+    request_body = ReqSession,
+    responses(
+        (status = 200, description = "Write the staged updates of a session.", body = [H256]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 409, description = "The request conflicts with the state of the tree.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn commit_session<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqSession>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let session = info.session;
+    let root = run(&multi_tree, move |store| store.commit_session(session)).await?;
+    log::info!(
+        "{:?}",
+        format!("[Commit Session] info: {:?}, root: {:?}", info, root)
+    );
+    Ok(HttpResponse::Ok().json(root))
+}
+
+/// Drop the staged updates of a session and close it.
+#[utoipa::path(
+    post,
+    path = "/session/rollback",
+    tag = SMT_API,
+    request_body = ReqSession,
+    responses(
+        (status = 200, description = "Drop the staged updates of a session.", body = [bool]),
+        (status = 400, description = "The request is invalid.", body = Error),
+        (status = 404, description = "The tree, root or session is unknown.", body = Error),
+        (status = 500, description = "The database failed or is corrupted.", body = Error),
+        (status = 503, description = "The server is busy, retry later.", body = Error)
+    )
+)]
+pub async fn rollback_session<S, T>(
+    multi_tree: web::Data<StorePool<S>>,
+    info: web::Json<ReqSession>,
+) -> Result<HttpResponse, Error>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let session = info.session;
+    run(&multi_tree, move |store| store.rollback_session(session)).await?;
+    log::info!("{:?}", format!("[Rollback Session] info: {:?}", info));
+    Ok(HttpResponse::Ok().json(true))
+}
+
+/// Run a store operation on the pool, a full queue is a 503.  
+/// The operation is queued when this is called, like [`StorePool::run`], so a handler may await other work
+/// (such as the upload of `/ingest`) that the operation consumes.
+fn run<S, R>(
+    pool: &StorePool<S>,
+    f: impl FnOnce(&S) -> Result<R, Error> + Send + 'static,
+) -> impl Future<Output = Result<R, Error>>
+where
+    S: Send + Sync + 'static,
+    R: Send + 'static,
+{
+    let result = pool.run(f);
+    async move { Ok(result.await??) }
+}
+
+/// Reject a batch of more than `limits.max_entries` entries.
+fn check_batch(len: usize, limits: &BatchLimits) -> Result<(), Error> {
+    if len > limits.max_entries {
+        return Err(Error::bad_request(format!(
+            "Too many entries: {}, at most {} per batch",
+            len, limits.max_entries
+        )));
+    }
+    Ok(())
+}
+
+/// Invalid JSON bodies are answered with an [`Error`] like all other errors.
+fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(json_error)
+}
+
+/// Register all APIs for the backend `S`, batch requests are bounded by `limits`.
+pub fn config<S, T>(cfg: &mut web::ServiceConfig, limits: BatchLimits)
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    cfg.app_data(web::Data::new(limits))
+        .app_data(json_config())
+        .app_data(web::QueryConfig::default().error_handler(query_error))
+        .service(
+            web::resource("/update_batch")
+                .app_data(json_config().limit(limits.max_body_size))
+                .route(web::post().to(update_batch::<S, T>)),
+        )
+        .service(
+            web::resource("/next_root_batch")
+                .app_data(json_config().limit(limits.max_body_size))
+                .route(web::post().to(get_next_root_batch::<S, T>)),
+        )
+        .service(
+            web::resource("/session/update")
+                .app_data(json_config().limit(limits.max_body_size))
+                .route(web::post().to(session_update::<S, T>)),
+        )
+        .route("/update", web::post().to(update_value::<S, T>))
+        .route("/ingest", web::post().to(ingest::<S, T>))
+        .route("/value", web::post().to(get_value::<S, T>))
+        .route("/merkle_proof", web::post().to(get_merkle_proof::<S, T>))
+        .route(
+            "/batch_merkle_proof",
+            web::post().to(get_batch_merkle_proof::<S, T>),
+        )
+        .route("/next_root", web::post().to(get_next_root::<S, T>))
+        .route("/root", web::post().to(get_root::<S, T>))
+        .route("/verify", web::post().to(verify))
+        .route(
+            "/verify_non_membership",
+            web::post().to(verify_non_membership),
+        )
+        .route("/verify_batch", web::post().to(verify_batch))
+        .route("/remove", web::post().to(remove_value::<S, T>))
+        .route("/clear", web::post().to(clear::<S, T>))
+        .route("/trees", web::post().to(list_trees::<S, T>))
+        .route("/trees/create", web::post().to(create_tree::<S, T>))
+        .route("/trees/describe", web::post().to(describe_tree::<S, T>))
+        .route("/trees/delete", web::post().to(delete_tree::<S, T>))
+        .route("/root_history", web::post().to(root_history::<S, T>))
+        .route("/value_at", web::post().to(get_value_at::<S, T>))
+        .route(
+            "/merkle_proof_at",
+            web::post().to(get_merkle_proof_at::<S, T>),
+        )
+        .route("/keys", web::post().to(list_keys::<S, T>))
+        .route("/entries", web::post().to(list_entries::<S, T>))
+        .route("/session/begin", web::post().to(begin_session::<S, T>))
+        .route("/session/value", web::post().to(session_value::<S, T>))
+        .route(
+            "/session/merkle_proof",
+            web::post().to(session_merkle_proof::<S, T>),
+        )
+        .route("/session/commit", web::post().to(commit_session::<S, T>))
+        .route(
+            "/session/rollback",
+            web::post().to(rollback_session::<S, T>),
+        );
+}
+
+/// Log to stdout and to files in `log_path`, the files are rotated daily.  
+/// Keep the returned handle alive for as long as the server runs.
+pub fn init_logger(log_path: &str, log_level: &str) -> Result<LoggerHandle, FlexiLoggerError> {
+    Logger::try_with_str(log_level)?
+        .log_to_file(FileSpec::default().directory(log_path))
+        .rotate(
+            Criterion::Age(Age::Day),
+            Naming::TimestampsDirect,
+            Cleanup::Never,
+        )
+        .append()
+        .log_to_stdout()
+        .start()
+}
+
+/// Serve the APIs of the store of `multi_tree` until the server stops or CTRL-C is received.
+pub async fn serve<S, T>(
+    multi_tree: StorePool<S>,
+    host: &str,
+    port: u16,
+    limits: BatchLimits,
+) -> std::io::Result<()>
+where
+    S: MSS<T, SMTKey, SMTValue, Keccak256Hasher> + Send + Sync + 'static,
+    T: TreeId,
+{
+    let multi_tree = web::Data::new(multi_tree);
+    let app = HttpServer::new(move || {
+        App::new()
+            .app_data(multi_tree.clone())
+            .configure(|cfg| config::<S, T>(cfg, limits))
+            .service(
+                SwaggerUi::new("/swagger-ui/{_:.*}")
+                    .url("/api-docs/openapi.json", ApiDoc::openapi()),
+            )
+    })
+    .shutdown_timeout(30)
+    .bind((host, port))?
+    .run();
+
+    let graceful_shutdown = async {
+        ctrl_c().await.expect("Failed to listen for event");
+        println!("Received CTRL-C, shutting down gracefully...");
+    };
+
+    let graceful_shutdown_task = tokio::spawn(graceful_shutdown);
+
+    tokio::select! {
+        _ = app => Ok(()),
+        _ = graceful_shutdown_task => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::MultiSMTStore,
+        migration::open_database,
+        store::{tree_prefix, COL_TREES},
+    };
+    use actix_web::{
+        body::MessageBody,
+        dev::{Payload, Service, ServiceResponse},
+        error::PayloadError,
+        http::StatusCode,
+        test,
+    };
+    use futures_util::{stream, Stream};
+    use serde_json::{json, Value};
+    use std::{pin::Pin, time::Duration};
+    use tempfile::{tempdir, TempDir};
+    use tokio::sync::mpsc;
+
+    type Store = MultiSMTStore<SMTKey, SMTValue, Keccak256Hasher>;
+
+    fn pool(threads: usize, queue_size: usize) -> (TempDir, web::Data<StorePool<Store>>) {
+        let dir = tempdir().unwrap();
+        let store = Store::open(dir.path()).unwrap();
+        let pool = StorePool::new(store, threads, queue_size).unwrap();
+        (dir, web::Data::new(pool))
+    }
+
+    fn ndjson(count: usize) -> String {
+        (0..count)
+            .map(|i| {
+                format!(
+                    "{{\"address\": \"{}\", \"nonce\": {}, \"balance\": \"1\"}}\n",
+                    i, i
+                )
+            })
+            .collect()
+    }
+
+    fn post(uri: &str, body: Value) -> test::TestRequest {
+        test::TestRequest::post().uri(uri).set_json(body)
+    }
+
+    async fn call<S, R, B>(app: &S, req: R) -> (StatusCode, Value)
+    where
+        S: Service<R, Response = ServiceResponse<B>, Error = actix_web::Error>,
+        B: MessageBody,
+    {
+        let resp = test::call_service(app, req).await;
+        (resp.status(), test::read_body_json(resp).await)
+    }
+
+    #[actix_web::test]
+    async fn test_errors() {
+        let dir = tempdir().unwrap();
+        // a registry entry that cannot be decoded
+        let db = open_database(dir.path()).unwrap();
+        let mut tx = db.transaction();
+        tx.put(COL_TREES, &tree_prefix("broken"), &[0xff]);
+        db.write(tx).unwrap();
+        drop(db);
+        let store = Store::open(dir.path()).unwrap().with_implicit_create(false);
+        let pool = web::Data::new(StorePool::new(store, 1, 4).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(pool)
+                .configure(|cfg| config::<Store, String>(cfg, BatchLimits::default())),
+        )
+        .await;
+        let update =
+            |value: u64| json!({"prefix": "a", "address": "1", "nonce": value, "balance": "1"});
+
+        // invalid bodies and query strings
+        let req = test::TestRequest::post()
+            .uri("/root")
+            .insert_header(("content-type", "application/json"))
+            .set_payload("{")
+            .to_request();
+        let (status, body) = call(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "bad_request");
+        assert_eq!(body["message"], "Invalid request body");
+        assert!(body["details"].is_string());
+        let req = test::TestRequest::post().uri("/ingest").to_request();
+        let (status, body) = call(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "Invalid query string");
+
+        // errors of the store
+        assert_eq!(
+            call(&app, post("/update", update(1)).to_request()).await,
+            (
+                StatusCode::NOT_FOUND,
+                json!({"code": "unknown_tree", "message": "Unknown tree: a", "details": null})
+            )
+        );
+        let (status, _) = call(
+            &app,
+            post("/trees/create", json!({"prefix": "a"})).to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            call(
+                &app,
+                post("/trees/create", json!({"prefix": "a"})).to_request()
+            )
+            .await,
+            (
+                StatusCode::CONFLICT,
+                json!({"code": "conflict", "message": "Tree already exists: a", "details": null})
+            )
+        );
+        let (status, body) = call(
+            &app,
+            post("/trees/create", json!({"prefix": "b", "hasher": "md5"})).to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "Invalid request body");
+        assert_eq!(
+            call(
+                &app,
+                post("/session/value", json!({"session": 4, "address": "1"})).to_request()
+            )
+            .await,
+            (
+                StatusCode::NOT_FOUND,
+                json!({"code": "not_found", "message": "Unknown session: 4", "details": null})
+            )
+        );
+        let (status, body) = call(
+            &app,
+            post("/trees/describe", json!({"prefix": "broken"})).to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "corruption");
+
+        // a session of a tree that changed since it began
+        let (status, session) = call(
+            &app,
+            post("/session/begin", json!({"prefix": "a"})).to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, post("/update", update(2)).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = call(
+            &app,
+            post(
+                "/session/value",
+                json!({"session": session, "address": "1"}),
+            )
+            .to_request(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "conflict");
+    }
+
+    fn kv(address: u64, nonce: u64) -> Value {
+        json!({"address": address.to_string(), "nonce": nonce, "balance": nonce.to_string()})
+    }
+
+    #[actix_web::test]
+    async fn test_update_batch() {
+        let (_dir, pool) = pool(1, 4);
+        let limits = BatchLimits {
+            max_entries: 3,
+            max_body_size: 512,
+            ..BatchLimits::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .configure(|cfg| config::<Store, String>(cfg, limits)),
+        )
+        .await;
+        let root = || json!(pool.store().get_root("tree".to_string()).unwrap());
+
+        let batch = json!({"prefix": "tree", "kvs": [kv(1, 1), kv(2, 2), kv(3, 3)]});
+        let (status, body) = call(&app, post("/update_batch", batch).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, root());
+        // the last entry of a key wins, a default value removes its key
+        let batch = json!({"prefix": "tree", "kvs": [kv(1, 4), kv(2, 0), kv(1, 5)]});
+        let (status, body) = call(&app, post("/update_batch", batch).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, root());
+        let store = pool.store();
+        assert_eq!(
+            store
+                .get_value(
+                    "tree".to_string(),
+                    SMTKey {
+                        address: "1".into()
+                    }
+                )
+                .unwrap()
+                .nonce,
+            5
+        );
+        assert_eq!(
+            store
+                .get_value(
+                    "tree".to_string(),
+                    SMTKey {
+                        address: "2".into()
+                    }
+                )
+                .unwrap(),
+            SMTValue::default()
+        );
+
+        let before = root();
+        let batch = json!({"prefix": "tree", "kvs": [kv(4, 4), kv(5, 5), kv(6, 6), kv(7, 7)]});
+        let (status, body) = call(&app, post("/update_batch", batch).to_request()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "Too many entries: 4, at most 3 per batch");
+        let batch = json!({"prefix": "x".repeat(512), "kvs": [kv(4, 4)]});
+        let (status, body) = call(&app, post("/update_batch", batch).to_request()).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "too_large");
+        // rejected batches write nothing
+        assert_eq!(root(), before);
+    }
+
+    #[actix_web::test]
+    async fn test_next_root_batch() {
+        let (_dir, pool) = pool(1, 4);
+        let limits = BatchLimits {
+            max_entries: 3,
+            ..BatchLimits::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .configure(|cfg| config::<Store, String>(cfg, limits)),
+        )
+        .await;
+        let root = || json!(pool.store().get_root("tree".to_string()).unwrap());
+
+        let batch = json!({"prefix": "tree", "kvs": [kv(1, 1), kv(2, 2)]});
+        let (status, _) = call(&app, post("/update_batch", batch).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        let before = root();
+
+        // inserted, updated and removed keys, nothing is written
+        let batch = json!({"prefix": "tree", "kvs": [kv(3, 3), kv(1, 4), kv(2, 0)]});
+        let (status, next) = call(&app, post("/next_root_batch", batch.clone()).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(next, before);
+        assert_eq!(root(), before);
+        let (status, body) = call(&app, post("/update_batch", batch).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, next);
+
+        let batch = json!({"prefix": "tree", "kvs": [kv(4, 4), kv(5, 5), kv(6, 6), kv(7, 7)]});
+        let (status, body) = call(&app, post("/next_root_batch", batch).to_request()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "Too many entries: 4, at most 3 per batch");
+    }
+
+    #[actix_web::test]
+    async fn test_busy() {
+        let (_dir, pool) = pool(1, 1);
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .configure(|cfg| config::<Store, String>(cfg, BatchLimits::default())),
+        )
+        .await;
+        let (started_tx, started) = std::sync::mpsc::channel();
+        let (release, release_rx) = std::sync::mpsc::channel::<()>();
+        let running = pool.run(move |_| {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+        started.recv().unwrap();
+        let queued = pool.run(|_| ());
+
+        // the thread is busy and the queue is full
+        let req = post("/root", json!({"prefix": "tree"})).to_request();
+        assert_eq!(
+            call(&app, req).await,
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                json!({"code": "busy", "message": "The store is busy, retry later", "details": null})
+            )
+        );
+
+        release.send(()).unwrap();
+        running.await.unwrap();
+        queued.await.unwrap();
+        let req = post("/root", json!({"prefix": "tree"})).to_request();
+        let (status, _) = call(&app, req).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_tree_locks() {
+        let (_dir, pool) = pool(4, 16);
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .configure(|cfg| config::<Store, String>(cfg, BatchLimits::default())),
+        )
+        .await;
+        let update = |prefix: &str, nonce: u64| {
+            post(
+                "/update",
+                json!({"prefix": prefix, "address": "1", "nonce": nonce, "balance": "1"}),
+            )
+            .to_request()
+        };
+        let value = || post("/value", json!({"prefix": "a", "address": "1"})).to_request();
+        fn done<F: Future>(f: F) -> tokio::time::Timeout<F> {
+            tokio::time::timeout(Duration::from_secs(10), f)
+        }
+        async fn waits<F: Future>(f: F) -> bool {
+            tokio::time::timeout(Duration::from_millis(200), f)
+                .await
+                .is_err()
+        }
+        let (status, _) = call(&app, update("a", 1)).await;
+        assert_eq!(status, StatusCode::OK);
+        let lock = pool.store().locks.get(&tree_prefix("a"));
+
+        // a long read of tree a: other reads of it and writes of other trees go on, a write of it waits
+        let read = lock.read();
+        let (status, body) = done(call(&app, value())).await.expect("the read waited");
+        assert_eq!((status, body["nonce"].clone()), (StatusCode::OK, json!(1)));
+        let (status, _) = done(call(&app, update("b", 1)))
+            .await
+            .expect("the write of b waited");
+        assert_eq!(status, StatusCode::OK);
+        let write = call(&app, update("a", 2));
+        tokio::pin!(write);
+        assert!(
+            waits(&mut write).await,
+            "the write of a did not wait for the read"
+        );
+        drop(read);
+        let (status, _) = done(write)
+            .await
+            .expect("the write of a waited after the read");
+        assert_eq!(status, StatusCode::OK);
+
+        // a long write of tree a: reads of it wait, reads of other trees go on
+        let write = lock.write();
+        let read = call(&app, value());
+        tokio::pin!(read);
+        assert!(
+            waits(&mut read).await,
+            "the read of a did not wait for the write"
+        );
+        let req = post("/root", json!({"prefix": "b"})).to_request();
+        let (status, _) = done(call(&app, req)).await.expect("the read of b waited");
+        assert_eq!(status, StatusCode::OK);
+        drop(write);
+        let (status, body) = done(read)
+            .await
+            .expect("the read of a waited after the write");
+        assert_eq!((status, body["nonce"].clone()), (StatusCode::OK, json!(2)));
+    }
+
+    #[actix_web::test]
+    async fn test_ingest() {
+        let (_dir, pool) = pool(1, 4);
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .configure(|cfg| config::<Store, String>(cfg, BatchLimits::default())),
+        )
+        .await;
+
+        let count = 3000;
+        let req = test::TestRequest::post()
+            .uri("/ingest?prefix=tree")
+            .insert_header(("content-type", "application/x-ndjson"))
+            .set_payload(ndjson(count))
+            .to_request();
+        let resp = tokio::time::timeout(Duration::from_secs(60), test::call_service(&app, req))
+            .await
+            .expect("the ingest stalled");
+        assert_eq!(resp.status(), StatusCode::OK);
+        let info: TreeInfo = test::read_body_json(resp).await;
+        assert_eq!(info.meta.leaf_count, count as u64);
+        assert_eq!(
+            info.root,
+            pool.store().get_root("tree".to_string()).unwrap()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_ingest_too_large() {
+        let (_dir, pool) = pool(1, 4);
+        let limits = BatchLimits {
+            max_ingest_size: 1024,
+            ..BatchLimits::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(pool.clone())
+                .configure(|cfg| config::<Store, String>(cfg, limits)),
+        )
+        .await;
+        let upload = |body: String| {
+            test::TestRequest::post()
+                .uri("/ingest?prefix=tree")
+                .insert_header(("content-type", "application/x-ndjson"))
+                .set_payload(body)
+                .to_request()
+        };
+
+        let body = ndjson(100);
+        assert!(body.len() > 1024);
+        let (status, body) = call(&app, upload(body)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "too_large");
+        assert_eq!(body["message"], "Upload too large, at most 1024 bytes");
+        // nothing is ingested
+        assert_eq!(
+            pool.store().get_root("tree".to_string()).unwrap(),
+            H256::zero()
+        );
+
+        let (status, body) = call(&app, upload(ndjson(10))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["leaf_count"], 10);
+    }
+
+    #[actix_web::test]
+    async fn test_ingest_upload() {
+        let (_dir, pool) = pool(1, 1);
+        let app = test::init_service(
+            App::new()
+                .app_data(pool)
+                .configure(|cfg| config::<Store, String>(cfg, BatchLimits::default())),
+        )
+        .await;
+
+        let (chunks, rx) = mpsc::unbounded_channel::<web::Bytes>();
+        let body: Pin<Box<dyn Stream<Item = Result<web::Bytes, PayloadError>>>> =
+            Box::pin(stream::unfold(rx, |mut rx| async move {
+                rx.recv().await.map(|chunk| (Ok(chunk), rx))
+            }));
+        let (req, _) = test::TestRequest::post()
+            .uri("/ingest?prefix=tree")
+            .insert_header(("content-type", "application/x-ndjson"))
+            .to_request()
+            .replace_payload(Payload::from(body));
+        let upload = test::call_service(&app, req);
+        let app = &app;
+        let requests = async move {
+            chunks.send(ndjson(10).into()).unwrap();
+            // the upload is still being received, it holds neither a tree nor the only thread of the pool
+            let req = post("/root", json!({"prefix": "other"})).to_request();
+            let (status, _) = tokio::time::timeout(Duration::from_secs(10), call(app, req))
+                .await
+                .expect("the upload holds the pool");
+            assert_eq!(status, StatusCode::OK);
+            drop(chunks);
+        };
+        let (resp, ()) = tokio::join!(upload, requests);
+        assert_eq!(resp.status(), StatusCode::OK);
+        let info: TreeInfo = test::read_body_json(resp).await;
+        assert_eq!(info.meta.leaf_count, 10);
+    }
+}
@@ -0,0 +1,443 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sessions of staged updates of a tree, for dry runs and speculative updates.  
+//! A session stages several rounds of updates in an [`OverlayStore`], which keeps the changed nodes in
+//! memory on top of the store of the tree. Values and proofs are read against the staged state, while
+//! everyone else still sees the committed tree. Committing a session writes all staged changes as one
+//! version of the tree, rolling it back drops them.  
+//! A session can only be used while the tree has not changed since the session began, afterwards it
+//! can only be rolled back. Sessions that are not used for [`SESSION_TTL`] are dropped.
+
+use crate::{
+    batch::BufferedStore,
+    error::{Error, ErrorCode},
+    hashers::Leaf,
+    registry::TreeMeta,
+    traits::merkle_proof,
+};
+use codec::Encode;
+use scale_info::TypeInfo;
+use smt_primitives::{kv::LeafHash, verify::Proof};
+use sparse_merkle_tree::{
+    error::Error as SMTError,
+    traits::{Hasher, StoreReadOps, StoreWriteOps},
+    BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    sync::{Arc, Mutex, MutexGuard, TryLockError},
+    time::{Duration, Instant},
+};
+
+/// The id of a session.
+pub type SessionId = u64;
+
+/// How long a session is kept without being used.
+pub const SESSION_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// The largest number of open sessions of a store.
+pub const MAX_SESSIONS: usize = 64;
+
+/// The number of low bits of a session id that hold the tag of its store, see [`Sessions::tagged`].
+pub const SESSION_TAG_BITS: u32 = 2;
+
+/// A store that keeps all changes in memory on top of the store `S`, which is never written.
+pub struct OverlayStore<V, S> {
+    inner: S,
+    branches: HashMap<BranchKey, Option<BranchNode>>,
+    leaves: HashMap<H256, Option<V>>,
+}
+
+impl<V, S> OverlayStore<V, S> {
+    pub fn new(inner: S) -> Self {
+        OverlayStore {
+            inner,
+            branches: Default::default(),
+            leaves: Default::default(),
+        }
+    }
+
+    /// The number of changed nodes.
+    pub fn len(&self) -> usize {
+        self.branches.len() + self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Move all changes to the store below, where they are buffered until it is committed.
+    pub fn flush(&mut self) -> Result<&mut S, Error>
+    where
+        S: StoreWriteOps<V>,
+    {
+        for (key, branch) in self.branches.drain() {
+            match branch {
+                Some(branch) => self.inner.insert_branch(key, branch)?,
+                None => self.inner.remove_branch(&key)?,
+            }
+        }
+        for (key, leaf) in self.leaves.drain() {
+            match leaf {
+                Some(leaf) => self.inner.insert_leaf(key, leaf)?,
+                None => self.inner.remove_leaf(&key)?,
+            }
+        }
+        Ok(&mut self.inner)
+    }
+}
+
+impl<V, S> StoreReadOps<V> for OverlayStore<V, S>
+where
+    V: Clone,
+    S: StoreReadOps<V>,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, SMTError> {
+        match self.branches.get(branch_key) {
+            Some(branch) => Ok(branch.clone()),
+            None => self.inner.get_branch(branch_key),
+        }
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, SMTError> {
+        match self.leaves.get(leaf_key) {
+            Some(leaf) => Ok(leaf.clone()),
+            None => self.inner.get_leaf(leaf_key),
+        }
+    }
+}
+
+impl<V, S> StoreWriteOps<V> for OverlayStore<V, S> {
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), SMTError> {
+        self.branches.insert(node_key, Some(branch));
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), SMTError> {
+        self.leaves.insert(leaf_key, Some(leaf));
+        Ok(())
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), SMTError> {
+        self.branches.insert(node_key.clone(), None);
+        Ok(())
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), SMTError> {
+        self.leaves.insert(*leaf_key, None);
+        Ok(())
+    }
+}
+
+/// The staged updates of one tree.
+pub struct Session<V, H, S> {
+    name: String,
+    base_root: H256,
+    tree: SparseMerkleTree<H, Leaf<V, H>, OverlayStore<Leaf<V, H>, S>>,
+    index: BTreeMap<H256, Option<Vec<u8>>>,
+}
+
+impl<V, H, S> Session<V, H, S>
+where
+    V: LeafHash + Default + Clone,
+    H: Hasher + Default,
+    S: BufferedStore<Leaf<V, H>> + StoreReadOps<Leaf<V, H>>,
+{
+    /// Begin a session on the tree `name`, whose store is `store`.
+    pub fn new(name: impl Into<String>, store: S) -> Result<Self, Error> {
+        let tree = SparseMerkleTree::new_with_store(OverlayStore::new(store))?;
+        Ok(Session {
+            name: name.into(),
+            base_root: *tree.root(),
+            tree,
+            index: Default::default(),
+        })
+    }
+
+    /// The name of the tree.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The root of the tree when the session began.
+    pub fn base_root(&self) -> H256 {
+        self.base_root
+    }
+
+    /// Fail if `current_root`, the committed root of the tree, is not the root the session began on.  
+    /// Nodes that are not staged are read from the store of the tree, so the staged tree is only
+    /// consistent while the committed tree is still at [`Session::base_root`].
+    pub fn check_base(&self, current_root: H256) -> Result<(), Error> {
+        if current_root != self.base_root {
+            return Err(conflict(&self.name));
+        }
+        Ok(())
+    }
+
+    /// The staged tree.
+    pub fn tree(&self) -> &SparseMerkleTree<H, Leaf<V, H>, OverlayStore<Leaf<V, H>, S>> {
+        &self.tree
+    }
+
+    /// Stage a round of updates, the last entry of a key wins and entries with the default value are
+    /// deletions, like [`crate::traits::MSS::update_all`]. Returns the staged root.
+    pub fn update<K: LeafHash + Encode>(&mut self, kvs: Vec<(K, V)>) -> Result<H256, Error> {
+        let kvs = kvs
+            .into_iter()
+            .map(|(k, v)| {
+                let path = k.leaf_hash::<H>();
+                let indexed = (!v.leaf_hash::<H>().is_zero()).then(|| k.encode());
+                self.index.insert(path, indexed);
+                (path, Leaf::new(v))
+            })
+            .collect();
+        Ok(*self.tree.update_all(kvs)?)
+    }
+
+    /// The staged value of a key.
+    pub fn get_value<K: LeafHash>(&self, key: &K) -> Result<V, Error> {
+        Ok(self.tree.get(&key.leaf_hash::<H>())?.into_inner())
+    }
+
+    /// The Merkle proof of a key against the staged root.
+    pub fn merkle_proof<K>(&self, key: K) -> Result<Proof<K, V>, Error>
+    where
+        K: LeafHash + Clone + Debug + TypeInfo,
+        V: Debug + TypeInfo,
+    {
+        merkle_proof(&self.tree, key)
+    }
+
+    /// Write the staged changes as one version of the tree, if its root is still `base_root`.
+    /// `current_root` is the committed root of the tree and `meta` its registry entry.
+    pub fn commit(
+        mut self,
+        current_root: H256,
+        mut meta: TreeMeta,
+        history_depth: u64,
+    ) -> Result<H256, Error> {
+        self.check_base(current_root)?;
+        let root = *self.tree.root();
+        let store = self.tree.store_mut().flush()?;
+        for (path, key) in self.index {
+            store.index_key(path, key);
+        }
+        meta.touch(store.leaf_delta());
+        store.commit_version(&meta, root, history_depth)?;
+        Ok(root)
+    }
+}
+
+/// A session and when it was last used, the session is taken out when it is closed.
+struct Slot<V, H, S> {
+    used: Instant,
+    session: Option<Session<V, H, S>>,
+}
+
+type SharedSlot<V, H, S> = Arc<Mutex<Slot<V, H, S>>>;
+
+/// The open sessions of a store.  
+/// Each session has its own lock, the map of sessions is only locked to find a session, so sessions
+/// can be used concurrently.
+pub struct Sessions<V, H, S> {
+    tag: u64,
+    sessions: Mutex<HashMap<SessionId, SharedSlot<V, H, S>>>,
+}
+
+impl<V, H, S> Default for Sessions<V, H, S> {
+    fn default() -> Self {
+        Self::tagged(0)
+    }
+}
+
+impl<V, H, S> Sessions<V, H, S> {
+    /// The sessions of a store whose session ids end with the bits of `tag`, so that the sessions of
+    /// stores sharing a database can be told apart, see [`crate::hashers::PerTreeHasher`].
+    pub fn tagged(tag: u64) -> Self {
+        Sessions {
+            tag,
+            sessions: Default::default(),
+        }
+    }
+
+    /// Keep a new session under a random id, sessions that expired are dropped first.
+    pub fn insert(&self, session: Session<V, H, S>) -> Result<SessionId, Error> {
+        let mut sessions = self.lock()?;
+        let now = Instant::now();
+        sessions.retain(|_, slot| match slot.try_lock() {
+            Ok(slot) => slot.session.is_some() && now.duration_since(slot.used) < SESSION_TTL,
+            // in use
+            Err(TryLockError::WouldBlock) => true,
+            Err(TryLockError::Poisoned(_)) => false,
+        });
+        if sessions.len() >= MAX_SESSIONS {
+            return Err(ErrorCode::Busy
+                .store_error(format!("Too many open sessions, at most {}", MAX_SESSIONS)));
+        }
+        let id = loop {
+            let id = (rand::random::<u64>() << SESSION_TAG_BITS) | self.tag;
+            if !sessions.contains_key(&id) {
+                break id;
+            }
+        };
+        let slot = Slot {
+            used: now,
+            session: Some(session),
+        };
+        sessions.insert(id, Arc::new(Mutex::new(slot)));
+        Ok(id)
+    }
+
+    /// Run `f` on an open session, other sessions are not blocked meanwhile.
+    pub fn with<R>(
+        &self,
+        id: SessionId,
+        f: impl FnOnce(&mut Session<V, H, S>) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let slot = self.slot(id)?;
+        let mut slot = slot
+            .lock()
+            .map_err(|e| ErrorCode::Internal.store_error(e))?;
+        if slot.used.elapsed() >= SESSION_TTL {
+            return Err(unknown_session(id));
+        }
+        slot.used = Instant::now();
+        let session = slot.session.as_mut().ok_or_else(|| unknown_session(id))?;
+        f(session)
+    }
+
+    /// Close a session, it can't be used anymore. Waits until the session is not in use.
+    pub fn remove(&self, id: SessionId) -> Result<Session<V, H, S>, Error> {
+        let slot = self
+            .lock()?
+            .remove(&id)
+            .ok_or_else(|| unknown_session(id))?;
+        let mut slot = slot
+            .lock()
+            .map_err(|e| ErrorCode::Internal.store_error(e))?;
+        if slot.used.elapsed() >= SESSION_TTL {
+            return Err(unknown_session(id));
+        }
+        slot.session.take().ok_or_else(|| unknown_session(id))
+    }
+
+    fn slot(&self, id: SessionId) -> Result<SharedSlot<V, H, S>, Error> {
+        self.lock()?
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| unknown_session(id))
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<SessionId, SharedSlot<V, H, S>>>, Error> {
+        self.sessions
+            .lock()
+            .map_err(|e| ErrorCode::Internal.store_error(e))
+    }
+}
+
+/// The error of using a session that is unknown, closed or expired.
+pub fn unknown_session(id: SessionId) -> Error {
+    ErrorCode::NotFound.store_error(format!("Unknown session: {}", id))
+}
+
+/// The error of using a session of a tree that changed since the session began.
+pub fn conflict(name: &str) -> Error {
+    ErrorCode::Conflict.store_error(format!(
+        "Tree {} changed since the session began, the session can only be rolled back",
+        name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smt_primitives::{keccak_hasher::Keccak256Hasher, kv::SMTValue};
+    use sparse_merkle_tree::{default_store::DefaultStore, merge::MergeValue};
+
+    #[test]
+    fn test_overlay_store() {
+        let mut inner = DefaultStore::<SMTValue>::default();
+        let leaf1_key: H256 = [1u8; 32].into();
+        let leaf2_key: H256 = [2u8; 32].into();
+        let leaf = |nonce| SMTValue { nonce, balance: 1 };
+        let branch_key = BranchKey::new(100, [3u8; 32].into());
+        let branch = BranchNode {
+            left: MergeValue::from_h256([4u8; 32].into()),
+            right: MergeValue::zero(),
+        };
+        inner.insert_leaf(leaf1_key, leaf(1)).unwrap();
+        inner
+            .insert_branch(branch_key.clone(), branch.clone())
+            .unwrap();
+
+        let mut overlay = OverlayStore::new(inner);
+        assert!(overlay.is_empty());
+        // reads fall through to the store below
+        assert_eq!(overlay.get_leaf(&leaf1_key).unwrap(), Some(leaf(1)));
+        assert_eq!(
+            overlay.get_branch(&branch_key).unwrap(),
+            Some(branch.clone())
+        );
+
+        overlay.insert_leaf(leaf1_key, leaf(2)).unwrap();
+        overlay.insert_leaf(leaf2_key, leaf(3)).unwrap();
+        overlay.remove_branch(&branch_key).unwrap();
+        assert_eq!(overlay.len(), 3);
+        assert_eq!(overlay.get_leaf(&leaf1_key).unwrap(), Some(leaf(2)));
+        assert_eq!(overlay.get_leaf(&leaf2_key).unwrap(), Some(leaf(3)));
+        assert_eq!(overlay.get_branch(&branch_key).unwrap(), None);
+        // the store below is not changed before a flush
+        assert_eq!(overlay.inner.get_leaf(&leaf1_key).unwrap(), Some(leaf(1)));
+
+        let inner = overlay.flush().unwrap();
+        assert_eq!(inner.get_leaf(&leaf1_key).unwrap(), Some(leaf(2)));
+        assert_eq!(inner.get_leaf(&leaf2_key).unwrap(), Some(leaf(3)));
+        assert_eq!(inner.get_branch(&branch_key).unwrap(), None);
+        assert!(overlay.is_empty());
+    }
+
+    #[test]
+    fn test_overlay_tree() {
+        let leaves = (0..50u64)
+            .map(|i| {
+                let value = SMTValue {
+                    nonce: i,
+                    balance: 1,
+                };
+                (H256::from([i as u8; 32]), value)
+            })
+            .collect::<Vec<_>>();
+        let mut tree = SparseMerkleTree::<Keccak256Hasher, SMTValue, _>::new_with_store(
+            OverlayStore::new(DefaultStore::<SMTValue>::default()),
+        )
+        .unwrap();
+        let mut expected =
+            SparseMerkleTree::<Keccak256Hasher, SMTValue, DefaultStore<_>>::default();
+        tree.update_all(leaves[..30].to_vec()).unwrap();
+        tree.update_all(leaves[20..].to_vec()).unwrap();
+        expected.update_all(leaves.clone()).unwrap();
+        assert_eq!(tree.root(), expected.root());
+
+        // flushed nodes are the nodes of the tree
+        let inner = tree.store_mut().flush().unwrap().clone();
+        let flushed =
+            SparseMerkleTree::<Keccak256Hasher, SMTValue, _>::new_with_store(inner).unwrap();
+        assert_eq!(flushed.root(), tree.root());
+    }
+}
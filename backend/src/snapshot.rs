@@ -0,0 +1,283 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshots of whole trees, to move a tree between machines or between storage backends.  
+//! A snapshot file is the magic bytes [`SNAPSHOT_MAGIC`], a SCALE-encoded [`SnapshotHeader`] and
+//! `count` SCALE-encoded `(key, value)` pairs ordered by their leaf keys, the value as the bytes the
+//! backends store. Importing a snapshot rebuilds the tree and only commits it if its root is the
+//! recorded one.
+
+use crate::error::{Error, ErrorCode};
+use codec::{Decode, Encode, Input, IoReader};
+use serde::{Deserialize, Serialize};
+use sparse_merkle_tree::H256;
+use std::io::{Read, Write};
+use utoipa::ToSchema;
+
+/// The first bytes of every snapshot file.
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"SMTS";
+/// The current snapshot format version.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The header of a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize, ToSchema)]
+pub struct SnapshotHeader {
+    /// The format version, see [`SNAPSHOT_VERSION`].
+    pub version: u32,
+    /// The name of the exported tree.
+    pub name: String,
+    /// The hasher of the tree, see [`crate::registry::HasherName`].
+    pub hasher: String,
+    /// The root of the tree.
+    pub root: H256,
+    /// The number of entries.
+    pub count: u64,
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::store(e)
+}
+
+/// Write the magic bytes and the header.
+pub fn write_header(writer: &mut dyn Write, header: &SnapshotHeader) -> Result<(), Error> {
+    writer.write_all(&SNAPSHOT_MAGIC).map_err(io_error)?;
+    writer.write_all(&header.encode()).map_err(io_error)
+}
+
+/// Write one entry.
+pub fn write_entry<K: Encode>(
+    writer: &mut dyn Write,
+    key: &K,
+    value: Vec<u8>,
+) -> Result<(), Error> {
+    writer.write_all(&(key, value).encode()).map_err(io_error)
+}
+
+fn invalid(e: codec::Error) -> Error {
+    ErrorCode::BadRequest.store_error(format!("Invalid snapshot: {}", e))
+}
+
+/// Read the magic bytes and the header of a snapshot.
+pub fn read_header<I: Input>(input: &mut I) -> Result<SnapshotHeader, Error> {
+    let mut magic = [0u8; 4];
+    if input.read(&mut magic).is_err() || magic != SNAPSHOT_MAGIC {
+        return Err(ErrorCode::BadRequest.store_error("Invalid snapshot: missing magic bytes"));
+    }
+    let header = SnapshotHeader::decode(input).map_err(invalid)?;
+    if header.version > SNAPSHOT_VERSION {
+        return Err(ErrorCode::BadRequest
+            .store_error(format!("Unsupported snapshot version {}", header.version)));
+    }
+    Ok(header)
+}
+
+/// Read the header of a snapshot and its entries, the values as the bytes the backends store.  
+/// The entries are decoded while they are iterated, so a snapshot is never held in memory. A truncated
+/// snapshot, or one with bytes after the last entry, ends with an error.
+pub fn read_snapshot<'a, K: Decode + 'a>(
+    reader: &'a mut dyn Read,
+) -> Result<
+    (
+        SnapshotHeader,
+        impl Iterator<Item = Result<(K, Vec<u8>), Error>> + 'a,
+    ),
+    Error,
+> {
+    let mut input = IoReader(reader);
+    let header = read_header(&mut input)?;
+    let mut remaining = Some(header.count);
+    let entries = std::iter::from_fn(move || {
+        let entry = match remaining? {
+            0 => match input.0.read(&mut [0u8]) {
+                Ok(0) => None,
+                Ok(_) => Some(Err(ErrorCode::BadRequest
+                    .store_error("Invalid snapshot: trailing bytes after the last entry"))),
+                Err(e) => Some(Err(io_error(e))),
+            },
+            _ => Some(<(K, Vec<u8>)>::decode(&mut input).map_err(invalid)),
+        };
+        // stop after the last entry or the first error
+        remaining = match entry {
+            Some(Ok(_)) => remaining.map(|n| n - 1),
+            _ => None,
+        };
+        entry
+    });
+    Ok((header, entries))
+}
+
+/// The error of importing a snapshot into a store with a different hasher.
+pub fn hasher_mismatch(snapshot: &str, store: &str) -> Error {
+    ErrorCode::BadRequest.store_error(format!(
+        "Snapshot hasher {} does not match the store hasher {}",
+        snapshot, store
+    ))
+}
+
+/// The error of importing a snapshot whose entries do not rebuild its root.
+pub fn root_mismatch(expected: &H256, actual: &H256) -> Error {
+    ErrorCode::VerificationFailed.store_error(format!(
+        "Snapshot root mismatch: recorded {:?}, rebuilt {:?}",
+        expected, actual
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::MultiSMTStore,
+        parity_apis::MultiSMTParityStore,
+        registry::{HasherName, TreeMeta},
+        traits::MSS,
+    };
+    use smt_primitives::{
+        keccak_hasher::Keccak256Hasher,
+        kv::{SMTKey, SMTValue},
+    };
+    use tempfile::tempdir;
+
+    fn key(i: u64) -> SMTKey {
+        SMTKey {
+            address: i.to_string(),
+        }
+    }
+
+    fn value(i: u64) -> SMTValue {
+        SMTValue {
+            nonce: i,
+            balance: i as u128,
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let header = SnapshotHeader {
+            version: SNAPSHOT_VERSION,
+            name: "tree".to_string(),
+            hasher: Keccak256Hasher::NAME.to_string(),
+            root: H256::zero(),
+            count: 2,
+        };
+        let mut file = vec![];
+        write_header(&mut file, &header).unwrap();
+        write_entry(&mut file, &key(1), value(1).into()).unwrap();
+        write_entry(&mut file, &key(2), value(2).into()).unwrap();
+        assert!(file.starts_with(&SNAPSHOT_MAGIC));
+
+        let mut input = file.as_slice();
+        let (read_header, entries) = read_snapshot::<SMTKey>(&mut input).unwrap();
+        assert_eq!(read_header, header);
+        assert_eq!(
+            entries.collect::<Result<Vec<_>, Error>>().unwrap(),
+            vec![
+                (key(1), Vec::<u8>::from(value(1))),
+                (key(2), Vec::<u8>::from(value(2)))
+            ]
+        );
+
+        // truncated, trailing bytes and foreign files are rejected
+        let read_all = |bytes: &[u8]| -> Result<Vec<(SMTKey, Vec<u8>)>, Error> {
+            read_snapshot::<SMTKey>(&mut &bytes[..])?.1.collect()
+        };
+        assert!(read_all(&file[..file.len() - 1]).is_err());
+        let mut longer = file.clone();
+        longer.push(0);
+        assert!(read_all(&longer).is_err());
+        assert!(read_all(&file[1..]).is_err());
+        assert_eq!(read_all(&file).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_export_import() {
+        let rocks_dir = tempdir().unwrap();
+        let parity_dir = tempdir().unwrap();
+        let rocks =
+            MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(rocks_dir.path()).unwrap();
+        let parity =
+            MultiSMTParityStore::<SMTKey, SMTValue, Keccak256Hasher>::open(parity_dir.path())
+                .unwrap();
+        let tree = "tree".to_string();
+
+        rocks
+            .update_all(
+                tree.clone(),
+                (1..1500).map(|i| (key(i), value(i))).collect(),
+            )
+            .unwrap();
+        rocks
+            .update(tree.clone(), key(7), SMTValue::default())
+            .unwrap();
+        let root = rocks.update(tree.clone(), key(8), value(80)).unwrap();
+
+        // RocksDB -> ParityDB
+        let mut file = vec![];
+        let header = rocks.export_tree(tree.clone(), &mut file).unwrap();
+        assert_eq!(header.root, root);
+        assert_eq!(header.count, 1498);
+        let meta = parity
+            .import_tree("copy".to_string(), &mut file.as_slice())
+            .unwrap();
+        assert_eq!(meta.leaf_count, 1498);
+        assert_eq!(parity.get_root("copy".to_string()).unwrap(), root);
+        assert_eq!(
+            parity.get_value("copy".to_string(), key(8)).unwrap(),
+            value(80)
+        );
+        let proof = parity
+            .get_merkle_proof("copy".to_string(), key(100))
+            .unwrap();
+        assert!(parity.verify(proof));
+
+        // ParityDB -> RocksDB, an existing tree is not overwritten
+        let mut copy = vec![];
+        parity.export_tree("copy".to_string(), &mut copy).unwrap();
+        let entries = |bytes: &[u8]| -> Vec<(SMTKey, Vec<u8>)> {
+            let mut input = bytes;
+            let (_, entries) = read_snapshot::<SMTKey>(&mut input).unwrap();
+            entries.collect::<Result<_, Error>>().unwrap()
+        };
+        assert_eq!(entries(&copy), entries(&file));
+        assert!(rocks
+            .import_tree(tree.clone(), &mut copy.as_slice())
+            .is_err());
+        rocks
+            .import_tree("copy".to_string(), &mut copy.as_slice())
+            .unwrap();
+        assert_eq!(rocks.get_root("copy".to_string()).unwrap(), root);
+
+        // a snapshot that does not rebuild its root is not imported
+        let (mut header, _) = read_snapshot::<SMTKey>(&mut file.as_slice()).unwrap();
+        header.count -= 1;
+        let mut tampered = vec![];
+        write_header(&mut tampered, &header).unwrap();
+        for (k, v) in entries(&file).iter().skip(1) {
+            write_entry(&mut tampered, k, v.clone()).unwrap();
+        }
+        assert!(parity
+            .import_tree("tampered".to_string(), &mut tampered.as_slice())
+            .is_err());
+        assert_eq!(
+            parity.tree_meta("tampered".to_string()).unwrap(),
+            None::<TreeMeta>
+        );
+        assert_eq!(
+            parity.get_root("tampered".to_string()).unwrap(),
+            H256::zero()
+        );
+    }
+}
@@ -1,22 +1,72 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The operations shared by all storage backends.  
+//! A new backend only needs to implement [`MSS`] to be served by the HTTP server.
+
+use crate::{
+    error::{Error, ErrorCode},
+    hashers::{HasherKind, Leaf},
+    history::RootVersion,
+    ingest::Progress,
+    key_index::Page,
+    registry::{TreeInfo, TreeMeta},
+    req::KVPair,
+    session::SessionId,
+    snapshot::{self, SnapshotHeader, SNAPSHOT_VERSION},
+};
+use codec::{Decode, Encode};
 use scale_info::TypeInfo;
 use serde::{Deserialize, Serialize};
-use smt_primitives::verify::Proof;
+use smt_primitives::{
+    kv::LeafHash,
+    verify::{
+        verify as smt_verify, verify_batch as smt_verify_batch,
+        verify_non_membership as smt_verify_non_membership, BatchProof, Proof,
+    },
+};
 use sparse_merkle_tree::{
-    error::{Error, Result as SMTResult},
-    traits::{Hasher, Value},
-    H256,
+    traits::{Hasher, StoreReadOps, Value},
+    CompiledMerkleProof, SparseMerkleTree, H256,
+};
+use std::{
+    fmt::Debug,
+    io::{Read, Write},
 };
-use std::{fmt::Debug, path::Path};
 use utoipa::{ToSchema, __dev::ComposeSchema};
 
 /// Multi Sparse Merkle Tree Store trait
 /// Defines common operations for managing multiple Sparse Merkle Trees in a single store
 pub trait MSS<T, K, V, H>: Sized
 where
-    T: AsRef<[u8]>, // tree_id 类型参数，只要求能转换为字节切片
-    K: Value + Clone + Serialize + ToSchema + Deserialize<'static> + ComposeSchema + Debug + TypeInfo,
+    K: Value
+        + LeafHash
+        + Clone
+        + Serialize
+        + ToSchema
+        + Deserialize<'static>
+        + ComposeSchema
+        + Debug
+        + TypeInfo
+        + Encode
+        + Decode,
     V: Default
         + Value
+        + LeafHash
         + Into<Vec<u8>>
         + From<Vec<u8>>
         + ToSchema
@@ -32,17 +82,14 @@ where
     /// Associated type for the tree implementation
     type Tree;
 
-    /// Open the database, create it if it does not exist
-    fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self>;
-
     /// Create or open a new tree
-    fn new_tree_with_store<'a>(&'a self, tree_id: T) -> Result<Self::Tree, Error>;
+    fn new_tree_with_store(&self, tree_id: T) -> Result<Self::Tree, Error>;
 
     /// Insert a value into a specific Merkle tree
-    fn update(&self, tree_id: T, key: K, value: V) -> SMTResult<H256>;
+    fn update(&self, tree_id: T, key: K, value: V) -> Result<H256, Error>;
 
     /// Insert multiple values into a Merkle tree at once
-    fn update_all(&self, tree_id: T, kvs: Vec<(K, V)>) -> SMTResult<H256>;
+    fn update_all(&self, tree_id: T, kvs: Vec<(K, V)>) -> Result<H256, Error>;
 
     /// Get the root hash of a specific tree
     fn get_root(&self, tree_id: T) -> Result<H256, Error>;
@@ -51,17 +98,272 @@ where
     fn get_value(&self, tree_id: T, key: K) -> Result<V, Error>;
 
     /// Get the Merkle proof for a specific key
+    /// If the key is not in the tree, this is a non-membership proof (the value is empty and `value_hash` is zero)
     fn get_merkle_proof(&self, tree_id: T, key: K) -> Result<Proof<K, V>, Error>;
 
+    /// Get one Merkle proof for multiple keys, the siblings are shared by all keys
+    /// Duplicate keys are proven once, and keys that are not in the tree are proven to be absent
+    fn get_batch_merkle_proof(&self, tree_id: T, keys: Vec<K>) -> Result<BatchProof<K, V>, Error>;
+
+    /// The current root and the past roots of a tree that are still kept, newest first
+    fn root_history(&self, tree_id: T) -> Result<Vec<RootVersion>, Error>;
+
+    /// Get the value of a specific key when the root of the tree was `root`
+    /// The root must be the current root or one of [`MSS::root_history`]
+    fn get_value_at(&self, tree_id: T, root: H256, key: K) -> Result<V, Error>;
+
+    /// Get the Merkle proof for a specific key against a past root of the tree
+    /// The root must be the current root or one of [`MSS::root_history`]
+    fn get_merkle_proof_at(&self, tree_id: T, root: H256, key: K) -> Result<Proof<K, V>, Error>;
+
+    /// The original keys of a tree ordered by their leaf keys, at most `limit` keys after the leaf key `start_after`
+    /// Only leaves written since the key index exists are listed
+    fn keys(&self, tree_id: T, start_after: Option<H256>, limit: usize) -> Result<Page<K>, Error>;
+
+    /// The keys and values of a tree, paginated like [`MSS::keys`]
+    fn entries(
+        &self,
+        tree_id: T,
+        start_after: Option<H256>,
+        limit: usize,
+    ) -> Result<Page<KVPair<K, V>>, Error>
+    where
+        T: Clone,
+    {
+        let page = self.keys(tree_id.clone(), start_after, limit)?;
+        let items = page
+            .items
+            .into_iter()
+            .map(|key| {
+                let value = self.get_value(tree_id.clone(), key.clone())?;
+                Ok(KVPair { key, value })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Page {
+            items,
+            next: page.next,
+        })
+    }
+
+    /// Call `f` with the original key and the stored value bytes of every leaf of a tree, ordered by their leaf keys,
+    /// in one pass over the key index, see [`MSS::keys`]
+    /// The tree cannot change while it is read, its metadata and root at that time are returned (`None` if it is not registered)
+    fn for_each_entry(
+        &self,
+        tree_id: T,
+        f: &mut dyn FnMut(K, Vec<u8>) -> Result<(), Error>,
+    ) -> Result<Option<TreeInfo>, Error>;
+
+    /// Export a tree to a snapshot, see [`crate::snapshot`]
+    /// The entries are streamed to `writer` while the key index is read
+    /// Fails if some leaves of the tree are not in the key index, or if the tree changed during the export
+    fn export_tree(&self, tree_id: T, writer: &mut dyn Write) -> Result<SnapshotHeader, Error>
+    where
+        T: Clone + Debug,
+    {
+        let unknown = || ErrorCode::UnknownTree.store_error(format!("Unknown tree: {:?}", tree_id));
+        let info = self.describe_tree(tree_id.clone())?.ok_or_else(unknown)?;
+        let header = SnapshotHeader {
+            version: SNAPSHOT_VERSION,
+            name: info.meta.name.clone(),
+            hasher: info.meta.hasher.clone(),
+            root: info.root,
+            count: info.meta.leaf_count,
+        };
+        snapshot::write_header(writer, &header)?;
+
+        let mut count = 0;
+        let read = self.for_each_entry(tree_id.clone(), &mut |key, value| {
+            count += 1;
+            snapshot::write_entry(writer, &key, value)
+        })?;
+        if read.as_ref() != Some(&info) {
+            return Err(ErrorCode::Conflict
+                .store_error(format!("Tree {} changed during the export", header.name)));
+        }
+        if count != header.count {
+            return Err(ErrorCode::Corruption.store_error(format!(
+                "Only {} of the {} leaves of tree {} are in the key index",
+                count, header.count, header.name
+            )));
+        }
+        Ok(header)
+    }
+
+    /// Import a snapshot as a new tree, the tree is only registered if the rebuilt root is the recorded one
+    /// The entries are streamed from `reader` into the tree like an ingest, see [`crate::ingest`]
+    fn import_tree(&self, tree_id: T, reader: &mut dyn Read) -> Result<TreeMeta, Error>;
+
+    /// Build an empty tree from a stream of entries, much faster than [`MSS::update_all`] for large trees, see [`crate::ingest`]
+    /// `progress` is called after every committed chunk
+    fn ingest(
+        &self,
+        tree_id: T,
+        entries: &mut dyn Iterator<Item = Result<(K, V), Error>>,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<TreeInfo, Error>;
+
+    /// Begin a session of staged updates of a tree, see [`crate::session`]
+    fn begin_session(&self, tree_id: T) -> Result<SessionId, Error>;
+
+    /// Stage a round of updates in a session, returns the staged root
+    /// The last entry of a key wins and entries with the default value are deletions, like [`MSS::update_all`]
+    fn session_update(&self, session: SessionId, kvs: Vec<(K, V)>) -> Result<H256, Error>;
+
+    /// Get the staged value of a key in a session
+    fn session_value(&self, session: SessionId, key: K) -> Result<V, Error>;
+
+    /// Get the Merkle proof for a key against the staged root of a session
+    fn session_merkle_proof(&self, session: SessionId, key: K) -> Result<Proof<K, V>, Error>;
+
+    /// Write the staged updates of a session as one version of its tree and close the session
+    /// Fails (and closes the session) if the tree changed since the session began
+    fn commit_session(&self, session: SessionId) -> Result<H256, Error>;
+
+    /// Drop the staged updates of a session and close it
+    fn rollback_session(&self, session: SessionId) -> Result<(), Error>;
+
     /// Get the Merkle proof in raw bytes format
-    fn get_merkle_proof_old(&self, tree_id: T, keys: Vec<K>) -> SMTResult<Vec<u8>>;
+    fn get_merkle_proof_old(&self, tree_id: T, keys: Vec<K>) -> Result<Vec<u8>, Error>;
 
     /// Calculate the future root hash before updating data
-    fn get_next_root(&self, old_proof: Vec<u8>, next_kvs: Vec<(K, V)>) -> Result<H256, Error>;
+    fn get_next_root(&self, old_proof: Vec<u8>, next_kvs: Vec<(K, V)>) -> Result<H256, Error> {
+        let p = CompiledMerkleProof(old_proof);
+        let kvs = next_kvs
+            .into_iter()
+            .map(|(k, v)| Ok((k.leaf_hash::<H>(), v.leaf_hash::<H>())))
+            .collect::<Result<Vec<(H256, H256)>, Error>>()?;
+
+        let next_root = p.compute_root::<H>(kvs)?;
+        Ok(next_root)
+    }
+
+    /// Calculate the root of a tree after applying `kvs` without writing them
+    /// The last entry of a key wins and entries with the default value are deletions, like [`MSS::update_all`]
+    fn next_root(&self, tree_id: T, kvs: Vec<(K, V)>) -> Result<H256, Error> {
+        let mut kvs = kvs
+            .into_iter()
+            .map(|(k, v)| (k.leaf_hash::<H>(), k, v))
+            .collect::<Vec<(H256, K, V)>>();
+        kvs.reverse();
+        kvs.sort_by_key(|(path, _, _)| *path);
+        kvs.dedup_by_key(|(path, _, _)| *path);
+        if kvs.is_empty() {
+            return self.get_root(tree_id);
+        }
+        let old_proof =
+            self.get_merkle_proof_old(tree_id, kvs.iter().map(|(_, k, _)| k.clone()).collect())?;
+        self.get_next_root(old_proof, kvs.into_iter().map(|(_, k, v)| (k, v)).collect())
+    }
 
-    /// Delete/clear a specific Merkle tree
+    /// Remove all leaves of a specific Merkle tree, a registered tree stays registered
     fn clear(&self, tree_id: T) -> Result<(), Error>;
 
+    /// Register an empty tree built with `hasher`, fails if it is already registered
+    fn create_tree(&self, tree_id: T, hasher: HasherKind) -> Result<TreeMeta, Error>;
+
+    /// The metadata of all registered trees
+    fn trees(&self) -> Result<Vec<TreeMeta>, Error>;
+
+    /// The metadata of a tree, `None` if it is not registered
+    fn tree_meta(&self, tree_id: T) -> Result<Option<TreeMeta>, Error>;
+
+    /// Delete a tree with all of its nodes and its registry entry
+    fn delete_tree(&self, tree_id: T) -> Result<(), Error>;
+
+    /// The metadata and the current root of a tree, `None` if it is not registered
+    fn describe_tree(&self, tree_id: T) -> Result<Option<TreeInfo>, Error>
+    where
+        T: Clone,
+    {
+        match self.tree_meta(tree_id.clone())? {
+            Some(meta) => Ok(Some(TreeInfo {
+                meta,
+                root: self.get_root(tree_id)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
     /// Verify a Merkle proof
-    fn verify(&self, proof: Proof<K, V>) -> bool;
+    fn verify(&self, proof: Proof<K, V>) -> bool {
+        verify_proof::<H, K, V>(proof)
+    }
+
+    /// Verify the non-membership proof, i.e. that the key is not in the tree
+    fn verify_non_membership(&self, proof: Proof<K, V>) -> bool {
+        verify_non_membership_proof::<H, K, V>(proof)
+    }
+
+    /// Verify the batch Merkle proof
+    fn verify_batch(&self, proof: BatchProof<K, V>) -> bool {
+        verify_batch_proof::<H, K, V>(proof)
+    }
+}
+
+/// Verify a Merkle proof of a tree built with the hasher `H`, see [`MSS::verify`]
+pub fn verify_proof<H, K, V>(proof: Proof<K, V>) -> bool
+where
+    H: Hasher + Default,
+    V: Default + PartialEq,
+{
+    proof.value != V::default()
+        && smt_verify::<H>(
+            proof.path,
+            proof.value_hash,
+            proof.leave_bitmap,
+            proof.siblings,
+            proof.root,
+        )
+}
+
+/// Verify a non-membership proof of a tree built with the hasher `H`, see [`MSS::verify_non_membership`]
+pub fn verify_non_membership_proof<H, K, V>(proof: Proof<K, V>) -> bool
+where
+    H: Hasher + Default,
+    V: Default + PartialEq,
+{
+    proof.value == V::default()
+        && smt_verify_non_membership::<H>(
+            proof.path,
+            proof.leave_bitmap,
+            proof.siblings,
+            proof.root,
+        )
+}
+
+/// Verify a batch Merkle proof of a tree built with the hasher `H`, see [`MSS::verify_batch`]
+pub fn verify_batch_proof<H: Hasher + Default, K, V>(proof: BatchProof<K, V>) -> bool {
+    let leaves = proof
+        .leaves
+        .iter()
+        .map(|leaf| (leaf.path, leaf.value_hash))
+        .collect();
+    smt_verify_batch::<H>(leaves, proof.leaves_bitmap, proof.siblings, proof.root)
+}
+
+/// The Merkle proof for a key of a tree, see [`MSS::get_merkle_proof`]
+pub fn merkle_proof<H, K, V, S>(
+    tree: &SparseMerkleTree<H, Leaf<V, H>, S>,
+    key: K,
+) -> Result<Proof<K, V>, Error>
+where
+    H: Hasher + Default,
+    K: LeafHash + Clone + Debug + TypeInfo,
+    V: LeafHash + Default + Clone + Debug + TypeInfo,
+    S: StoreReadOps<Leaf<V, H>>,
+{
+    let path = key.leaf_hash::<H>();
+    let proof = tree.merkle_proof(vec![path])?;
+    let leave_bitmap = proof.leaves_bitmap()[0];
+    let value = tree.get(&path)?.into_inner();
+    Ok(Proof {
+        key,
+        value_hash: value.leaf_hash::<H>(),
+        value,
+        path,
+        root: *tree.root(),
+        leave_bitmap,
+        siblings: proof.merkle_path().clone(),
+    })
 }
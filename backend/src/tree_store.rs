@@ -0,0 +1,221 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Store the nodes of one Merkle tree in a [`KvDb`].
+
+use crate::{
+    batch::{BufferedStore, WriteBatch},
+    error::Error,
+    history, key_index,
+    kv::{tree_prefix, Change, Column, KvDb},
+    registry::TreeMeta,
+};
+use codec::{Decode, Encode};
+use sparse_merkle_tree::{
+    error::Error as SMTError,
+    traits::{StoreReadOps, StoreWriteOps, Value},
+    BranchKey, BranchNode, H256,
+};
+use std::sync::Arc;
+
+/// The nodes of one tree, namespaced by the tree prefix.  
+/// Changes are buffered in memory until [`TreeStore::commit`] writes them in one commit,
+/// so a crash in the middle of an update never leaves a half-written tree behind.
+pub struct TreeStore<D> {
+    inner: Arc<D>,
+    prefix: Vec<u8>,
+    batch: WriteBatch,
+    leaf_delta: i64,
+}
+
+impl<D: KvDb> TreeStore<D> {
+    pub fn new(db: Arc<D>, name: &str) -> Self {
+        TreeStore {
+            inner: db,
+            prefix: tree_prefix(name),
+            batch: Default::default(),
+            leaf_delta: 0,
+        }
+    }
+
+    /// A read-only view of a past version of the tree, `overlay` holds the changes that roll the
+    /// tree back to it (see [`history::rollback_to`]). The view must never be committed.
+    pub fn with_overlay(db: Arc<D>, name: &str, overlay: WriteBatch) -> Self {
+        TreeStore {
+            batch: overlay,
+            ..Self::new(db, name)
+        }
+    }
+
+    fn key(&self, node_key: &[u8]) -> Vec<u8> {
+        [self.prefix.as_slice(), node_key].concat()
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, SMTError> {
+        match self.batch.get(key) {
+            Some(value) => Ok(value.cloned()),
+            None => self
+                .inner
+                .get(Column::Nodes, key)
+                .map_err(|e| SMTError::Store(e.to_string())),
+        }
+    }
+
+    /// The number of leaves added (or removed if negative) by the buffered changes.
+    pub fn leaf_delta(&self) -> i64 {
+        self.leaf_delta
+    }
+
+    /// Record the original key of the leaf `path`, or remove it if `None`, in the same commit as the leaf.
+    pub fn index_key(&mut self, path: H256, key: Option<Vec<u8>>) {
+        let index_key = key_index::index_key(&self.prefix, &path);
+        match key {
+            Some(key) => self.batch.put(index_key, key),
+            None => self.batch.delete(index_key),
+        }
+    }
+
+    /// Write all buffered changes to the database in one atomic commit.
+    pub fn commit(&mut self) -> Result<(), Error> {
+        self.write(None)
+    }
+
+    /// Write all buffered changes as a new version of the tree with the given root, together with
+    /// the registry entry of the tree, in one atomic commit.  
+    /// The undo logs of the last `history_depth` versions are kept, see [`crate::history`].
+    pub fn commit_version(
+        &mut self,
+        meta: &TreeMeta,
+        root: H256,
+        history_depth: u64,
+    ) -> Result<(), Error> {
+        self.write(Some((meta, root, history_depth)))
+    }
+
+    fn write(&mut self, version: Option<(&TreeMeta, H256, u64)>) -> Result<(), Error> {
+        if self.batch.is_empty() && version.is_none() {
+            return Ok(());
+        }
+        let mut nodes = self.batch.take();
+        let db = &self.inner;
+        let mut changes = vec![];
+        if let Some((meta, root, history_depth)) = version {
+            let history = history::record(&self.prefix, root, history_depth, &nodes, |key| {
+                db.get(Column::Nodes, key).map_err(Error::store)
+            })?;
+            nodes.extend(history);
+            changes.push(Change::Put(
+                Column::Trees,
+                self.prefix.clone(),
+                meta.encode(),
+            ));
+        }
+        changes.extend(nodes.into_iter().map(|(key, value)| match value {
+            Some(value) => Change::Put(Column::Nodes, key, value),
+            None => Change::Delete(Column::Nodes, key),
+        }));
+        self.leaf_delta = 0;
+        db.commit(changes).map_err(Error::store)
+    }
+}
+
+impl<D, V> StoreWriteOps<V> for TreeStore<D>
+where
+    D: KvDb,
+    V: Value + Into<Vec<u8>>,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), SMTError> {
+        let key = self.key(&node_key.encode());
+        self.batch.put(key, branch.encode());
+        Ok(())
+    }
+
+    // 叶子就是数据
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), SMTError> {
+        let key = self.key(&leaf_key.encode());
+        if self.get(&key)?.is_none() {
+            self.leaf_delta += 1;
+        }
+        self.batch.put(key, leaf.into());
+        Ok(())
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), SMTError> {
+        let key = self.key(&node_key.encode());
+        self.batch.delete(key);
+        Ok(())
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), SMTError> {
+        let key = self.key(&leaf_key.encode());
+        if self.get(&key)?.is_some() {
+            self.leaf_delta -= 1;
+        }
+        self.batch.delete(key);
+        Ok(())
+    }
+}
+
+impl<D, V> StoreReadOps<V> for TreeStore<D>
+where
+    D: KvDb,
+    V: Value + From<Vec<u8>>,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, SMTError> {
+        self.get(&self.key(&branch_key.encode()))?
+            .map(|v| {
+                BranchNode::decode(&mut v.as_slice()).map_err(|e| SMTError::Store(e.to_string()))
+            })
+            .transpose()
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, SMTError> {
+        self.get(&self.key(&leaf_key.encode()))
+            .map(|s| s.map(|v| v.into()))
+    }
+}
+
+impl<D, V> BufferedStore<V> for TreeStore<D>
+where
+    D: KvDb,
+    V: Value + Into<Vec<u8>>,
+{
+    fn index_key(&mut self, path: H256, key: Option<Vec<u8>>) {
+        TreeStore::index_key(self, path, key)
+    }
+
+    fn pending(&self) -> usize {
+        self.batch.len()
+    }
+
+    fn leaf_delta(&self) -> i64 {
+        TreeStore::leaf_delta(self)
+    }
+
+    fn commit(&mut self) -> Result<(), Error> {
+        TreeStore::commit(self)
+    }
+
+    fn commit_version(
+        &mut self,
+        meta: &TreeMeta,
+        root: H256,
+        history_depth: u64,
+    ) -> Result<(), Error> {
+        TreeStore::commit_version(self, meta, root, history_depth)
+    }
+}
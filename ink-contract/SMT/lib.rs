@@ -73,7 +73,7 @@ mod smt {
         #![allow(unused_imports)]
         /// Imports all the definitions from the outer scope so we can use them here.
         use super::*;
-        use smt_backend_lib::apis::MultiSMTStore;
+        use smt_backend_lib::{apis::MultiSMTStore, traits::MSS};
         use smt_primitives::{
             keccak_hasher::Keccak256Hasher,
             kv::{SMTKey, SMTValue},
@@ -88,7 +88,7 @@ mod smt {
                     .unwrap();
             // 创建一个tree
             let tree = "tree1";
-            multi_tree.clear(tree.to_string());
+            multi_tree.clear(tree.to_string()).unwrap();
             let mut kvs: Vec<(SMTKey, SMTValue)> = vec![];
 
             for i in 0..size {
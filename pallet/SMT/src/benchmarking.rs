@@ -20,21 +20,152 @@ use super::*;
 
 #[allow(unused)]
 use crate::Pallet as Template;
+use codec::Decode;
 use frame_benchmarking::v2::*;
+use frame_support::{
+    sp_runtime::traits::TrailingZeroInput,
+    traits::{EnsureOrigin, Get},
+    BoundedVec,
+};
 use frame_system::RawOrigin;
-use mock::creat_db_and_get_proof;
-use primitives::kv::{SMTKey, SMTValue};
+use primitives::{
+    kv::LeafHash,
+    sparse_merkle_tree::{merge::MergeValue, H256},
+    verify::{compute_root, Proof},
+};
+use scale_info::prelude::vec::Vec;
+
+/// The largest number of siblings of a proof, one per height of the tree.
+const MAX_SIBLINGS: u32 = 256;
+
+fn tree_id<T: Config>() -> T::TreeId {
+    Decode::decode(&mut TrailingZeroInput::zeroes()).expect("the tree id decodes from zeroes")
+}
+
+/// A valid proof of the leaf of [`Config::BenchmarkHelper`] with `siblings` non-empty siblings, its root is
+/// not published.
+fn proof_with_siblings<T: Config>(siblings: u32) -> Proof<T::SMTKey, T::SMTValue> {
+    let (key, value) = T::BenchmarkHelper::leaf();
+    let (path, value_hash) = (
+        key.leaf_hash::<T::SMTHasher>(),
+        value.leaf_hash::<T::SMTHasher>(),
+    );
+    let mut leave_bitmap = H256::zero();
+    (0..siblings).for_each(|i| leave_bitmap.set_bit(i as u8));
+    let siblings = (0..siblings)
+        .map(|i| {
+            let mut sibling = [1u8; 32];
+            sibling[0] = i as u8;
+            MergeValue::from_h256(sibling.into())
+        })
+        .collect::<Vec<_>>();
+    let root = compute_root::<T::SMTHasher>(path, value_hash, leave_bitmap, siblings.clone())
+        .expect("a sibling for every bit of the bitmap");
+    Proof {
+        key,
+        value,
+        path,
+        value_hash,
+        root,
+        leave_bitmap,
+        siblings,
+    }
+}
+
+/// Publish `root` for the tree of the benchmarks.
+fn publish<T: Config>(root: H256) {
+    Roots::<T>::insert(
+        tree_id::<T>(),
+        root,
+        RootInfo {
+            version: 1,
+            set_at: frame_system::Pallet::<T>::block_number(),
+            expires_at: None,
+        },
+    );
+}
 
 #[benchmarks]
 mod benchmarks {
     use super::*;
 
+    /// A proof of a leaf with `s` siblings, a proof of a tree of a million leaves has about 20.
+    #[benchmark]
+    fn smt_verify(s: Linear<0, MAX_SIBLINGS>) {
+        let proof = proof_with_siblings::<T>(s);
+        publish::<T>(proof.root);
+        let caller: T::AccountId = whitelisted_caller();
+        #[extrinsic_call]
+        smt_verify(RawOrigin::Signed(caller), tree_id::<T>(), proof);
+    }
+
+    #[benchmark]
+    fn set_root() -> Result<(), BenchmarkError> {
+        let origin =
+            T::RootOrigin::try_successful_origin().map_err(|_| BenchmarkError::Weightless)?;
+        // the worst case bumps the version of a tree that has a root
+        publish::<T>(H256::from([1; 32]));
+        LatestRoot::<T>::insert(tree_id::<T>(), (H256::from([1; 32]), 1));
+        #[extrinsic_call]
+        _(
+            origin as T::RuntimeOrigin,
+            tree_id::<T>(),
+            H256::from([2; 32]),
+            None,
+        );
+        assert_eq!(
+            LatestRoot::<T>::get(tree_id::<T>()),
+            Some((H256::from([2; 32]), 2))
+        );
+        Ok(())
+    }
+
+    #[benchmark]
+    fn revoke_root() -> Result<(), BenchmarkError> {
+        let origin =
+            T::RootOrigin::try_successful_origin().map_err(|_| BenchmarkError::Weightless)?;
+        publish::<T>(H256::from([1; 32]));
+        #[extrinsic_call]
+        _(
+            origin as T::RuntimeOrigin,
+            tree_id::<T>(),
+            H256::from([1; 32]),
+        );
+        assert!(!Roots::<T>::contains_key(
+            tree_id::<T>(),
+            H256::from([1; 32])
+        ));
+        Ok(())
+    }
+
+    /// A claim of a leaf with `s` siblings. The hook is charged by [`crate::OnClaim::weight`], so the
+    /// weights are generated with `()` as the hook of the runtime.
+    #[benchmark]
+    fn claim(s: Linear<0, MAX_SIBLINGS>) {
+        let proof = proof_with_siblings::<T>(s);
+        publish::<T>(proof.root);
+        let path = proof.path;
+        let caller: T::AccountId = whitelisted_caller();
+        #[extrinsic_call]
+        claim(RawOrigin::Signed(caller), tree_id::<T>(), proof);
+        assert!(Claimed::<T>::contains_key(tree_id::<T>(), path));
+    }
+
+    /// `n` proofs with `s` siblings in total against one root.
     #[benchmark]
-    fn smt_verify() {
-        let proof = creat_db_and_get_proof(3);
+    fn smt_verify_batch(n: Linear<1, { T::MaxBatchProofs::get() }>, s: Linear<0, MAX_SIBLINGS>) {
+        // the copies of a proof of one leaf share its root, the siblings are spread over them
+        let proof = proof_with_siblings::<T>(s / n);
+        publish::<T>(proof.root);
+        let root = proof.root;
+        let proofs: BoundedVec<_, T::MaxBatchProofs> = (0..n)
+            .map(|_| proof.clone())
+            .collect::<Vec<_>>()
+            .try_into()
+            .expect("at most MaxBatchProofs proofs");
         let caller: T::AccountId = whitelisted_caller();
         #[extrinsic_call]
-        smt_verify(RawOrigin::Signed(caller), p);
+        smt_verify_batch(RawOrigin::Signed(caller), tree_id::<T>(), root, proofs);
     }
 
     impl_benchmark_test_suite!(Template, crate::mock::new_test_ext(), crate::mock::Test);
@@ -17,7 +17,10 @@
 
 //! This template will be responsible for verifying Merkle tree proofs on-chain. We follow the principle of minimizing on-chain computation and storage resources as much as possible, providing only the verification method.  
 //! The advantage of Merkle trees lies precisely in this approach.  
-//! The Merkle tree proof is provided by the `merkle_proof` API from the SMT backend.
+//! The Merkle tree proof is provided by the `merkle_proof` API from the SMT backend.  
+//! A proof is only accepted against a root of the tree that was published on-chain by [`Config::RootOrigin`], so
+//! a caller cannot prove a leaf of a tree of their own.  
+//! A leaf can be claimed once per tree, e.g. for airdrops, what the claim pays out is up to [`Config::OnClaim`].
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
@@ -34,6 +37,36 @@ pub mod weights;
 #[cfg(feature = "runtime-benchmarks")]
 mod benchmarking;
 
+use frame_support::{dispatch::DispatchResult, weights::Weight};
+
+/// What a claimed leaf pays out, e.g. a transfer of the balance of the value to the account of the key.
+/// It is called after the proof is verified and before the leaf is marked as claimed, the claim fails
+/// (and nothing is recorded) if it fails.
+pub trait OnClaim<AccountId, TreeId, Key, Value> {
+    /// `who` submitted the claim of the leaf `key` of the tree `tree_id`.
+    fn on_claim(who: &AccountId, tree_id: &TreeId, key: &Key, value: &Value) -> DispatchResult;
+
+    /// The weight of [`OnClaim::on_claim`], it is charged on top of the weight of the claim.
+    fn weight() -> Weight;
+}
+
+impl<AccountId, TreeId, Key, Value> OnClaim<AccountId, TreeId, Key, Value> for () {
+    fn on_claim(_: &AccountId, _: &TreeId, _: &Key, _: &Value) -> DispatchResult {
+        Ok(())
+    }
+
+    fn weight() -> Weight {
+        Weight::zero()
+    }
+}
+
+/// Provide a leaf the benchmarks can claim.
+#[cfg(feature = "runtime-benchmarks")]
+pub trait BenchmarkHelper<Key, Value> {
+    /// A key and a non-empty value, the [`OnClaim`] of the runtime must accept their claim.
+    fn leaf() -> (Key, Value);
+}
+
 #[frame_support::pallet]
 pub mod pallet {
     use crate::weights::WeightInfo;
@@ -41,6 +74,7 @@ pub mod pallet {
     use frame_support::{dispatch::DispatchResultWithPostInfo, pallet_prelude::*};
     use frame_system::pallet_prelude::*;
     use primitives::{
+        kv::LeafHash,
         sparse_merkle_tree::{
             traits::{Hasher, Value},
             H256,
@@ -57,16 +91,84 @@ pub mod pallet {
         /// A type representing the weights required by the dispatchables of this pallet.
         type WeightInfo: crate::weights::WeightInfo;
         /// The data type of the Key in the KVDB.
-        type SMTKey: Value + Default + Debug + Clone + TypeInfo + Encode + Decode + PartialEq;
+        type SMTKey: Value
+            + LeafHash
+            + Default
+            + Debug
+            + Clone
+            + TypeInfo
+            + Encode
+            + Decode
+            + PartialEq;
         /// The data type of the value in the KVDB.
-        type SMTValue: Value + Default + Debug + Clone + TypeInfo + Encode + Decode + PartialEq;
-        /// The hash algorithm chosen for this Merkle tree off-chain.
+        type SMTValue: Value
+            + LeafHash
+            + Default
+            + Debug
+            + Clone
+            + TypeInfo
+            + Encode
+            + Decode
+            + PartialEq;
+        /// The hash algorithm chosen for this Merkle tree off-chain, the leaves are hashed with it too.
         type SMTHasher: Hasher + Default;
+        /// The identifier of a tree, e.g. the id of an airdrop.
+        type TreeId: Parameter + Member + MaxEncodedLen;
+        /// The origin allowed to publish and revoke the roots of the trees, e.g. an operator account or governance.
+        type RootOrigin: EnsureOrigin<Self::RuntimeOrigin>;
+        /// The largest number of proofs verified by one `smt_verify_batch`.
+        #[pallet::constant]
+        type MaxBatchProofs: Get<u32>;
+        /// What a claimed leaf pays out.
+        type OnClaim: crate::OnClaim<Self::AccountId, Self::TreeId, Self::SMTKey, Self::SMTValue>;
+        /// Provide a leaf the benchmarks can claim.
+        #[cfg(feature = "runtime-benchmarks")]
+        type BenchmarkHelper: crate::BenchmarkHelper<Self::SMTKey, Self::SMTValue>;
+    }
+
+    /// A published root of a tree.
+    #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, TypeInfo, MaxEncodedLen)]
+    pub struct RootInfo<BlockNumber> {
+        /// The version of the root in its tree, the first root of a tree is version 1.
+        pub version: u32,
+        /// The block the root was published in.
+        pub set_at: BlockNumber,
+        /// The last block proofs against the root are accepted in, `None` if the root does not expire.
+        pub expires_at: Option<BlockNumber>,
     }
 
     #[pallet::pallet]
     pub struct Pallet<T>(_);
 
+    /// The trusted roots of each tree, a proof is only verified against one of them.
+    #[pallet::storage]
+    pub type Roots<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::TreeId,
+        Identity,
+        H256,
+        RootInfo<BlockNumberFor<T>>,
+        OptionQuery,
+    >;
+
+    /// The paths of the claimed leaves of each tree and the block they were claimed in.
+    #[pallet::storage]
+    pub type Claimed<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::TreeId,
+        Identity,
+        H256,
+        BlockNumberFor<T>,
+        OptionQuery,
+    >;
+
+    /// The root published last for each tree and its version, it may have been revoked since.
+    #[pallet::storage]
+    pub type LatestRoot<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::TreeId, (H256, u32), OptionQuery>;
+
     // Pallets use events to inform users when important changes are made.
     // https://docs.substrate.io/v3/runtime/events-and-errors
     #[pallet::event]
@@ -76,11 +178,34 @@ pub mod pallet {
         SMTVerify {
             /// Who submitted the proof to the blockchain.
             account: T::AccountId,
+            /// The tree of the leaf.
+            tree_id: T::TreeId,
             /// The path of the leaf being proven.
             path: H256,
             /// root hash
             root: H256,
         },
+        /// A root of a tree was published.
+        RootSet {
+            tree_id: T::TreeId,
+            root: H256,
+            /// The version of the root in its tree.
+            version: u32,
+            /// The last block proofs against the root are accepted in.
+            expires_at: Option<BlockNumberFor<T>>,
+        },
+        /// A root of a tree was revoked, proofs against it are no longer accepted.
+        RootRevoked { tree_id: T::TreeId, root: H256 },
+        /// A leaf was claimed.
+        Claimed {
+            /// Who submitted the claim.
+            account: T::AccountId,
+            tree_id: T::TreeId,
+            /// The path of the claimed leaf.
+            path: H256,
+            /// The root the leaf was proven against.
+            root: H256,
+        },
     }
 
     // Errors inform users that something went wrong.
@@ -88,6 +213,18 @@ pub mod pallet {
     pub enum Error<T> {
         /// Merkle proof verification failed.
         SMTVerifyFaild,
+        /// The root of the proof was not published for the tree, or it was revoked.
+        UnknownRoot,
+        /// The root of the proof has expired.
+        RootExpired,
+        /// The root was already published for the tree.
+        RootAlreadySet,
+        /// The expiry block of a root has already passed.
+        ExpiryInPast,
+        /// The leaf was already claimed.
+        AlreadyClaimed,
+        /// A batch holds no proofs.
+        EmptyBatch,
     }
 
     #[pallet::hooks]
@@ -98,28 +235,182 @@ pub mod pallet {
     // Dispatchable functions must be annotated with a weight and must return a DispatchResult.
     #[pallet::call]
     impl<T: Config> Pallet<T> {
-        /// Verify the Merkle proof provided off-chain.
-        ///
+        /// Verify the Merkle proof provided off-chain against a trusted root of the tree.
+        /// The path and the value hash of the proof are derived from its key and value, like in [`Pallet::claim`].
         #[pallet::call_index(0)]
-        #[pallet::weight(T::WeightInfo::smt_verify().saturating_mul(1_u64 + proof.siblings.len() as u64))]
+        #[pallet::weight(T::WeightInfo::smt_verify(proof.siblings.len() as u32))]
         pub fn smt_verify(
             origin: OriginFor<T>,
+            tree_id: T::TreeId,
             proof: verify::Proof<T::SMTKey, T::SMTValue>,
         ) -> DispatchResultWithPostInfo {
             let who = ensure_signed(origin)?;
-            Self::do_verify(who, proof)
+            Self::do_verify(who, tree_id, proof)
+        }
+
+        /// Publish a root of a tree, proofs against it are accepted until the block `expires_at` (included).
+        /// The roots published before stay valid until they expire or are revoked.
+        #[pallet::call_index(1)]
+        #[pallet::weight(T::WeightInfo::set_root())]
+        pub fn set_root(
+            origin: OriginFor<T>,
+            tree_id: T::TreeId,
+            root: H256,
+            expires_at: Option<BlockNumberFor<T>>,
+        ) -> DispatchResult {
+            T::RootOrigin::ensure_origin(origin)?;
+            let now = frame_system::Pallet::<T>::block_number();
+            ensure!(
+                expires_at.map_or(true, |expires_at| expires_at >= now),
+                Error::<T>::ExpiryInPast
+            );
+            ensure!(
+                !Roots::<T>::contains_key(&tree_id, root),
+                Error::<T>::RootAlreadySet
+            );
+
+            let version =
+                LatestRoot::<T>::get(&tree_id).map_or(1, |(_, version)| version.saturating_add(1));
+            Roots::<T>::insert(
+                &tree_id,
+                root,
+                RootInfo {
+                    version,
+                    set_at: now,
+                    expires_at,
+                },
+            );
+            LatestRoot::<T>::insert(&tree_id, (root, version));
+            Self::deposit_event(Event::<T>::RootSet {
+                tree_id,
+                root,
+                version,
+                expires_at,
+            });
+            Ok(())
+        }
+
+        /// Revoke a root of a tree, e.g. an expired one or one published by mistake.
+        #[pallet::call_index(2)]
+        #[pallet::weight(T::WeightInfo::revoke_root())]
+        pub fn revoke_root(origin: OriginFor<T>, tree_id: T::TreeId, root: H256) -> DispatchResult {
+            T::RootOrigin::ensure_origin(origin)?;
+            ensure!(
+                Roots::<T>::take(&tree_id, root).is_some(),
+                Error::<T>::UnknownRoot
+            );
+            Self::deposit_event(Event::<T>::RootRevoked { tree_id, root });
+            Ok(())
+        }
+
+        /// Claim a leaf of a tree once, its proof is verified against a trusted root of the tree and
+        /// [`Config::OnClaim`] pays it out.
+        /// The path and the value hash of the proof are derived from its key and value with [`Config::SMTHasher`],
+        /// not taken from the caller.
+        #[pallet::call_index(3)]
+        #[pallet::weight(
+            T::WeightInfo::claim(proof.siblings.len() as u32).saturating_add(T::OnClaim::weight())
+        )]
+        pub fn claim(
+            origin: OriginFor<T>,
+            tree_id: T::TreeId,
+            proof: verify::Proof<T::SMTKey, T::SMTValue>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::ensure_trusted_root(&tree_id, proof.root)?;
+            let path = proof.key.leaf_hash::<T::SMTHasher>();
+            ensure!(
+                !Claimed::<T>::contains_key(&tree_id, path),
+                Error::<T>::AlreadyClaimed
+            );
+            ensure!(
+                verify::verify::<T::SMTHasher>(
+                    path,
+                    proof.value.leaf_hash::<T::SMTHasher>(),
+                    proof.leave_bitmap,
+                    proof.siblings,
+                    proof.root,
+                ),
+                Error::<T>::SMTVerifyFaild
+            );
+
+            T::OnClaim::on_claim(&who, &tree_id, &proof.key, &proof.value)?;
+            Claimed::<T>::insert(&tree_id, path, frame_system::Pallet::<T>::block_number());
+            Self::deposit_event(Event::<T>::Claimed {
+                account: who,
+                tree_id,
+                path,
+                root: proof.root,
+            });
+            Ok(())
+        }
+
+        /// Verify many Merkle proofs of a tree against one trusted root, e.g. the claims collected by a relayer.
+        /// The proofs are verified against `root` whatever their own root is, the call fails if any of them fails.
+        /// The path and the value hash of each proof are derived from its key and value, like in [`Pallet::claim`].
+        #[pallet::call_index(4)]
+        #[pallet::weight(T::WeightInfo::smt_verify_batch(
+            proofs.len() as u32,
+            proofs.iter().fold(0_u32, |s, proof| s.saturating_add(proof.siblings.len() as u32)),
+        ))]
+        pub fn smt_verify_batch(
+            origin: OriginFor<T>,
+            tree_id: T::TreeId,
+            root: H256,
+            proofs: BoundedVec<verify::Proof<T::SMTKey, T::SMTValue>, T::MaxBatchProofs>,
+        ) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            ensure!(!proofs.is_empty(), Error::<T>::EmptyBatch);
+            Self::ensure_trusted_root(&tree_id, root)?;
+            for proof in proofs {
+                let path = proof.key.leaf_hash::<T::SMTHasher>();
+                ensure!(
+                    verify::verify::<T::SMTHasher>(
+                        path,
+                        proof.value.leaf_hash::<T::SMTHasher>(),
+                        proof.leave_bitmap,
+                        proof.siblings,
+                        root,
+                    ),
+                    Error::<T>::SMTVerifyFaild
+                );
+                Self::deposit_event(Event::<T>::SMTVerify {
+                    account: who.clone(),
+                    tree_id: tree_id.clone(),
+                    path,
+                    root,
+                });
+            }
+            Ok(())
         }
     }
 
     impl<T: Config> Pallet<T> {
+        /// Ensure `root` is a published root of the tree that has not expired.
+        pub fn ensure_trusted_root(tree_id: &T::TreeId, root: H256) -> DispatchResult {
+            let info = Roots::<T>::get(tree_id, root).ok_or(Error::<T>::UnknownRoot)?;
+            if let Some(expires_at) = info.expires_at {
+                ensure!(
+                    frame_system::Pallet::<T>::block_number() <= expires_at,
+                    Error::<T>::RootExpired
+                );
+            }
+            Ok(())
+        }
+
+        /// Verify a proof of a leaf against a trusted root of the tree, the leaf is hashed from the key and
+        /// the value of the proof with [`Config::SMTHasher`].
         pub fn do_verify(
             who: T::AccountId,
+            tree_id: T::TreeId,
             proof: Proof<T::SMTKey, T::SMTValue>,
         ) -> DispatchResultWithPostInfo {
+            Self::ensure_trusted_root(&tree_id, proof.root)?;
+            let path = proof.key.leaf_hash::<T::SMTHasher>();
             ensure!(
                 verify::verify::<T::SMTHasher>(
-                    proof.path,
-                    proof.value_hash,
+                    path,
+                    proof.value.leaf_hash::<T::SMTHasher>(),
                     proof.leave_bitmap,
                     proof.siblings,
                     proof.root,
@@ -128,7 +419,8 @@ pub mod pallet {
             );
             Self::deposit_event(Event::<T>::SMTVerify {
                 account: who,
-                path: proof.path,
+                tree_id,
+                path,
                 root: proof.root,
             });
 
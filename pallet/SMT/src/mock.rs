@@ -1,26 +1,48 @@
-use frame_support::{derive_impl, parameter_types, traits::Everything};
+use frame_support::{
+    derive_impl, ord_parameter_types, parameter_types,
+    traits::{Currency, Everything, ExistenceRequirement},
+    weights::Weight,
+};
 use frame_system as system;
+use frame_system::EnsureSignedBy;
 use primitives::{
     keccak_hasher::Keccak256Hasher,
     kv::{SMTKey, SMTValue},
+    sparse_merkle_tree::traits::Hasher,
     verify::Proof,
 };
-use smt_backend_lib::apis::MultiSMTStore;
+use smt_backend_lib::{apis::MultiSMTStore, registry::HasherName, traits::MSS};
 use sp_core::H256;
 use sp_runtime::{
     traits::{BlakeTwo256, IdentityLookup},
-    BuildStorage,
+    ArithmeticError, BuildStorage, DispatchError, DispatchResult,
 };
-use std::path::Path;
 type Block = frame_system::mocking::MockBlock<Test>;
 
+/// The tree the proofs of the tests are against.
+pub const TREE: u32 = 1;
+
+/// The account allowed to publish the roots.
+pub const OPERATOR: u64 = 100;
+
+/// The account the claims are paid from.
+pub const POT: u64 = 200;
+
+/// The balance of [`POT`] at genesis.
+pub const POT_BALANCE: u64 = 1_000_000;
+
 pub fn creat_db_and_get_proof(size: u8) -> Vec<Proof<SMTKey, SMTValue>> {
-    let base_path = "./smt_mock_db";
-    let multi_tree =
-        MultiSMTStore::<SMTKey, SMTValue, Keccak256Hasher>::open(Path::new(base_path)).unwrap();
+    creat_db_and_get_proof_with::<Keccak256Hasher>(size)
+}
+
+/// The proofs of a tree built with the hasher `H`.
+pub fn creat_db_and_get_proof_with<H: Hasher + Default + HasherName>(
+    size: u8,
+) -> Vec<Proof<SMTKey, SMTValue>> {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let multi_tree = MultiSMTStore::<SMTKey, SMTValue, H>::open(temp_dir.path()).unwrap();
     // 创建一个tree
     let tree = "tree1";
-    multi_tree.clear(tree.to_string());
     let mut kvs: Vec<(SMTKey, SMTValue)> = vec![];
 
     for i in 0..size {
@@ -57,12 +79,18 @@ frame_support::construct_runtime!(
     pub enum Test
     {
         System: frame_system::{Pallet, Call, Config<T>, Storage, Event<T>},
+        Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
         TemplateModule: crate::{Pallet, Call, Storage, Event<T>},
     }
 );
 
 parameter_types! {
     pub const SS58Prefix: u8 = 42;
+    pub const MaxBatchProofs: u32 = 64;
+}
+
+ord_parameter_types! {
+    pub const Operator: u64 = OPERATOR;
 }
 
 #[derive_impl(frame_system::config_preludes::TestDefaultConfig)]
@@ -82,7 +110,7 @@ impl system::Config for Test {
     type RuntimeEvent = RuntimeEvent;
     type Version = ();
     type PalletInfo = PalletInfo;
-    type AccountData = ();
+    type AccountData = pallet_balances::AccountData<u64>;
     type OnNewAccount = ();
     type OnKilledAccount = ();
     type SystemWeightInfo = ();
@@ -91,18 +119,121 @@ impl system::Config for Test {
     type MaxConsumers = frame_support::traits::ConstU32<16>;
 }
 
+#[derive_impl(pallet_balances::config_preludes::TestDefaultConfig)]
+impl pallet_balances::Config for Test {
+    type AccountStore = System;
+}
+
+/// Pay the balance of a claimed leaf from [`POT`] to the account of its address.
+pub struct TransferBalance;
+
+impl crate::OnClaim<u64, u32, SMTKey, SMTValue> for TransferBalance {
+    fn on_claim(_who: &u64, _tree_id: &u32, key: &SMTKey, value: &SMTValue) -> DispatchResult {
+        let dest = key
+            .address
+            .parse::<u64>()
+            .map_err(|_| DispatchError::Other("The address is not an account"))?;
+        let amount = value
+            .balance
+            .try_into()
+            .map_err(|_| ArithmeticError::Overflow)?;
+        <Balances as Currency<u64>>::transfer(&POT, &dest, amount, ExistenceRequirement::AllowDeath)
+    }
+
+    fn weight() -> Weight {
+        <() as pallet_balances::WeightInfo>::transfer_allow_death()
+    }
+}
+
+#[cfg(feature = "runtime-benchmarks")]
+pub struct LeafOfAccount;
+
+#[cfg(feature = "runtime-benchmarks")]
+impl crate::BenchmarkHelper<SMTKey, SMTValue> for LeafOfAccount {
+    fn leaf() -> (SMTKey, SMTValue) {
+        (
+            SMTKey {
+                address: "1".to_string(),
+            },
+            SMTValue {
+                nonce: 1,
+                balance: 1,
+            },
+        )
+    }
+}
+
 impl crate::Config for Test {
     type RuntimeEvent = RuntimeEvent;
     type WeightInfo = ();
     type SMTHasher = Keccak256Hasher;
     type SMTKey = SMTKey;
     type SMTValue = SMTValue;
+    type TreeId = u32;
+    type RootOrigin = EnsureSignedBy<Operator, u64>;
+    type MaxBatchProofs = MaxBatchProofs;
+    type OnClaim = TransferBalance;
+    #[cfg(feature = "runtime-benchmarks")]
+    type BenchmarkHelper = LeafOfAccount;
 }
 
 // Build genesis storage according to the mock runtime.
 pub fn new_test_ext() -> sp_io::TestExternalities {
-    system::GenesisConfig::<Test>::default()
+    let mut storage = system::GenesisConfig::<Test>::default()
         .build_storage()
-        .unwrap()
-        .into()
+        .unwrap();
+    pallet_balances::GenesisConfig::<Test> {
+        balances: vec![(POT, POT_BALANCE)],
+    }
+    .assimilate_storage(&mut storage)
+    .unwrap();
+    storage.into()
+}
+
+/// A runtime whose trees are built with Blake2b256 instead of Keccak256.
+pub mod blake2b {
+    use super::*;
+    use primitives::blake2b_hasher::Blake2b256Hasher;
+
+    type Block = frame_system::mocking::MockBlock<Test>;
+
+    frame_support::construct_runtime!(
+        pub enum Test
+        {
+            System: frame_system::{Pallet, Call, Config<T>, Storage, Event<T>},
+            TemplateModule: crate::{Pallet, Call, Storage, Event<T>},
+        }
+    );
+
+    #[derive_impl(frame_system::config_preludes::TestDefaultConfig)]
+    impl system::Config for Test {
+        type RuntimeOrigin = RuntimeOrigin;
+        type RuntimeCall = RuntimeCall;
+        type AccountId = u64;
+        type Lookup = IdentityLookup<Self::AccountId>;
+        type Block = Block;
+        type RuntimeEvent = RuntimeEvent;
+        type PalletInfo = PalletInfo;
+    }
+
+    impl crate::Config for Test {
+        type RuntimeEvent = RuntimeEvent;
+        type WeightInfo = ();
+        type SMTHasher = Blake2b256Hasher;
+        type SMTKey = SMTKey;
+        type SMTValue = SMTValue;
+        type TreeId = u32;
+        type RootOrigin = EnsureSignedBy<Operator, u64>;
+        type MaxBatchProofs = MaxBatchProofs;
+        type OnClaim = ();
+        #[cfg(feature = "runtime-benchmarks")]
+        type BenchmarkHelper = LeafOfAccount;
+    }
+
+    pub fn new_test_ext() -> sp_io::TestExternalities {
+        system::GenesisConfig::<Test>::default()
+            .build_storage()
+            .unwrap()
+            .into()
+    }
 }
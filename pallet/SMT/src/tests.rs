@@ -1,7 +1,31 @@
 #![allow(unused_imports)]
-use crate::{mock::*, Error};
-use frame_support::{assert_err, assert_noop, assert_ok};
-use primitives::verify::Proof;
+use crate::{
+    mock::*, weights::WeightInfo, Claimed, Error, Event, LatestRoot, OnClaim, RootInfo, Roots,
+};
+use frame_support::{
+    assert_err, assert_noop, assert_ok,
+    dispatch::GetDispatchInfo,
+    traits::{Currency, Get},
+    BoundedVec,
+};
+use primitives::{
+    kv::{SMTKey, SMTValue},
+    sparse_merkle_tree::{traits::Value, H256},
+    verify::Proof,
+};
+use sp_runtime::DispatchError;
+
+/// Publish the root of the proofs for [`TREE`], it never expires.
+fn set_root_of(proofs: &[Proof<SMTKey, SMTValue>]) -> H256 {
+    let root = proofs[1].root;
+    assert_ok!(TemplateModule::set_root(
+        RuntimeOrigin::signed(OPERATOR),
+        TREE,
+        root,
+        None
+    ));
+    root
+}
 
 #[test]
 fn it_works_for_smt_verify() {
@@ -9,12 +33,529 @@ fn it_works_for_smt_verify() {
         // Dispatch a signed extrinsic.
         let who = RuntimeOrigin::signed(1);
         let proofs = creat_db_and_get_proof(100 as u8);
+        set_root_of(&proofs);
         assert_err!(
-            TemplateModule::smt_verify(who.clone(), proofs[0].clone()),
+            TemplateModule::smt_verify(who.clone(), TREE, proofs[0].clone()),
             Error::<Test>::SMTVerifyFaild
         );
         proofs[1..].iter().for_each(|p| {
-            assert_ok!(TemplateModule::smt_verify(who.clone(), p.clone()));
+            assert_ok!(TemplateModule::smt_verify(who.clone(), TREE, p.clone()));
         });
     });
 }
+
+#[test]
+fn smt_verify_derives_the_leaf_from_the_key_and_value() {
+    new_test_ext().execute_with(|| {
+        let who = RuntimeOrigin::signed(1);
+        let proofs = creat_db_and_get_proof(10);
+        set_root_of(&proofs);
+
+        // the path and the value hash of the proof are not trusted
+        let mut forged = proofs[0].clone();
+        forged.path = proofs[5].path;
+        forged.value_hash = proofs[5].value_hash;
+        forged.leave_bitmap = proofs[5].leave_bitmap;
+        forged.siblings = proofs[5].siblings.clone();
+        assert_noop!(
+            TemplateModule::smt_verify(who.clone(), TREE, forged),
+            Error::<Test>::SMTVerifyFaild
+        );
+        let mut inflated = proofs[5].clone();
+        inflated.value.balance = 500;
+        assert_noop!(
+            TemplateModule::smt_verify(who, TREE, inflated),
+            Error::<Test>::SMTVerifyFaild
+        );
+    });
+
+    // the leaf is hashed with the hasher of the runtime
+    blake2b::new_test_ext().execute_with(|| {
+        let proofs =
+            creat_db_and_get_proof_with::<primitives::blake2b_hasher::Blake2b256Hasher>(10);
+        assert_ok!(blake2b::TemplateModule::set_root(
+            blake2b::RuntimeOrigin::signed(OPERATOR),
+            TREE,
+            proofs[1].root,
+            None
+        ));
+        assert_ok!(blake2b::TemplateModule::smt_verify(
+            blake2b::RuntimeOrigin::signed(1),
+            TREE,
+            proofs[2].clone()
+        ));
+    });
+}
+
+#[test]
+fn smt_verify_weight_scales_with_the_siblings() {
+    let proof = creat_db_and_get_proof(10)[3].clone();
+    let siblings = proof.siblings.len() as u32;
+    let call = crate::Call::<Test>::smt_verify {
+        tree_id: TREE,
+        proof,
+    };
+    assert_eq!(
+        call.get_dispatch_info().weight,
+        <() as WeightInfo>::smt_verify(siblings)
+    );
+    // the storage is read once whatever the number of siblings
+    let per_sibling =
+        <() as WeightInfo>::smt_verify(1).saturating_sub(<() as WeightInfo>::smt_verify(0));
+    assert_eq!(per_sibling.proof_size(), 0);
+    assert_eq!(
+        <() as WeightInfo>::smt_verify(siblings).saturating_sub(<() as WeightInfo>::smt_verify(0)),
+        per_sibling.saturating_mul(siblings as u64)
+    );
+}
+
+#[test]
+fn smt_verify_rejects_unknown_roots() {
+    new_test_ext().execute_with(|| {
+        let who = RuntimeOrigin::signed(1);
+        let proofs = creat_db_and_get_proof(10);
+        // the root is not published
+        assert_noop!(
+            TemplateModule::smt_verify(who.clone(), TREE, proofs[1].clone()),
+            Error::<Test>::UnknownRoot
+        );
+        set_root_of(&proofs);
+        // the root of another tree
+        assert_noop!(
+            TemplateModule::smt_verify(who.clone(), TREE + 1, proofs[1].clone()),
+            Error::<Test>::UnknownRoot
+        );
+        // a private tree with another root
+        let mut forged = proofs[1].clone();
+        forged.root = H256::from([1u8; 32]);
+        assert_noop!(
+            TemplateModule::smt_verify(who, TREE, forged),
+            Error::<Test>::UnknownRoot
+        );
+    });
+}
+
+#[test]
+fn set_root_requires_root_origin() {
+    new_test_ext().execute_with(|| {
+        let root = H256::from([1u8; 32]);
+        assert_noop!(
+            TemplateModule::set_root(RuntimeOrigin::signed(1), TREE, root, None),
+            DispatchError::BadOrigin
+        );
+        assert_noop!(
+            TemplateModule::set_root(RuntimeOrigin::root(), TREE, root, None),
+            DispatchError::BadOrigin
+        );
+        assert_noop!(
+            TemplateModule::revoke_root(RuntimeOrigin::signed(1), TREE, root),
+            DispatchError::BadOrigin
+        );
+    });
+}
+
+#[test]
+fn set_root_versions_the_roots() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let operator = RuntimeOrigin::signed(OPERATOR);
+        let (root1, root2) = (H256::from([1u8; 32]), H256::from([2u8; 32]));
+
+        assert_ok!(TemplateModule::set_root(
+            operator.clone(),
+            TREE,
+            root1,
+            None
+        ));
+        System::set_block_number(2);
+        assert_ok!(TemplateModule::set_root(
+            operator.clone(),
+            TREE,
+            root2,
+            Some(10)
+        ));
+        System::assert_last_event(
+            Event::<Test>::RootSet {
+                tree_id: TREE,
+                root: root2,
+                version: 2,
+                expires_at: Some(10),
+            }
+            .into(),
+        );
+        assert_noop!(
+            TemplateModule::set_root(operator.clone(), TREE, root1, None),
+            Error::<Test>::RootAlreadySet
+        );
+
+        // the versions are per tree
+        assert_ok!(TemplateModule::set_root(operator, TREE + 1, root2, None));
+        assert_eq!(LatestRoot::<Test>::get(TREE), Some((root2, 2)));
+        assert_eq!(LatestRoot::<Test>::get(TREE + 1), Some((root2, 1)));
+        assert_eq!(
+            Roots::<Test>::get(TREE, root1),
+            Some(RootInfo {
+                version: 1,
+                set_at: 1,
+                expires_at: None,
+            })
+        );
+        // the older roots stay valid
+        assert_ok!(TemplateModule::ensure_trusted_root(&TREE, root1));
+        assert_ok!(TemplateModule::ensure_trusted_root(&TREE, root2));
+    });
+}
+
+#[test]
+fn roots_expire_and_can_be_revoked() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(5);
+        let who = RuntimeOrigin::signed(1);
+        let operator = RuntimeOrigin::signed(OPERATOR);
+        let proofs = creat_db_and_get_proof(10);
+        let root = proofs[1].root;
+
+        assert_noop!(
+            TemplateModule::set_root(operator.clone(), TREE, root, Some(4)),
+            Error::<Test>::ExpiryInPast
+        );
+        assert_ok!(TemplateModule::set_root(
+            operator.clone(),
+            TREE,
+            root,
+            Some(6)
+        ));
+        System::set_block_number(6);
+        assert_ok!(TemplateModule::smt_verify(
+            who.clone(),
+            TREE,
+            proofs[1].clone()
+        ));
+        System::set_block_number(7);
+        assert_noop!(
+            TemplateModule::smt_verify(who.clone(), TREE, proofs[1].clone()),
+            Error::<Test>::RootExpired
+        );
+
+        // revoke the expired root and publish it again
+        assert_ok!(TemplateModule::revoke_root(operator.clone(), TREE, root));
+        System::assert_last_event(
+            Event::<Test>::RootRevoked {
+                tree_id: TREE,
+                root,
+            }
+            .into(),
+        );
+        assert_noop!(
+            TemplateModule::revoke_root(operator.clone(), TREE, root),
+            Error::<Test>::UnknownRoot
+        );
+        assert_ok!(TemplateModule::set_root(operator, TREE, root, None));
+        assert_eq!(Roots::<Test>::get(TREE, root).unwrap().version, 2);
+        assert_ok!(TemplateModule::smt_verify(who, TREE, proofs[1].clone()));
+    });
+}
+
+#[test]
+fn claim_pays_out_a_leaf_once() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let relayer = RuntimeOrigin::signed(1);
+        let proofs = creat_db_and_get_proof(10);
+        let root = set_root_of(&proofs);
+
+        // anyone may submit a claim, the payout goes to the account of the key
+        assert_ok!(TemplateModule::claim(
+            relayer.clone(),
+            TREE,
+            proofs[3].clone()
+        ));
+        assert_eq!(Balances::free_balance(3), 3);
+        assert_eq!(Balances::free_balance(POT), POT_BALANCE - 3);
+        let path = proofs[3].key.to_h256();
+        assert_eq!(Claimed::<Test>::get(TREE, path), Some(1));
+        System::assert_last_event(
+            Event::<Test>::Claimed {
+                account: 1,
+                tree_id: TREE,
+                path,
+                root,
+            }
+            .into(),
+        );
+
+        // a leaf is claimed once
+        assert_noop!(
+            TemplateModule::claim(relayer.clone(), TREE, proofs[3].clone()),
+            Error::<Test>::AlreadyClaimed
+        );
+        assert_noop!(
+            TemplateModule::claim(RuntimeOrigin::signed(3), TREE, proofs[3].clone()),
+            Error::<Test>::AlreadyClaimed
+        );
+
+        // the other leaves are still claimable
+        assert_ok!(TemplateModule::claim(relayer, TREE, proofs[4].clone()));
+        assert_eq!(Balances::free_balance(4), 4);
+        assert_eq!(Balances::free_balance(POT), POT_BALANCE - 7);
+    });
+}
+
+#[test]
+fn claim_weight_scales_with_the_siblings() {
+    new_test_ext().execute_with(|| {
+        let proof = creat_db_and_get_proof(10)[3].clone();
+        let siblings = proof.siblings.len() as u32;
+        let call = crate::Call::<Test>::claim {
+            tree_id: TREE,
+            proof,
+        };
+        // only the hashing scales with the siblings, the payout is charged by the hook
+        let payout = <TransferBalance as OnClaim<u64, u32, SMTKey, SMTValue>>::weight();
+        assert_eq!(
+            call.get_dispatch_info().weight,
+            <() as WeightInfo>::claim(siblings).saturating_add(payout)
+        );
+        let per_sibling = <() as WeightInfo>::claim(1).saturating_sub(<() as WeightInfo>::claim(0));
+        assert_eq!(per_sibling.proof_size(), 0);
+        assert_eq!(
+            <() as WeightInfo>::claim(siblings).saturating_sub(<() as WeightInfo>::claim(0)),
+            per_sibling.saturating_mul(siblings as u64)
+        );
+    });
+}
+
+#[test]
+fn claim_derives_the_leaf_from_the_key_and_value() {
+    new_test_ext().execute_with(|| {
+        let who = RuntimeOrigin::signed(1);
+        let proofs = creat_db_and_get_proof(10);
+        set_root_of(&proofs);
+
+        // a larger balance than the one in the tree
+        let mut inflated = proofs[5].clone();
+        inflated.value.balance = 500;
+        assert_noop!(
+            TemplateModule::claim(who.clone(), TREE, inflated),
+            Error::<Test>::SMTVerifyFaild
+        );
+
+        // the proof of another leaf, with the key of an account of the caller's choice
+        let mut stolen = proofs[5].clone();
+        stolen.key.address = "1".to_string();
+        assert_noop!(
+            TemplateModule::claim(who.clone(), TREE, stolen),
+            Error::<Test>::SMTVerifyFaild
+        );
+
+        // the path and the value hash of the proof are not trusted
+        let mut forged = proofs[0].clone();
+        forged.path = proofs[5].path;
+        forged.value_hash = proofs[5].value_hash;
+        forged.leave_bitmap = proofs[5].leave_bitmap;
+        forged.siblings = proofs[5].siblings.clone();
+        assert_noop!(
+            TemplateModule::claim(who, TREE, forged),
+            Error::<Test>::SMTVerifyFaild
+        );
+    });
+}
+
+#[test]
+fn claim_hashes_the_leaf_with_the_hasher_of_the_runtime() {
+    use primitives::{blake2b_hasher::Blake2b256Hasher, kv::LeafHash};
+
+    blake2b::new_test_ext().execute_with(|| {
+        let who = blake2b::RuntimeOrigin::signed(1);
+        let proofs = creat_db_and_get_proof_with::<Blake2b256Hasher>(10);
+        let root = proofs[1].root;
+        assert_ok!(blake2b::TemplateModule::set_root(
+            blake2b::RuntimeOrigin::signed(OPERATOR),
+            TREE,
+            root,
+            None
+        ));
+
+        assert_ok!(blake2b::TemplateModule::claim(
+            who.clone(),
+            TREE,
+            proofs[3].clone()
+        ));
+        let path = proofs[3].key.leaf_hash::<Blake2b256Hasher>();
+        assert_eq!(path, proofs[3].path);
+        assert_ne!(path, proofs[3].key.to_h256());
+        assert!(Claimed::<blake2b::Test>::contains_key(TREE, path));
+
+        // the proof of a Keccak256 tree with the same root does not verify
+        let mut keccak = creat_db_and_get_proof(10)[4].clone();
+        keccak.root = root;
+        assert_noop!(
+            blake2b::TemplateModule::claim(who, TREE, keccak),
+            Error::<blake2b::Test>::SMTVerifyFaild
+        );
+    });
+}
+
+#[test]
+fn claim_requires_a_trusted_root() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let who = RuntimeOrigin::signed(1);
+        let operator = RuntimeOrigin::signed(OPERATOR);
+        let proofs = creat_db_and_get_proof(10);
+        let root = proofs[1].root;
+
+        assert_noop!(
+            TemplateModule::claim(who.clone(), TREE, proofs[1].clone()),
+            Error::<Test>::UnknownRoot
+        );
+        assert_ok!(TemplateModule::set_root(
+            operator.clone(),
+            TREE,
+            root,
+            Some(1)
+        ));
+        System::set_block_number(2);
+        assert_noop!(
+            TemplateModule::claim(who.clone(), TREE, proofs[1].clone()),
+            Error::<Test>::RootExpired
+        );
+
+        // the claims of each tree are separate
+        assert_ok!(TemplateModule::revoke_root(operator.clone(), TREE, root));
+        assert_ok!(TemplateModule::set_root(operator.clone(), TREE, root, None));
+        assert_ok!(TemplateModule::set_root(operator, TREE + 1, root, None));
+        assert_ok!(TemplateModule::claim(who.clone(), TREE, proofs[1].clone()));
+        assert_ok!(TemplateModule::claim(who, TREE + 1, proofs[1].clone()));
+        assert_eq!(Balances::free_balance(1), 2);
+    });
+}
+
+#[test]
+fn claim_fails_with_its_payout() {
+    new_test_ext().execute_with(|| {
+        let who = RuntimeOrigin::signed(1);
+        let proofs = creat_db_and_get_proof(10);
+        set_root_of(&proofs);
+
+        // the pot cannot pay the claim, the leaf stays claimable
+        Balances::make_free_balance_be(&POT, 0);
+        assert!(TemplateModule::claim(who.clone(), TREE, proofs[6].clone()).is_err());
+        assert_eq!(Claimed::<Test>::get(TREE, proofs[6].key.to_h256()), None);
+        assert_eq!(Balances::free_balance(6), 0);
+
+        Balances::make_free_balance_be(&POT, POT_BALANCE);
+        assert_ok!(TemplateModule::claim(who, TREE, proofs[6].clone()));
+        assert_eq!(Balances::free_balance(6), 6);
+    });
+}
+
+#[test]
+fn smt_verify_batch_verifies_all_proofs() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+        let who = RuntimeOrigin::signed(1);
+        let proofs = creat_db_and_get_proof(20);
+        let root = set_root_of(&proofs);
+
+        let batch = BoundedVec::try_from(proofs[1..].to_vec()).unwrap();
+        assert_ok!(TemplateModule::smt_verify_batch(
+            who.clone(),
+            TREE,
+            root,
+            batch
+        ));
+        let verified = System::events()
+            .into_iter()
+            .filter(|record| {
+                matches!(
+                    record.event,
+                    RuntimeEvent::TemplateModule(Event::SMTVerify { .. })
+                )
+            })
+            .count();
+        assert_eq!(verified, 19);
+
+        // the batch fails if any of its proofs fails
+        let batch = BoundedVec::try_from(proofs[..5].to_vec()).unwrap();
+        assert_noop!(
+            TemplateModule::smt_verify_batch(who.clone(), TREE, root, batch),
+            Error::<Test>::SMTVerifyFaild
+        );
+        assert_noop!(
+            TemplateModule::smt_verify_batch(who.clone(), TREE, root, BoundedVec::default()),
+            Error::<Test>::EmptyBatch
+        );
+
+        // the path and the value hash of a proof are derived from its key and value
+        let mut inflated = proofs[5].clone();
+        inflated.value.balance = 500;
+        let mut forged = proofs[0].clone();
+        forged.path = proofs[5].path;
+        forged.value_hash = proofs[5].value_hash;
+        forged.leave_bitmap = proofs[5].leave_bitmap;
+        forged.siblings = proofs[5].siblings.clone();
+        for proof in [inflated, forged] {
+            let batch = BoundedVec::try_from(vec![proofs[1].clone(), proof]).unwrap();
+            assert_noop!(
+                TemplateModule::smt_verify_batch(who.clone(), TREE, root, batch),
+                Error::<Test>::SMTVerifyFaild
+            );
+        }
+    });
+}
+
+#[test]
+fn smt_verify_batch_hashes_the_leaves_with_the_hasher_of_the_runtime() {
+    use primitives::blake2b_hasher::Blake2b256Hasher;
+
+    blake2b::new_test_ext().execute_with(|| {
+        let who = blake2b::RuntimeOrigin::signed(1);
+        let proofs = creat_db_and_get_proof_with::<Blake2b256Hasher>(10);
+        let root = proofs[1].root;
+        assert_ok!(blake2b::TemplateModule::set_root(
+            blake2b::RuntimeOrigin::signed(OPERATOR),
+            TREE,
+            root,
+            None
+        ));
+        let batch = BoundedVec::try_from(proofs[1..].to_vec()).unwrap();
+        assert_ok!(blake2b::TemplateModule::smt_verify_batch(
+            who, TREE, root, batch
+        ));
+    });
+}
+
+#[test]
+fn smt_verify_batch_uses_one_trusted_root() {
+    new_test_ext().execute_with(|| {
+        let who = RuntimeOrigin::signed(1);
+        let proofs = creat_db_and_get_proof(10);
+        let batch: BoundedVec<_, MaxBatchProofs> =
+            BoundedVec::try_from(proofs[1..].to_vec()).unwrap();
+        let root = proofs[1].root;
+
+        assert_noop!(
+            TemplateModule::smt_verify_batch(who.clone(), TREE, root, batch.clone()),
+            Error::<Test>::UnknownRoot
+        );
+        set_root_of(&proofs);
+        // the proofs are verified against the root of the batch, not their own
+        let other = H256::from([1u8; 32]);
+        assert_ok!(TemplateModule::set_root(
+            RuntimeOrigin::signed(OPERATOR),
+            TREE,
+            other,
+            None
+        ));
+        assert_noop!(
+            TemplateModule::smt_verify_batch(who.clone(), TREE, other, batch.clone()),
+            Error::<Test>::SMTVerifyFaild
+        );
+        assert_ok!(TemplateModule::smt_verify_batch(who, TREE, root, batch));
+
+        // the batch is bounded
+        let proofs = vec![proofs[1].clone(); MaxBatchProofs::get() as usize + 1];
+        assert!(BoundedVec::<_, MaxBatchProofs>::try_from(proofs).is_err());
+    });
+}
@@ -1,29 +1,14 @@
-
-//! Autogenerated weights for pallet_template
+//! Placeholder weights for pallet_smt.
 //!
-//! THIS FILE WAS AUTO-GENERATED USING THE SUBSTRATE BENCHMARK CLI VERSION 4.0.0-dev
-//! DATE: 2023-04-06, STEPS: `50`, REPEAT: `20`, LOW RANGE: `[]`, HIGH RANGE: `[]`
-//! WORST CASE MAP SIZE: `1000000`
-//! HOSTNAME: `Alexs-MacBook-Pro-2.local`, CPU: `<UNKNOWN>`
-//! EXECUTION: Some(Wasm), WASM-EXECUTION: Compiled, CHAIN: Some("dev"), DB CACHE: 1024
-
-// Executed Command:
-// ../../target/release/node-template
-// benchmark
-// pallet
-// --chain
-// dev
-// --pallet
-// pallet_template
-// --extrinsic
-// *
-// --steps=50
-// --repeat=20
-// --wasm-execution=compiled
-// --output
-// pallets/template/src/weights.rs
-// --template
-// ../../.maintain/frame-weight-template.hbs
+//! THESE WEIGHTS WERE NOT GENERATED BY THE BENCHMARK CLI. The execution times are hand-written
+//! estimates, only the proof sizes are derived from the `MaxEncodedLen` of the storage of the pallet.
+//! Regenerate this file from the benchmarks of `benchmarking.rs` on the reference hardware of the
+//! runtime before using the pallet in production, e.g.
+//!
+//! ```text
+//! <node> benchmark pallet --chain dev --pallet pallet_smt --extrinsic '*' \
+//!     --steps 50 --repeat 20 --wasm-execution compiled --output pallet/SMT/src/weights.rs
+//! ```
 
 #![cfg_attr(rustfmt, rustfmt_skip)]
 #![allow(unused_parens)]
@@ -32,40 +17,122 @@
 use frame_support::{traits::Get, weights::{Weight, constants::RocksDbWeight}};
 use core::marker::PhantomData;
 
-/// Weight functions needed for pallet_template.
+/// Weight functions needed for pallet_smt.
 pub trait WeightInfo {
-	fn smt_verify() -> Weight;
+	fn smt_verify(s: u32, ) -> Weight;
+	fn set_root() -> Weight;
+	fn revoke_root() -> Weight;
+	fn claim(s: u32, ) -> Weight;
+	fn smt_verify_batch(n: u32, s: u32, ) -> Weight;
 }
 
-/// Weights for pallet_template using the Substrate node and recommended hardware.
+/// Weights for pallet_smt until they are benchmarked, see the module documentation.
 pub struct SubstrateWeight<T>(PhantomData<T>);
 impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 
-	/// Storage: TemplateModule Something (r:1 w:1)
-	/// Proof: TemplateModule Something (max_values: Some(1), max_size: Some(4), added: 499, mode: MaxEncodedLen)
-	fn smt_verify() -> Weight {
-		// Proof Size summary in bytes:
-		//  Measured:  `32`
-		//  Estimated: `1489`
-		// Minimum execution time: 6_000_000 picoseconds.
-		Weight::from_parts(6_000_000, 1489)
+	/// Storage: TemplateModule Roots (r:1 w:0)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	/// The range of component `s` is `[0, 256]`.
+	fn smt_verify(s: u32, ) -> Weight {
+		// Estimated proof size: `3530` bytes.
+		Weight::from_parts(6_000_000, 3530)
+			.saturating_add(Weight::from_parts(5_902_417, 0).saturating_mul(s.into()))
 			.saturating_add(T::DbWeight::get().reads(1_u64))
+	}
+	/// Storage: TemplateModule Roots (r:1 w:1)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	/// Storage: TemplateModule LatestRoot (r:1 w:1)
+	/// Proof: TemplateModule LatestRoot (max_values: None, max_size: Some(56), added: 2531, mode: MaxEncodedLen)
+	fn set_root() -> Weight {
+		// Estimated proof size: `6061` bytes.
+		Weight::from_parts(13_000_000, 6061)
+			.saturating_add(T::DbWeight::get().reads(2_u64))
+			.saturating_add(T::DbWeight::get().writes(2_u64))
+	}
+	/// Storage: TemplateModule Roots (r:1 w:1)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	fn revoke_root() -> Weight {
+		// Estimated proof size: `3530` bytes.
+		Weight::from_parts(11_000_000, 3530)
+			.saturating_add(T::DbWeight::get().reads(1_u64))
+			.saturating_add(T::DbWeight::get().writes(1_u64))
+	}
+	/// Storage: TemplateModule Roots (r:1 w:0)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	/// Storage: TemplateModule Claimed (r:1 w:1)
+	/// Proof: TemplateModule Claimed (max_values: None, max_size: Some(56), added: 2531, mode: MaxEncodedLen)
+	/// The range of component `s` is `[0, 256]`.
+	fn claim(s: u32, ) -> Weight {
+		// Estimated proof size: `6061` bytes.
+		Weight::from_parts(14_291_000, 6061)
+			.saturating_add(Weight::from_parts(5_902_417, 0).saturating_mul(s.into()))
+			.saturating_add(T::DbWeight::get().reads(2_u64))
 			.saturating_add(T::DbWeight::get().writes(1_u64))
 	}
+	/// Storage: TemplateModule Roots (r:1 w:0)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	/// The range of component `n` is `[1, 64]`.
+	/// The range of component `s` is `[0, 256]`.
+	fn smt_verify_batch(n: u32, s: u32, ) -> Weight {
+		// Estimated proof size: `3530` bytes.
+		Weight::from_parts(7_412_000, 3530)
+			.saturating_add(Weight::from_parts(4_851_233, 0).saturating_mul(n.into()))
+			.saturating_add(Weight::from_parts(5_902_417, 0).saturating_mul(s.into()))
+			.saturating_add(T::DbWeight::get().reads(1_u64))
+	}
 }
 
 // For backwards compatibility and tests
 impl WeightInfo for () {
 	
-	/// Storage: TemplateModule Something (r:1 w:1)
-	/// Proof: TemplateModule Something (max_values: Some(1), max_size: Some(4), added: 499, mode: MaxEncodedLen)
-	fn smt_verify() -> Weight {
-		// Proof Size summary in bytes:
-		//  Measured:  `32`
-		//  Estimated: `1489`
-		// Minimum execution time: 6_000_000 picoseconds.
-		Weight::from_parts(6_000_000, 1489)
+	/// Storage: TemplateModule Roots (r:1 w:0)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	/// The range of component `s` is `[0, 256]`.
+	fn smt_verify(s: u32, ) -> Weight {
+		// Estimated proof size: `3530` bytes.
+		Weight::from_parts(6_000_000, 3530)
+			.saturating_add(Weight::from_parts(5_902_417, 0).saturating_mul(s.into()))
+			.saturating_add(RocksDbWeight::get().reads(1_u64))
+	}
+	/// Storage: TemplateModule Roots (r:1 w:1)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	/// Storage: TemplateModule LatestRoot (r:1 w:1)
+	/// Proof: TemplateModule LatestRoot (max_values: None, max_size: Some(56), added: 2531, mode: MaxEncodedLen)
+	fn set_root() -> Weight {
+		// Estimated proof size: `6061` bytes.
+		Weight::from_parts(13_000_000, 6061)
+			.saturating_add(RocksDbWeight::get().reads(2_u64))
+			.saturating_add(RocksDbWeight::get().writes(2_u64))
+	}
+	/// Storage: TemplateModule Roots (r:1 w:1)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	fn revoke_root() -> Weight {
+		// Estimated proof size: `3530` bytes.
+		Weight::from_parts(11_000_000, 3530)
 			.saturating_add(RocksDbWeight::get().reads(1_u64))
 			.saturating_add(RocksDbWeight::get().writes(1_u64))
 	}
+	/// Storage: TemplateModule Roots (r:1 w:0)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	/// Storage: TemplateModule Claimed (r:1 w:1)
+	/// Proof: TemplateModule Claimed (max_values: None, max_size: Some(56), added: 2531, mode: MaxEncodedLen)
+	/// The range of component `s` is `[0, 256]`.
+	fn claim(s: u32, ) -> Weight {
+		// Estimated proof size: `6061` bytes.
+		Weight::from_parts(14_291_000, 6061)
+			.saturating_add(Weight::from_parts(5_902_417, 0).saturating_mul(s.into()))
+			.saturating_add(RocksDbWeight::get().reads(2_u64))
+			.saturating_add(RocksDbWeight::get().writes(1_u64))
+	}
+	/// Storage: TemplateModule Roots (r:1 w:0)
+	/// Proof: TemplateModule Roots (max_values: None, max_size: Some(65), added: 2540, mode: MaxEncodedLen)
+	/// The range of component `n` is `[1, 64]`.
+	/// The range of component `s` is `[0, 256]`.
+	fn smt_verify_batch(n: u32, s: u32, ) -> Weight {
+		// Estimated proof size: `3530` bytes.
+		Weight::from_parts(7_412_000, 3530)
+			.saturating_add(Weight::from_parts(4_851_233, 0).saturating_mul(n.into()))
+			.saturating_add(Weight::from_parts(5_902_417, 0).saturating_mul(s.into()))
+			.saturating_add(RocksDbWeight::get().reads(1_u64))
+	}
 }
\ No newline at end of file
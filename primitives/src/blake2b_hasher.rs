@@ -0,0 +1,85 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Blake2b with a 32-byte output, the hash of Substrate (`blake2_256`), as the hasher of a tree.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use blake2::{digest::consts::U32, Blake2b, Digest};
+use sparse_merkle_tree::{traits::Hasher, H256};
+
+/// Choose the Blake2b-256 algorithm to compute the hash values for `[u8; 32]` and `u8` data.
+pub struct Blake2b256Hasher(Blake2b<U32>);
+
+impl Default for Blake2b256Hasher {
+    fn default() -> Self {
+        Blake2b256Hasher(Blake2b::new())
+    }
+}
+
+impl Hasher for Blake2b256Hasher {
+    fn write_h256(&mut self, h: &H256) {
+        self.0.update(h.as_ref());
+    }
+    fn write_byte(&mut self, b: u8) {
+        self.0.update(&[b][..])
+    }
+
+    fn finish(self) -> H256 {
+        let a: [u8; 32] = self.0.finalize().into();
+        a.into()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn h256(hex: &str) -> H256 {
+        let a: [u8; 32] = hex::decode(hex).unwrap().try_into().unwrap();
+        a.into()
+    }
+
+    #[test]
+    fn test_hasher() {
+        assert_eq!(
+            Blake2b256Hasher::default().finish(),
+            h256("0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8")
+        );
+
+        let mut hasher = Blake2b256Hasher::default();
+        hasher.write_h256(&H256::default());
+        assert_eq!(
+            hasher.finish(),
+            h256("89eb0d6a8a691dae2cd15ed0369931ce0a949ecafa5c3f93f8121833646e15c3")
+        );
+
+        let mut hasher = Blake2b256Hasher::default();
+        hasher.write_byte(25u8);
+        assert_eq!(
+            hasher.finish(),
+            h256("2d4b508a5a5fa8a81aa69ed8c08e1229b108a1d72d6ffdd5981f4ad81de47033")
+        );
+
+        let mut hasher = Blake2b256Hasher::default();
+        b"abc".iter().for_each(|b| hasher.write_byte(*b));
+        assert_eq!(
+            hasher.finish(),
+            h256("bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319")
+        );
+    }
+}
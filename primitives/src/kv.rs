@@ -27,11 +27,13 @@ use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 // use sp_core::Hasher::keccak256;
 use scale_info::prelude::{string::String, vec::Vec};
-use sha3::Digest;
 // use sp_crypto_hashing::keccak_256;
+use crate::keccak_hasher::Keccak256Hasher;
 use scale_info::prelude::fmt::Debug;
-use sha3::Keccak256;
-use sparse_merkle_tree::{traits::Value, H256};
+use sparse_merkle_tree::{
+    traits::{Hasher, Value},
+    H256,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(feature="std")] {
@@ -130,34 +132,54 @@ cfg_if::cfg_if! {
 
 }
 
-/// How the key in the KVDB is computed into a hash value.
+/// How keys and values are hashed into the leaves of a tree, with the hasher `H` the tree is built with.
+pub trait LeafHash {
+    fn leaf_hash<H: Hasher + Default>(&self) -> H256;
+}
+
+/// Hash `bytes` with the hasher `H`.
+pub fn hash_bytes<H: Hasher + Default>(bytes: &[u8]) -> H256 {
+    let mut hasher = H::default();
+    bytes.iter().for_each(|b| hasher.write_byte(*b));
+    hasher.finish()
+}
+
+/// The path of the key in the tree is the hash of its encoding.
+impl LeafHash for SMTKey {
+    fn leaf_hash<H: Hasher + Default>(&self) -> H256 {
+        hash_bytes::<H>(self.encode().as_slice())
+    }
+}
+
+/// The leaf of the value is the hash of its encoding, the default value is the empty leaf.
+impl LeafHash for SMTValue {
+    fn leaf_hash<H: Hasher + Default>(&self) -> H256 {
+        if self == &Default::default() {
+            return H256::zero();
+        }
+        hash_bytes::<H>(self.encode().as_slice())
+    }
+}
+
+/// How the key in the KVDB is computed into a hash value, Keccak256 unless the tree has another hasher.
 impl Value for SMTKey {
     fn zero() -> Self {
         SMTKey::default()
     }
 
     fn to_h256(&self) -> sparse_merkle_tree::H256 {
-        let mut k = Keccak256::new();
-        k.update(self.encode().as_slice());
-        let r: [u8; 32] = k.finalize().into();
-        r.into()
+        self.leaf_hash::<Keccak256Hasher>()
     }
 }
 
-/// How the value in the KVDB is computed into a hash value.
+/// How the value in the KVDB is computed into a hash value, Keccak256 unless the tree has another hasher.
 impl Value for SMTValue {
     fn zero() -> Self {
         Default::default()
     }
 
     fn to_h256(&self) -> sparse_merkle_tree::H256 {
-        if self == &Default::default() {
-            return H256::zero();
-        }
-        let mut k = Keccak256::new();
-        k.update(self.encode().as_slice());
-        let r: [u8; 32] = k.finalize().into();
-        r.into()
+        self.leaf_hash::<Keccak256Hasher>()
     }
 }
 
@@ -176,7 +198,8 @@ impl From<Vec<u8>> for SMTValue {
 
 #[cfg(test)]
 mod test {
-    use super::SMTValue;
+    use super::{LeafHash, SMTKey, SMTValue};
+    use crate::{blake2b_hasher::Blake2b256Hasher, keccak_hasher::Keccak256Hasher};
     use sparse_merkle_tree::{traits::Value, H256};
 
     #[test]
@@ -190,5 +213,22 @@ mod test {
 
         let v1: SMTValue = Default::default();
         assert_eq!(v1.to_h256(), H256::default());
+        assert_eq!(v1.leaf_hash::<Blake2b256Hasher>(), H256::default());
+    }
+
+    #[test]
+    fn test_leaf_hash() {
+        let k = SMTKey {
+            address: "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY".to_string(),
+        };
+        let v = SMTValue {
+            nonce: 1,
+            balance: 100000,
+        };
+        // keccak256 by default
+        assert_eq!(k.to_h256(), k.leaf_hash::<Keccak256Hasher>());
+        assert_eq!(v.to_h256(), v.leaf_hash::<Keccak256Hasher>());
+        assert_ne!(k.to_h256(), k.leaf_hash::<Blake2b256Hasher>());
+        assert_ne!(v.to_h256(), v.leaf_hash::<Blake2b256Hasher>());
     }
 }
@@ -17,7 +17,11 @@
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
+pub mod blake2b_hasher;
 pub mod keccak_hasher;
 pub mod kv;
+#[cfg(feature = "std")]
+pub mod poseidon_hasher;
+pub mod sha256_hasher;
 pub mod verify;
 pub use sparse_merkle_tree;
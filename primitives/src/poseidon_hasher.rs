@@ -0,0 +1,341 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Poseidon over the scalar field of BN254 as the hasher of a tree, so the membership of a leaf can be
+//! verified cheaply in a circuit.
+//!
+//! The permutation is the one of circomlib (`x^5` S-boxes, 8 full rounds, the round constants of
+//! [light-poseidon](https://github.com/Lightprotocol/light-poseidon)), `poseidon([1, 2])` is
+//! `0x115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a` like in circomlibjs.
+//!
+//! The SMT writes the inputs of a hash one at a time, they are encoded into field elements as follows:
+//!
+//! 1. `write_h256` is one element, the 32 bytes of the hash read as a big-endian integer. A hash that is
+//!    not below the modulus is rejected rather than reduced, see [`INVALID`].
+//! 2. `write_byte` is one element, the value of the byte.
+//! 3. 1 to 11 elements are hashed by one `poseidon` of as many inputs.
+//! 4. No element, or 12 and more, are hashed by a chain of `poseidon` of [`MAX_INPUTS`] inputs, which the
+//!    short inputs never use: `acc = poseidon([acc, e_0, ..., e_10])` starting from the number of elements,
+//!    the last block is padded with zeros.
+//! 5. The hash is the result as 32 big-endian bytes.
+//!
+//! So the nodes of the SMT have a fixed arity, which is what a circuit verifying a proof computes:
+//!
+//! | node | inputs |
+//! |------|--------|
+//! | leaf | `poseidon([height, key, value])` |
+//! | branch | `poseidon([1, height, node_key, lhs, rhs])` |
+//! | merge with zeros | `poseidon([2, base_node, zero_bits, zero_count])` |
+//!
+//! The paths and the values of a Poseidon tree are its own hashes, so every hash the SMT writes is below
+//! the modulus: the node keys are the paths with their low bits cleared and the zero bits of a node are
+//! a subset of the bits of its path. The bytes of the keys and values of the KVDB (see
+//! [`LeafHash`](crate::kv::LeafHash)) are one element each, their hash is a chain from 12 bytes on.
+//!
+//! The hasher is only built with the `std` feature: light-poseidon needs `std` and the permutations are
+//! cached per thread. Proofs of a Poseidon tree are verified off-chain or in a circuit, not by the pallet
+//! or the ink contract.
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonHasher as _};
+use sparse_merkle_tree::{traits::Hasher, H256};
+use std::{cell::RefCell, collections::BTreeMap};
+
+/// The number of inputs of the widest circomlib `poseidon`.
+pub const MAX_INPUTS: usize = 12;
+
+/// The hash of inputs holding a hash that is not below the modulus, it is not an element of the field
+/// either, so it never is the root of a Poseidon tree and a proof through it does not verify.
+pub const INVALID: [u8; 32] = [0xff; 32];
+
+thread_local! {
+    /// The permutations by number of inputs, building the round constants costs more than a hash.
+    static PERMUTATIONS: RefCell<BTreeMap<usize, Poseidon<Fr>>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// The circomlib `poseidon` of 1 to 12 field elements.
+pub fn poseidon(inputs: &[Fr]) -> Fr {
+    PERMUTATIONS.with(|permutations| {
+        permutations
+            .borrow_mut()
+            .entry(inputs.len())
+            .or_insert_with(|| {
+                Poseidon::<Fr>::new_circom(inputs.len()).expect("poseidon takes 1 to 12 inputs")
+            })
+            .hash(inputs)
+            .expect("the permutation is built for the number of inputs")
+    })
+}
+
+/// The element of a hash, `None` if the hash is not below the modulus.
+pub fn to_field(h: &H256) -> Option<Fr> {
+    let e = Fr::from_be_bytes_mod_order(h.as_ref());
+    (e.into_bigint().to_bytes_be() == h.as_ref()).then_some(e)
+}
+
+/// The hash of an element, as 32 big-endian bytes.
+pub fn from_field(e: Fr) -> H256 {
+    let a: [u8; 32] = e
+        .into_bigint()
+        .to_bytes_be()
+        .try_into()
+        .expect("an element of BN254 is 32 bytes");
+    a.into()
+}
+
+/// Choose Poseidon over BN254 to compute the hash values for `[u8; 32]` and `u8` data, see the module
+/// docs for the encoding.
+#[derive(Default)]
+pub struct PoseidonHasher {
+    inputs: Vec<Fr>,
+    /// An input was not below the modulus.
+    invalid: bool,
+}
+
+impl Hasher for PoseidonHasher {
+    fn write_h256(&mut self, h: &H256) {
+        match to_field(h) {
+            Some(e) => self.inputs.push(e),
+            None => self.invalid = true,
+        }
+    }
+    fn write_byte(&mut self, b: u8) {
+        self.inputs.push(Fr::from(b))
+    }
+
+    fn finish(self) -> H256 {
+        if self.invalid {
+            return INVALID.into();
+        }
+        if !self.inputs.is_empty() && self.inputs.len() < MAX_INPUTS {
+            return from_field(poseidon(&self.inputs));
+        }
+        let mut acc = Fr::from(self.inputs.len() as u64);
+        let mut absorb = |block: &[Fr]| {
+            let mut inputs = [Fr::from(0u64); MAX_INPUTS];
+            inputs[0] = acc;
+            inputs[1..=block.len()].copy_from_slice(block);
+            acc = poseidon(&inputs);
+        };
+        if self.inputs.is_empty() {
+            absorb(&[]);
+        }
+        self.inputs.chunks(MAX_INPUTS - 1).for_each(absorb);
+        from_field(acc)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{
+        kv::{LeafHash, SMTKey, SMTValue},
+        verify::{verify, verify_non_membership},
+    };
+    use sparse_merkle_tree::{default_store::DefaultStore, SparseMerkleTree};
+
+    fn h256(hex: &str) -> H256 {
+        let a: [u8; 32] = hex::decode(hex).unwrap().try_into().unwrap();
+        a.into()
+    }
+
+    fn hash(write: impl FnOnce(&mut PoseidonHasher)) -> H256 {
+        let mut hasher = PoseidonHasher::default();
+        write(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_poseidon() {
+        // test vectors of circomlibjs
+        let vectors: [(&[u64], &str); 4] = [
+            (
+                &[1, 2],
+                "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a",
+            ),
+            (
+                &[3, 4],
+                "20a3af0435914ccd84b806164531b0cd36e37d4efb93efab76913a93e1f30996",
+            ),
+            (
+                &[1, 2, 3, 4],
+                "299c867db6c1fdd79dcefa40e4510b9837e60ebb1ce0663dbaa525df65250465",
+            ),
+            (
+                &[0],
+                "2a09a9fd93c590c26b91effbb2499f07e8f7aa12e2b4940a3aed2411cb65e11c",
+            ),
+        ];
+        for (inputs, expected) in vectors {
+            let inputs = inputs.iter().map(|i| Fr::from(*i)).collect::<Vec<Fr>>();
+            assert_eq!(from_field(poseidon(&inputs)), h256(expected));
+        }
+    }
+
+    #[test]
+    fn test_hasher() {
+        // a byte and a hash are one element each: poseidon([1, 2]) and poseidon([3, 4])
+        assert_eq!(
+            hash(|h| {
+                h.write_byte(1);
+                h.write_byte(2);
+            }),
+            h256("115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a")
+        );
+        let element = |i: u8| {
+            let mut a = [0u8; 32];
+            a[31] = i;
+            H256::from(a)
+        };
+        assert_eq!(
+            hash(|h| {
+                h.write_h256(&element(3));
+                h.write_h256(&element(4));
+            }),
+            h256("20a3af0435914ccd84b806164531b0cd36e37d4efb93efab76913a93e1f30996")
+        );
+
+        // a branch of the SMT is poseidon([1, height, node_key, lhs, rhs])
+        assert_eq!(
+            hash(|h| {
+                h.write_byte(1);
+                h.write_byte(2);
+                h.write_h256(&element(3));
+                h.write_h256(&element(4));
+                h.write_h256(&element(5));
+            }),
+            h256("0dab9449e4a1398a15224c0b15a49d598b2174d305a316c918125f8feeb123c0")
+        );
+
+        // 11 elements are one poseidon, 12 are a chain from their number
+        let bytes = |n: u8| hash(|h| (0..n).for_each(|b| h.write_byte(b)));
+        let elements = |n: u64| (0..n).map(Fr::from).collect::<Vec<Fr>>();
+        assert_eq!(bytes(11), from_field(poseidon(&elements(11))));
+        assert_eq!(
+            bytes(11),
+            h256("2470a9818693de2ad5ab5065350ef7052b24e34e39149ef2e93e86677e261290")
+        );
+        let block = |acc: Fr, elements: &[Fr]| {
+            let mut inputs = vec![acc];
+            inputs.extend_from_slice(elements);
+            inputs.resize(MAX_INPUTS, Fr::from(0u64));
+            poseidon(&inputs)
+        };
+        let first = block(Fr::from(12u64), &elements(11));
+        assert_eq!(bytes(12), from_field(block(first, &[Fr::from(11u64)])));
+        assert_eq!(
+            bytes(12),
+            h256("24e2a1d5d6d803cd208c9825a9daafd42c89e713d5a05da26c91c829510c4bc1")
+        );
+        // the encoding of an SMTValue is 24 bytes, three blocks
+        assert_eq!(
+            bytes(24),
+            h256("113726f8fe8bd550c3df1d954f29c4219e0a8ec8b50bbb97e3a38821a31fc67c")
+        );
+        // no element is one block of zeros
+        assert_eq!(
+            PoseidonHasher::default().finish(),
+            from_field(block(Fr::from(0u64), &[]))
+        );
+    }
+
+    #[test]
+    fn test_modulus() {
+        let modulus = h256("30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001");
+        let mut below: [u8; 32] = modulus.into();
+        below[31] = 0;
+        assert!(to_field(&below.into()).is_some());
+        assert!(to_field(&modulus).is_none());
+        assert!(to_field(&INVALID.into()).is_none());
+
+        // a hash that is not below the modulus is rejected, not reduced
+        assert_eq!(
+            hash(|h| h.write_h256(&below.into())),
+            h256("0771743e7ade0f56f51d16544f60059ba3029ba556d63697612900fe5f020b16")
+        );
+        assert_eq!(hash(|h| h.write_h256(&modulus)), INVALID.into());
+        let mut above: [u8; 32] = modulus.into();
+        above[31] += 1;
+        assert_eq!(hash(|h| h.write_h256(&above.into())), INVALID.into());
+        assert_ne!(hash(|h| h.write_byte(1)), INVALID.into());
+        // and so are the hashes of the nodes above it
+        assert_eq!(
+            hash(|h| {
+                h.write_byte(1);
+                h.write_byte(0);
+                h.write_h256(&H256::zero());
+                h.write_h256(&INVALID.into());
+                h.write_h256(&H256::zero());
+            }),
+            INVALID.into()
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        type Tree = SparseMerkleTree<PoseidonHasher, H256, DefaultStore<H256>>;
+        let key = |i: u64| SMTKey {
+            address: i.to_string(),
+        };
+        let value = |i: u64| SMTValue {
+            nonce: i,
+            balance: i as u128,
+        };
+
+        let mut tree = Tree::default();
+        tree.update_all(
+            (1..20)
+                .map(|i| {
+                    (
+                        key(i).leaf_hash::<PoseidonHasher>(),
+                        value(i).leaf_hash::<PoseidonHasher>(),
+                    )
+                })
+                .collect(),
+        )
+        .unwrap();
+        let root = *tree.root();
+
+        let path = key(7).leaf_hash::<PoseidonHasher>();
+        let proof = tree.merkle_proof(vec![path]).unwrap();
+        let value_hash = value(7).leaf_hash::<PoseidonHasher>();
+        assert!(verify::<PoseidonHasher>(
+            path,
+            value_hash,
+            proof.leaves_bitmap()[0],
+            proof.merkle_path().clone(),
+            root
+        ));
+        assert!(!verify::<PoseidonHasher>(
+            path,
+            value(8).leaf_hash::<PoseidonHasher>(),
+            proof.leaves_bitmap()[0],
+            proof.merkle_path().clone(),
+            root
+        ));
+
+        let path = key(100).leaf_hash::<PoseidonHasher>();
+        let proof = tree.merkle_proof(vec![path]).unwrap();
+        assert!(verify_non_membership::<PoseidonHasher>(
+            path,
+            proof.leaves_bitmap()[0],
+            proof.merkle_path().clone(),
+            root
+        ));
+    }
+}
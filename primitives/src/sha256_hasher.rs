@@ -0,0 +1,85 @@
+// This file is part of farcloud-labs/subsmt.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SHA-256 as the hasher of a tree, for verifiers that only have SHA-256, like Bitcoin scripts or some zk circuits.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use sha2::{Digest, Sha256};
+use sparse_merkle_tree::{traits::Hasher, H256};
+
+/// Choose the SHA-256 algorithm to compute the hash values for `[u8; 32]` and `u8` data.
+pub struct Sha256Hasher(Sha256);
+
+impl Default for Sha256Hasher {
+    fn default() -> Self {
+        Sha256Hasher(Sha256::new())
+    }
+}
+
+impl Hasher for Sha256Hasher {
+    fn write_h256(&mut self, h: &H256) {
+        self.0.update(h.as_ref());
+    }
+    fn write_byte(&mut self, b: u8) {
+        self.0.update(&[b][..])
+    }
+
+    fn finish(self) -> H256 {
+        let a: [u8; 32] = self.0.finalize().into();
+        a.into()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn h256(hex: &str) -> H256 {
+        let a: [u8; 32] = hex::decode(hex).unwrap().try_into().unwrap();
+        a.into()
+    }
+
+    #[test]
+    fn test_hasher() {
+        assert_eq!(
+            Sha256Hasher::default().finish(),
+            h256("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+
+        let mut hasher = Sha256Hasher::default();
+        hasher.write_h256(&H256::default());
+        assert_eq!(
+            hasher.finish(),
+            h256("66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925")
+        );
+
+        let mut hasher = Sha256Hasher::default();
+        hasher.write_byte(25u8);
+        assert_eq!(
+            hasher.finish(),
+            h256("68aa2e2ee5dff96e3355e6c7ee373e3d6a4e17f75f9518d843709c0c9bc3e3d4")
+        );
+
+        let mut hasher = Sha256Hasher::default();
+        b"abc".iter().for_each(|b| hasher.write_byte(*b));
+        assert_eq!(
+            hasher.finish(),
+            h256("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+    }
+}
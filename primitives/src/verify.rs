@@ -34,7 +34,7 @@ use serde::{self, Deserialize, Serialize};
 use sparse_merkle_tree::{
     merge::{hash_base_node, merge, MergeValue},
     traits::Hasher,
-    H256,
+    MerkleProof, H256,
 };
 
 cfg_if::cfg_if! {
@@ -62,6 +62,34 @@ cfg_if::cfg_if! {
             pub siblings: Vec<MergeValue>,
         }
 
+        /// A leaf in a batch Merkle proof.
+        #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, TypeInfo, PartialEq, ToSchema)]
+        pub struct BatchLeaf<K: Debug + Clone + TypeInfo, V: Default + Debug + Clone + TypeInfo> {
+            /// The key in the KVDB.
+            #[serde(flatten)]
+            pub key: K,
+            /// The value in the KVDB.
+            #[serde(flatten)]
+            pub value: V,
+            /// The Merkle leaf's path (i.e., the hash value of the key).
+            pub path: H256,
+            /// The hash value of the Merkle leaf (i.e., the hash value of the value), zero if the key is not in the tree.
+            pub value_hash: H256,
+        }
+
+        /// Merkle proof of multiple leaves against one root, the siblings are shared by all leaves.
+        #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, TypeInfo, PartialEq, ToSchema)]
+        pub struct BatchProof<K: Debug + Clone + TypeInfo, V: Default + Debug + Clone + TypeInfo> {
+            /// The proven leaves, sorted by path.
+            pub leaves: Vec<BatchLeaf<K, V>>,
+            /// Merkle root hash.
+            pub root: H256,
+            /// Path markers, one per leaf and in the same order as `leaves`.
+            pub leaves_bitmap: Vec<H256>,
+            /// Branches needed to compute the root from all leaves together.
+            pub siblings: Vec<MergeValue>,
+        }
+
     } else {
          /// Merkle proof.
         #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, TypeInfo, PartialEq)]
@@ -83,6 +111,34 @@ cfg_if::cfg_if! {
             /// Branches encountered on the leave_bitmap that need to be hashed. They correspond one-to-one with the leave_bitmap.
             pub siblings: Vec<MergeValue>,
         }
+
+        /// A leaf in a batch Merkle proof.
+        #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, TypeInfo, PartialEq)]
+        pub struct BatchLeaf<K: Debug + Clone + TypeInfo, V: Default + Debug + Clone + TypeInfo> {
+            /// The key in the KVDB.
+            #[serde(flatten)]
+            pub key: K,
+            /// The value in the KVDB.
+            #[serde(flatten)]
+            pub value: V,
+            /// The Merkle leaf's path (i.e., the hash value of the key).
+            pub path: H256,
+            /// The hash value of the Merkle leaf (i.e., the hash value of the value), zero if the key is not in the tree.
+            pub value_hash: H256,
+        }
+
+        /// Merkle proof of multiple leaves against one root, the siblings are shared by all leaves.
+        #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, TypeInfo, PartialEq)]
+        pub struct BatchProof<K: Debug + Clone + TypeInfo, V: Default + Debug + Clone + TypeInfo> {
+            /// The proven leaves, sorted by path.
+            pub leaves: Vec<BatchLeaf<K, V>>,
+            /// Merkle root hash.
+            pub root: H256,
+            /// Path markers, one per leaf and in the same order as `leaves`.
+            pub leaves_bitmap: Vec<H256>,
+            /// Branches needed to compute the root from all leaves together.
+            pub siblings: Vec<MergeValue>,
+        }
     }
 
 }
@@ -124,20 +180,16 @@ fn into_merge_value<H: Hasher + Default>(key: H256, value: H256, height: u8) ->
     }
 }
 
-/// Verify the Merkle proof,  
-/// including the verification when there is only one leaf (which differs slightly from multi-leaf cases).
-pub fn verify<H: Hasher + Default>(
+/// Recompute the Merkle root from a single leaf and its proof.
+/// Returns `None` if the proof is malformed (the leave_bitmap marks more branches than there are siblings).
+pub fn compute_root<H: Hasher + Default>(
     path: H256,
     value_hash: H256,
     leave_bitmap: H256,
     siblings: Vec<MergeValue>,
-    root: H256,
-) -> bool {
-    if value_hash.is_zero() {
-        return false;
-    }
+) -> Option<H256> {
     if siblings.is_empty() {
-        return single_leaf_verify::<H>(path, value_hash).hash::<H>() == root;
+        return Some(single_leaf_verify::<H>(path, value_hash).hash::<H>());
     }
 
     let mut current_path = path;
@@ -154,12 +206,13 @@ pub fn verify<H: Hasher + Default>(
             if n == 0 {
                 current_v = into_merge_value::<H>(path, value_hash, i);
             }
+            let sibling = siblings.get(n)?.clone();
             if current_path.is_right(i) {
-                left = siblings[n].clone();
+                left = sibling;
                 right = current_v.clone();
             } else {
                 left = current_v.clone();
-                right = siblings[n].clone();
+                right = sibling;
             }
 
             n += 1;
@@ -179,5 +232,199 @@ pub fn verify<H: Hasher + Default>(
 
         current_path = parent_path;
     }
-    current_v.hash::<H>() == root
+    Some(current_v.hash::<H>())
+}
+
+/// Verify the Merkle proof,  
+/// including the verification when there is only one leaf (which differs slightly from multi-leaf cases).
+pub fn verify<H: Hasher + Default>(
+    path: H256,
+    value_hash: H256,
+    leave_bitmap: H256,
+    siblings: Vec<MergeValue>,
+    root: H256,
+) -> bool {
+    if value_hash.is_zero() {
+        return false;
+    }
+    compute_root::<H>(path, value_hash, leave_bitmap, siblings) == Some(root)
+}
+
+/// Verify the non-membership proof, i.e. that the leaf at `path` is empty under `root`.  
+/// The proof is the one returned by `get_merkle_proof` for a key that is not in the tree (its `value_hash` is zero).
+pub fn verify_non_membership<H: Hasher + Default>(
+    path: H256,
+    leave_bitmap: H256,
+    siblings: Vec<MergeValue>,
+    root: H256,
+) -> bool {
+    compute_root::<H>(path, H256::zero(), leave_bitmap, siblings) == Some(root)
+}
+
+/// Verify the batch Merkle proof of multiple leaves.  
+/// `leaves` are `(path, value_hash)` pairs and `leaves_bitmap` must follow the order of the leaves sorted by path.
+/// A leaf with a zero `value_hash` is proven to be absent from the tree.
+pub fn verify_batch<H: Hasher + Default>(
+    leaves: Vec<(H256, H256)>,
+    leaves_bitmap: Vec<H256>,
+    siblings: Vec<MergeValue>,
+    root: H256,
+) -> bool {
+    if leaves.is_empty() {
+        return false;
+    }
+    MerkleProof::new(leaves_bitmap, siblings)
+        .verify::<H>(&root, leaves)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        keccak_hasher::Keccak256Hasher,
+        kv::{SMTKey, SMTValue},
+    };
+    use sparse_merkle_tree::{default_store::DefaultStore, traits::Value, SparseMerkleTree};
+
+    type Tree = SparseMerkleTree<Keccak256Hasher, SMTValue, DefaultStore<SMTValue>>;
+
+    fn key(i: u64) -> SMTKey {
+        SMTKey {
+            address: i.to_string(),
+        }
+    }
+
+    fn proof_of(tree: &Tree, k: &SMTKey) -> (H256, Vec<MergeValue>) {
+        let proof = tree.merkle_proof(vec![k.to_h256()]).unwrap();
+        (proof.leaves_bitmap()[0], proof.merkle_path().clone())
+    }
+
+    #[test]
+    fn test_non_membership() {
+        let mut tree = Tree::default();
+        // empty tree
+        let (bitmap, siblings) = proof_of(&tree, &key(0));
+        assert!(verify_non_membership::<Keccak256Hasher>(
+            key(0).to_h256(),
+            bitmap,
+            siblings,
+            *tree.root()
+        ));
+
+        for i in 1..10 {
+            tree.update(
+                key(i).to_h256(),
+                SMTValue {
+                    nonce: i,
+                    balance: i as u128,
+                },
+            )
+            .unwrap();
+        }
+        let root = *tree.root();
+
+        // absent key
+        let (bitmap, siblings) = proof_of(&tree, &key(100));
+        assert!(verify_non_membership::<Keccak256Hasher>(
+            key(100).to_h256(),
+            bitmap,
+            siblings.clone(),
+            root
+        ));
+        assert!(!verify::<Keccak256Hasher>(
+            key(100).to_h256(),
+            H256::zero(),
+            bitmap,
+            siblings,
+            root
+        ));
+
+        // present key
+        let (bitmap, siblings) = proof_of(&tree, &key(1));
+        assert!(!verify_non_membership::<Keccak256Hasher>(
+            key(1).to_h256(),
+            bitmap,
+            siblings.clone(),
+            root
+        ));
+        let value = tree.get(&key(1).to_h256()).unwrap();
+        assert!(verify::<Keccak256Hasher>(
+            key(1).to_h256(),
+            value.to_h256(),
+            bitmap,
+            siblings,
+            root
+        ));
+
+        // malformed proof
+        assert!(!verify_non_membership::<Keccak256Hasher>(
+            key(100).to_h256(),
+            H256::from([0xffu8; 32]),
+            vec![MergeValue::zero()],
+            root
+        ));
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        let mut tree = Tree::default();
+        for i in 1..20 {
+            tree.update(
+                key(i).to_h256(),
+                SMTValue {
+                    nonce: i,
+                    balance: i as u128,
+                },
+            )
+            .unwrap();
+        }
+        let root = *tree.root();
+
+        // present and absent keys together
+        let mut leaves: Vec<(H256, H256)> = [1, 5, 7, 100, 101]
+            .iter()
+            .map(|i| {
+                let path = key(*i).to_h256();
+                (path, tree.get(&path).unwrap().to_h256())
+            })
+            .collect();
+        leaves.sort_by_key(|(path, _)| *path);
+        let proof = tree
+            .merkle_proof(leaves.iter().map(|(path, _)| *path).collect())
+            .unwrap();
+        let (leaves_bitmap, siblings) = proof.take();
+
+        assert!(verify_batch::<Keccak256Hasher>(
+            leaves.clone(),
+            leaves_bitmap.clone(),
+            siblings.clone(),
+            root
+        ));
+
+        // a wrong value
+        let mut wrong_leaves = leaves.clone();
+        wrong_leaves[0].1 = H256::from([1u8; 32]);
+        assert!(!verify_batch::<Keccak256Hasher>(
+            wrong_leaves,
+            leaves_bitmap.clone(),
+            siblings.clone(),
+            root
+        ));
+
+        // a missing leaf
+        assert!(!verify_batch::<Keccak256Hasher>(
+            leaves[1..].to_vec(),
+            leaves_bitmap.clone(),
+            siblings.clone(),
+            root
+        ));
+
+        assert!(!verify_batch::<Keccak256Hasher>(
+            vec![],
+            vec![],
+            vec![],
+            root
+        ));
+    }
 }